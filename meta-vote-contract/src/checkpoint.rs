@@ -0,0 +1,141 @@
+use crate::*;
+use near_sdk::near_bindgen;
+
+/// Voting power of a voter (or the contract total) from `timestamp_ms` on.
+/// A checkpoint with timestamp 0 holds the value known before checkpoints existed.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Debug)]
+pub struct VotingPowerCheckpoint {
+    pub block_height: u64,
+    pub timestamp_ms: EpochMillis,
+    pub voting_power: u128,
+}
+
+impl VotingPowerCheckpoint {
+    pub(crate) fn now(voting_power: u128) -> Self {
+        Self {
+            block_height: env::block_height(),
+            timestamp_ms: get_current_epoch_millis(),
+            voting_power,
+        }
+    }
+
+    pub(crate) fn baseline(voting_power: u128) -> Self {
+        Self {
+            block_height: 0,
+            timestamp_ms: 0,
+            voting_power,
+        }
+    }
+}
+
+/// appends a checkpoint, several changes in the same block keep only the last value
+pub(crate) fn push_checkpoint(
+    checkpoints: &mut Vector<VotingPowerCheckpoint>,
+    checkpoint: VotingPowerCheckpoint,
+) {
+    if let Some(last_index) = checkpoints.len().checked_sub(1) {
+        let last = checkpoints.get(last_index).unwrap();
+        if last.block_height == checkpoint.block_height {
+            checkpoints.replace(last_index, &checkpoint);
+            return;
+        }
+        if last.voting_power == checkpoint.voting_power {
+            return;
+        }
+    }
+    checkpoints.push(&checkpoint);
+}
+
+/// binary search for the last checkpoint at or before timestamp_ms
+pub(crate) fn find_checkpoint_at(
+    checkpoints: &Vector<VotingPowerCheckpoint>,
+    timestamp_ms: EpochMillis,
+) -> Option<VotingPowerCheckpoint> {
+    let mut low = 0;
+    let mut high = checkpoints.len();
+    while low < high {
+        let mid = (low + high) / 2;
+        if checkpoints.get(mid).unwrap().timestamp_ms <= timestamp_ms {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    if low == 0 {
+        None
+    } else {
+        checkpoints.get(low - 1)
+    }
+}
+
+impl MetaVoteContract {
+    /// Creates the voter checkpoint list, keeping the voting power the voter had
+    /// before checkpoints existed as baseline.
+    /// Must be called before modifying the voter locking positions.
+    pub(crate) fn internal_ensure_voter_checkpoints(&mut self, voter_id: &VoterId) {
+        if self.voter_checkpoints.contains_key(voter_id) {
+            return;
+        }
+        let initial_storage_usage = env::storage_usage();
+        let mut checkpoints = Vector::new(StorageKey::VoterCheckpointsList {
+            hash_id: generate_hash_id(voter_id),
+        });
+        if let Some(voter) = self.voters.get(voter_id) {
+            push_checkpoint(
+                &mut checkpoints,
+                VotingPowerCheckpoint::baseline(self.internal_get_counted_voting_power(voter_id, &voter)),
+            );
+        }
+        self.voter_checkpoints.insert(voter_id.clone(), checkpoints);
+        // also called on withdraw and unlock, so the deposit is checked on the next lock or vote
+        if self.storage_accounts.contains_key(voter_id) {
+            self.internal_record_storage(voter_id, initial_storage_usage);
+        }
+    }
+
+    /// Records the voter and contract voting power after a locking position changed.
+    pub(crate) fn internal_checkpoint_voting_power(&mut self, voter_id: &VoterId, voter: &Voter) {
+        self.internal_ensure_voter_checkpoints(voter_id);
+        // streamed rewards up to now are for the previous voting power
+        self.internal_settle_stream_rewards(voter_id);
+        self.rewards_total_voting_power = self.total_voting_power;
+        let voting_power = self.internal_get_counted_voting_power(voter_id, voter);
+        let checkpoints = self.voter_checkpoints.get_mut(voter_id).unwrap();
+        push_checkpoint(checkpoints, VotingPowerCheckpoint::now(voting_power));
+
+        push_checkpoint(
+            &mut self.total_voting_power_checkpoints,
+            VotingPowerCheckpoint::now(self.total_voting_power),
+        );
+    }
+}
+
+#[near_bindgen]
+impl MetaVoteContract {
+    // --------
+    // view fns
+    // --------
+
+    /// voting power (locked positions, plus decaying unlocking positions) a voter had at timestamp_ms.
+    /// With the Decay model, unlocking positions are as of the voter last sync.
+    pub fn get_voting_power_at(&self, voter_id: VoterId, timestamp_ms: EpochMillis) -> U128String {
+        match self.voter_checkpoints.get(&voter_id) {
+            Some(checkpoints) => find_checkpoint_at(checkpoints, timestamp_ms)
+                .map_or(0, |checkpoint| checkpoint.voting_power),
+            // no changes since checkpoints exist, current value is valid
+            None => self
+                .voters
+                .get(&voter_id)
+                .map_or(0, |voter| self.internal_get_counted_voting_power(&voter_id, &voter)),
+        }
+        .into()
+    }
+
+    /// contract total_voting_power at timestamp_ms.
+    /// With the Decay model, it is only current after `operator_sync_voting_power`.
+    pub fn get_total_voting_power_at(&self, timestamp_ms: EpochMillis) -> U128String {
+        find_checkpoint_at(&self.total_voting_power_checkpoints, timestamp_ms)
+            .map_or(0, |checkpoint| checkpoint.voting_power)
+            .into()
+    }
+}
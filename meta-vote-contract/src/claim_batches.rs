@@ -0,0 +1,361 @@
+use crate::interface::*;
+use crate::*;
+use near_sdk::collections::LookupMap as ClaimAmounts;
+use near_sdk::near_bindgen;
+use near_sdk::serde::{Deserialize, Serialize};
+
+/// `for-claims` distribution with an expiry. The amounts are added to the voters claimable
+/// rewards and also tracked per batch; claims consume the batches that expire first.
+/// After the expiry the operator sweeps the unclaimed amounts to the treasury.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ClaimBatch {
+    pub token_id: AccountId,
+    pub expiry_ms: EpochMillis,
+    pub total_amount: u128,
+    /// not yet claimed nor swept
+    pub unclaimed: u128,
+    pub accounts: Vector<VoterId>,
+    pub amounts: ClaimAmounts<VoterId, u128>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClaimBatchJSON {
+    pub batch_id: u64,
+    pub token_id: String,
+    pub expiry_ms: EpochMillis,
+    pub total_amount: U128String,
+    pub unclaimed: U128String,
+    pub accounts: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExpiringClaimJSON {
+    pub batch_id: u64,
+    pub token_id: String,
+    pub amount: U128String,
+    pub expiry_ms: EpochMillis,
+}
+
+/// expiring amount removed from a batch by a claim, restored if the claim transfer fails
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ConsumedClaim {
+    pub batch_id: u64,
+    pub expiry_ms: EpochMillis,
+    pub amount: U128String,
+}
+
+/// msg of the `for-claims-expiring:` deposit, amounts as in `for-claims:`
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExpiringClaimsMsg {
+    pub expiry_days: Days,
+    pub claims: Vec<(String, u64)>,
+}
+
+impl ClaimBatch {
+    fn to_json(&self, batch_id: u64) -> ClaimBatchJSON {
+        ClaimBatchJSON {
+            batch_id,
+            token_id: self.token_id.to_string(),
+            expiry_ms: self.expiry_ms,
+            total_amount: self.total_amount.into(),
+            unclaimed: self.unclaimed.into(),
+            accounts: self.accounts.len(),
+        }
+    }
+}
+
+impl MetaVoteContract {
+    fn new_claim_batch(
+        batch_id: u64,
+        token_id: &AccountId,
+        expiry_ms: EpochMillis,
+        total_amount: u128,
+    ) -> ClaimBatch {
+        let hash_id = generate_hash_id(&batch_id.to_string());
+        ClaimBatch {
+            token_id: token_id.clone(),
+            expiry_ms,
+            total_amount,
+            unclaimed: total_amount,
+            accounts: Vector::new(StorageKey::ClaimBatchAccounts { hash_id }),
+            amounts: ClaimAmounts::new(StorageKey::ClaimBatchAmounts { hash_id }),
+        }
+    }
+
+    fn internal_add_voter_claim_batch(&mut self, account: &VoterId, token_id: &AccountId, batch_id: u64) {
+        let key = (account.clone(), token_id.clone());
+        let mut batch_ids = self
+            .voter_claim_batches
+            .get(&key)
+            .cloned()
+            .unwrap_or_default();
+        if !batch_ids.contains(&batch_id) {
+            batch_ids.push(batch_id);
+            self.voter_claim_batches.insert(key, batch_ids);
+        }
+    }
+
+    /// distributes for claims (see distribute_for_claims) and records the batch.
+    /// Only the owner or the operator, the voters batches are loaded on each claim.
+    pub(crate) fn internal_distribute_expiring_claims(
+        &mut self,
+        sender_id: &AccountId,
+        total_amount: u128,
+        info: &ExpiringClaimsMsg,
+    ) {
+        require!(
+            *sender_id == self.owner_id || *sender_id == self.operator_id,
+            "Only the owner or the operator can distribute expiring claims"
+        );
+        require!(
+            info.expiry_days > 0,
+            "expiry_days must be greater than zero"
+        );
+        self.distribute_for_claims(total_amount, &info.claims);
+
+        let token_id = env::predecessor_account_id();
+        let unit = self.internal_reward_unit(&token_id);
+        let batch_id = self.next_claim_batch_id;
+        self.next_claim_batch_id += 1;
+        let mut batch = Self::new_claim_batch(
+            batch_id,
+            &token_id,
+            get_current_epoch_millis() + days_to_millis(info.expiry_days),
+            total_amount,
+        );
+        for (account, amount) in info.claims.iter() {
+            let amount = *amount as u128 * unit;
+            if amount == 0 {
+                continue;
+            }
+            match batch.amounts.get(account) {
+                Some(existing) => {
+                    batch.amounts.insert(account, &(existing + amount));
+                }
+                None => {
+                    batch.accounts.push(account);
+                    batch.amounts.insert(account, &amount);
+                    self.internal_add_voter_claim_batch(account, &token_id, batch_id);
+                }
+            }
+        }
+        Event::ExpiringClaims {
+            batch_id,
+            token: token_id.as_str(),
+            total_amount: total_amount.into(),
+            expiry_ms: batch.expiry_ms,
+        }
+        .emit();
+        self.claim_batches.insert(&batch_id, &batch);
+    }
+
+    /// Removes a claimed amount from the voter batches, the ones that expire first first.
+    /// The rest of the amount is from claims without expiry. Returns the amounts removed
+    /// from each batch.
+    pub(crate) fn internal_consume_claim_batches(
+        &mut self,
+        token_id: &AccountId,
+        account: &VoterId,
+        amount: u128,
+    ) -> Vec<ConsumedClaim> {
+        let key = (account.clone(), token_id.clone());
+        let mut batch_ids = match self.voter_claim_batches.get(&key) {
+            Some(batch_ids) => batch_ids.clone(),
+            None => return Vec::new(),
+        };
+        let mut consumed_claims = Vec::new();
+        batch_ids.sort_by_key(|batch_id| self.claim_batches.get(batch_id).unwrap().expiry_ms);
+        let mut remaining = amount;
+        while remaining > 0 && !batch_ids.is_empty() {
+            let batch_id = batch_ids[0];
+            let mut batch = self.claim_batches.get(&batch_id).unwrap();
+            let batch_amount = batch.amounts.get(account).unwrap_or_default();
+            let consumed = std::cmp::min(batch_amount, remaining);
+            if consumed == batch_amount {
+                batch.amounts.remove(account);
+                batch_ids.remove(0);
+            } else {
+                batch.amounts.insert(account, &(batch_amount - consumed));
+            }
+            batch.unclaimed -= consumed;
+            remaining -= consumed;
+            consumed_claims.push(ConsumedClaim {
+                batch_id,
+                expiry_ms: batch.expiry_ms,
+                amount: consumed.into(),
+            });
+            if batch.unclaimed == 0 {
+                batch.accounts.clear();
+                self.claim_batches.remove(&batch_id);
+            } else {
+                self.claim_batches.insert(&batch_id, &batch);
+            }
+        }
+        if batch_ids.is_empty() {
+            self.voter_claim_batches.remove(&key);
+        } else {
+            self.voter_claim_batches.insert(key, batch_ids);
+        }
+        consumed_claims
+    }
+
+    /// Returns up to `amount` of a failed claim transfer to the batches it was consumed from,
+    /// the last consumed first. A batch removed meanwhile is recreated, to be swept after its expiry.
+    pub(crate) fn internal_restore_claim_batches(
+        &mut self,
+        token_id: &AccountId,
+        account: &VoterId,
+        consumed_claims: &[ConsumedClaim],
+        amount: u128,
+    ) {
+        let mut remaining = amount;
+        for consumed in consumed_claims.iter().rev() {
+            if remaining == 0 {
+                break;
+            }
+            let restored = std::cmp::min(consumed.amount.0, remaining);
+            remaining -= restored;
+            let batch = match self.claim_batches.get(&consumed.batch_id) {
+                Some(mut batch) => {
+                    let batch_amount = batch.amounts.get(account).unwrap_or_default();
+                    batch.amounts.insert(account, &(batch_amount + restored));
+                    batch.unclaimed += restored;
+                    batch
+                }
+                None => {
+                    let mut batch = Self::new_claim_batch(
+                        consumed.batch_id,
+                        token_id,
+                        consumed.expiry_ms,
+                        restored,
+                    );
+                    batch.accounts.push(account);
+                    batch.amounts.insert(account, &restored);
+                    batch
+                }
+            };
+            self.claim_batches.insert(&consumed.batch_id, &batch);
+            self.internal_add_voter_claim_batch(account, token_id, consumed.batch_id);
+        }
+    }
+}
+
+#[near_bindgen]
+impl MetaVoteContract {
+    /// Removes the unclaimed amounts of the accounts [from_index, from_index + limit) of an
+    /// expired batch from their claimable rewards and transfers them to the treasury.
+    /// The batch is removed once nothing is left unclaimed.
+    pub fn sweep_expired_claims(
+        &mut self,
+        batch_id: u64,
+        from_index: u32,
+        limit: u32,
+    ) -> U128String {
+        self.assert_operator();
+        let mut batch = match self.claim_batches.get(&batch_id) {
+            Some(batch) => batch,
+            None => panic!("invalid claim batch {}", batch_id),
+        };
+        require!(
+            get_current_epoch_millis() >= batch.expiry_ms,
+            "The claim batch has not expired"
+        );
+        let token_id = batch.token_id.clone();
+        let until = std::cmp::min(
+            from_index.saturating_add(limit) as u64,
+            batch.accounts.len(),
+        );
+        let mut swept = 0;
+        for index in from_index as u64..until {
+            let account = batch.accounts.get(index).unwrap();
+            let amount = match batch.amounts.remove(&account) {
+                Some(amount) => amount,
+                None => continue,
+            };
+            self.internal_sub_claimable(&token_id, &account, amount);
+            let key = (account, token_id.clone());
+            let mut batch_ids = self
+                .voter_claim_batches
+                .get(&key)
+                .cloned()
+                .unwrap_or_default();
+            batch_ids.retain(|id| *id != batch_id);
+            if batch_ids.is_empty() {
+                self.voter_claim_batches.remove(&key);
+            } else {
+                self.voter_claim_batches.insert(key, batch_ids);
+            }
+            swept += amount;
+        }
+        batch.unclaimed -= swept;
+        if batch.unclaimed == 0 {
+            // the accounts list was sent in a single transaction, it can be cleared in one
+            batch.accounts.clear();
+            self.claim_batches.remove(&batch_id);
+        } else {
+            self.claim_batches.insert(&batch_id, &batch);
+        }
+
+        Event::SweepExpiredClaims {
+            batch_id,
+            token: token_id.as_str(),
+            amount: swept.into(),
+            treasury_id: self.treasury_id.as_str(),
+        }
+        .emit();
+        if swept > 0 {
+            // if the transfer fails, the owner can withdraw it as excess balance
+            ext_ft::ext(token_id)
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .with_attached_deposit(1)
+                .ft_transfer(self.treasury_id.to_string(), swept.into(), None);
+        }
+        swept.into()
+    }
+
+    // --------
+    // view fns
+    // --------
+
+    pub fn get_claim_batches(&self, from_index: u32, limit: u32) -> Vec<ClaimBatchJSON> {
+        let keys = self.claim_batches.keys_as_vector();
+        let values = self.claim_batches.values_as_vector();
+        let until = std::cmp::min(from_index.saturating_add(limit) as u64, keys.len());
+        (from_index as u64..until)
+            .map(|index| values.get(index).unwrap().to_json(keys.get(index).unwrap()))
+            .collect()
+    }
+
+    /// claimable amounts of the voter that expire, by batch. The rest of the claimable
+    /// rewards have no expiry.
+    pub fn get_expiring_claims(&self, voter_id: VoterId) -> Vec<ExpiringClaimJSON> {
+        let mut tokens = vec![
+            self.mpdao_token_contract_address.clone(),
+            self.stnear_token_contract_address.clone(),
+        ];
+        tokens.extend(self.reward_tokens.keys());
+        let mut result = Vec::new();
+        for token_id in tokens {
+            let key = (voter_id.clone(), token_id.clone());
+            for batch_id in self
+                .voter_claim_batches
+                .get(&key)
+                .cloned()
+                .unwrap_or_default()
+            {
+                let batch = self.claim_batches.get(&batch_id).unwrap();
+                result.push(ExpiringClaimJSON {
+                    batch_id,
+                    token_id: token_id.to_string(),
+                    amount: batch.amounts.get(&voter_id).unwrap_or_default().into(),
+                    expiry_ms: batch.expiry_ms,
+                });
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,93 @@
+use crate::*;
+use near_sdk::near_bindgen;
+
+#[near_bindgen]
+impl MetaVoteContract {
+    /// Opt-in to have the claimable mpDAO locked for `locking_period` days by the operator,
+    /// None to opt-out.
+    pub fn set_auto_compound(&mut self, locking_period: Option<Days>) {
+        let voter_id = env::predecessor_account_id().to_string();
+        match locking_period {
+            Some(locking_period) => {
+                require!(
+                    locking_period >= self.min_unbond_period
+                        && locking_period <= self.max_unbond_period,
+                    format!(
+                        "Unbound period must be between {} and {} days",
+                        self.min_unbond_period, self.max_unbond_period
+                    )
+                );
+                self.internal_get_voter_or_panic(&voter_id);
+                let initial_storage_usage = env::storage_usage();
+                self.auto_compound.insert(&voter_id, &locking_period);
+                self.internal_charge_storage(&voter_id, initial_storage_usage);
+            }
+            None => {
+                let initial_storage_usage = env::storage_usage();
+                self.auto_compound.remove(&voter_id);
+                self.internal_charge_storage(&voter_id, initial_storage_usage);
+            }
+        }
+    }
+
+    /// Locks the claimable mpDAO of the opted-in voters [from_index, from_index + limit),
+    /// when it is at least the min deposit amount. Voters that cannot lock it now are
+    /// skipped with an AutoCompoundSkipped event. Returns the next index.
+    pub fn auto_compound_claims(&mut self, from_index: u32, limit: u32) -> u32 {
+        self.assert_operator();
+        let until = std::cmp::min(
+            from_index.saturating_add(limit),
+            self.auto_compound.len() as u32,
+        );
+        for index in from_index..until {
+            let voter_id = self
+                .auto_compound
+                .keys_as_vector()
+                .get(index as u64)
+                .unwrap();
+            // streamed rewards first
+            self.internal_settle_stream_rewards(&voter_id);
+            let amount = self.claimable_mpdao.get(&voter_id).unwrap_or_default();
+            if amount < self.min_deposit_amount {
+                continue;
+            }
+            let voter = match self.voters.get(&voter_id) {
+                Some(voter) => voter,
+                None => continue,
+            };
+            let locking_period = self.auto_compound.get(&voter_id).unwrap();
+            if !self.internal_can_deposit_locking_position(locking_period, &voter_id, &voter) {
+                Event::AutoCompoundSkipped {
+                    voter_id: &voter_id,
+                    locking_period,
+                    amount: amount.into(),
+                }
+                .emit();
+                continue;
+            }
+            self.internal_claim_and_lock(&voter_id, &voter_id, amount, locking_period);
+        }
+        until
+    }
+
+    // --------
+    // view fns
+    // --------
+
+    pub fn get_auto_compound(&self, voter_id: VoterId) -> Option<Days> {
+        self.auto_compound.get(&voter_id)
+    }
+
+    /// [(voter_id, locking_period)] of the opted-in voters
+    pub fn get_auto_compound_voters(&self, from_index: u32, limit: u32) -> Vec<(VoterId, Days)> {
+        let keys = self.auto_compound.keys_as_vector();
+        let until = std::cmp::min(from_index.saturating_add(limit) as u64, keys.len());
+        (from_index as u64..until)
+            .map(|index| {
+                let voter_id = keys.get(index).unwrap();
+                let locking_period = self.auto_compound.get(&voter_id).unwrap();
+                (voter_id, locking_period)
+            })
+            .collect()
+    }
+}
@@ -3,11 +3,52 @@ use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 
 pub const ONE_MPDAO: u128 = 1_000_000; // MPDAO has 6 decimals
 pub const E18: u128 = 1_000_000_000_000_000_000; // to convert 6 decimals to 24 decimals
+pub const E20: u128 = 100_000_000_000_000_000_000;
 pub const TGAS: u64 = 1_000_000_000_000;
 
+pub const MAX_DELEGATES_PER_VOTER: usize = 10;
+/// the delegators list is loaded by every delegation to the delegate
+pub const MAX_DELEGATORS_PER_DELEGATE: usize = 100;
+
+/// reward tokens besides mpDAO and stNEAR, claim_all transfers all of them in one call
+pub const MAX_REWARD_TOKENS: usize = 3;
+/// streamed rewards per voting power (24 decimals) are scaled by 1e30
+pub const REWARD_PER_VOTING_POWER_PRECISION: u128 = 1_000_000_000_000_000_000_000_000_000_000;
+
+/// incentive pools per votable object, and days after the cutoff to claim them
+pub const MAX_INCENTIVE_POOLS_PER_OBJECT: usize = 10;
+pub const INCENTIVE_POOL_CLAIM_DAYS: u16 = 60;
+/// minimum amount of a new incentive pool, in units of the reward token
+pub const MIN_INCENTIVE_POOL_UNITS: u128 = 100;
+pub const MAX_INCENTIVE_VOTES_CLEARED_PER_CALL: u64 = 100;
+pub const MAX_MERKLE_WORDS_CLEARED_PER_CALL: u64 = 100;
+
+/// NEP-145 min storage balance, a voter with a locking position and a vote
+pub const MIN_VOTER_STORAGE_BYTES: u64 = 3_000;
+/// Upper bound of the storage of a new locking position, with its voter and checkpoints
+pub const LOCKING_POSITION_STORAGE_BYTES: u64 = 1_000;
+
+/// Voting power multiplier curve, 10_000 basis points is a 1x multiplier
+pub const BASIS_POINTS: u32 = 10_000;
+pub const MAX_CURVE_POINTS: usize = 20;
+pub const MAX_MULTIPLIER_BPS: u32 = 20 * BASIS_POINTS;
+
+/// EIP-712 typed data for EVM delegations
+pub const EIP712_DOMAIN_TYPE: &str = "EIP712Domain(string name,string version)";
+pub const EIP712_DOMAIN_NAME: &str = "Meta Vote";
+pub const EIP712_DOMAIN_VERSION: &str = "1";
+pub const EIP712_DELEGATION_TYPE: &str =
+    "Delegation(string contract,string delegate,uint256 nonce,uint256 deadline)";
+
 /// Amount of gas for fungible token transfers.
 pub const GAS_FOR_FT_TRANSFER: Gas = Gas(47 * TGAS);
 pub const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(11 * TGAS);
+pub const GAS_FOR_FT_BALANCE_OF: Gas = Gas(10 * TGAS);
+pub const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas(100 * TGAS);
+/// stNEAR ft_transfer_call to the swap contract, that sends back the mpDAO to lock
+pub const GAS_FOR_SWAP: Gas = Gas(150 * TGAS);
+pub const GAS_FOR_RESOLVE_SWAP: Gas = Gas(15 * TGAS);
+pub const GAS_FOR_OWNER_WITHDRAW_CALLBACK: Gas = Gas(GAS_FOR_FT_TRANSFER.0 + 10 * TGAS);
 
 #[derive(BorshSerialize, BorshDeserialize, BorshStorageKey)]
 pub enum StorageKey {
@@ -23,5 +64,34 @@ pub enum StorageKey {
     EvmDelegates,
     EvmDelegationSignatures,
     EvmPreDelegation,
+    VoterCheckpoints,
+    VoterCheckpointsList { hash_id: CryptoHash },
+    TotalVotingPowerCheckpoints,
+    DelegatedTo,
+    DelegatedFrom,
+    EvmDelegationNonces,
+    DecayingVotingPower,
+    StorageAccounts,
+    UnbondingSchedule,
+    RewardTokens,
+    RewardTokenClaimable { hash_id: CryptoHash },
+    RewardStreams,
+    StreamRewardsPaid,
+    IncentivePools,
+    ObjectIncentivePools,
+    IncentiveVotes,
+    IncentivePoolsUnclaimed,
+    MerkleDistributions,
+    MerkleClaimed,
+    MerkleUnclaimed,
+    ClaimBatches,
+    ClaimBatchAccounts { hash_id: CryptoHash },
+    ClaimBatchAmounts { hash_id: CryptoHash },
+    VoterClaimBatches,
+    PendingSwaps,
+    AutoCompound,
+    MpdaoCountedVoters,
+    IncentivePoolVoters { pool_id: u64 },
+    MerkleClaimedWords { distribution_id: u64 },
 }
 
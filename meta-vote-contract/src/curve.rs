@@ -0,0 +1,70 @@
+use crate::*;
+use near_sdk::{assert_one_yocto, near_bindgen};
+
+impl MetaVoteContract {
+    /// voting power for a locking position, with the owner curve or the default days/60 multiplier
+    pub(crate) fn internal_calculate_voting_power(
+        &self,
+        mpdao_amount: MpDAOAmount,
+        unbond_days: Days,
+    ) -> u128 {
+        if self.voting_power_curve.is_empty() {
+            calculate_voting_power(mpdao_amount, unbond_days)
+        } else {
+            calculate_voting_power_from_curve(mpdao_amount, unbond_days, &self.voting_power_curve)
+        }
+    }
+}
+
+fn assert_valid_curve(curve: &[(Days, u32)]) {
+    assert!(
+        curve.len() <= MAX_CURVE_POINTS,
+        "The max number of curve points is {}",
+        MAX_CURVE_POINTS
+    );
+    for (days, multiplier_bps) in curve.iter() {
+        assert!(
+            *multiplier_bps <= MAX_MULTIPLIER_BPS,
+            "Multiplier for {} days is above the max of {} basis points",
+            days,
+            MAX_MULTIPLIER_BPS
+        );
+    }
+    for points in curve.windows(2) {
+        require!(points[0].0 < points[1].0, "Curve days must be increasing");
+        require!(
+            points[0].1 <= points[1].1,
+            "Curve multipliers can not decrease for longer periods"
+        );
+    }
+}
+
+#[near_bindgen]
+impl MetaVoteContract {
+    /// Sets the voting power multiplier curve as (days, multiplier_bps) points,
+    /// e.g. [[30, 5000], [300, 50000]] is the default 0.5x..5x.
+    /// An empty curve restores the default days/60 multiplier.
+    /// Existing locking positions keep their voting power until recalculated.
+    #[payable]
+    pub fn set_voting_power_curve(&mut self, curve: Vec<(Days, u32)>) {
+        assert_one_yocto();
+        self.assert_only_owner();
+        assert_valid_curve(&curve);
+        self.voting_power_curve = curve;
+    }
+
+    // --------
+    // view fns
+    // --------
+
+    /// empty means the default days/60 multiplier
+    pub fn get_voting_power_curve(&self) -> Vec<(Days, u32)> {
+        self.voting_power_curve.clone()
+    }
+
+    /// voting power a new locking position would get
+    pub fn preview_voting_power(&self, mpdao_amount: U128String, locking_period: Days) -> U128String {
+        self.internal_calculate_voting_power(mpdao_amount.0, locking_period)
+            .into()
+    }
+}
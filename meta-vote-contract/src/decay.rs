@@ -0,0 +1,136 @@
+use crate::*;
+use near_sdk::{assert_one_yocto, near_bindgen};
+
+impl MetaVoteContract {
+    /// voting power of the voter counted in available_voting_power and total_voting_power:
+    /// locked positions plus the decaying voting power of unlocking positions at the last sync
+    pub(crate) fn internal_get_counted_voting_power(&self, voter_id: &VoterId, voter: &Voter) -> u128 {
+        voter.sum_locked_voting_power()
+            + self
+                .decaying_voting_power
+                .get(voter_id)
+                .copied()
+                .unwrap_or_default()
+    }
+
+    /// Updates the voter (and contract total) voting power to the current value of its unlocking positions.
+    /// Votes are removed if the decayed voting power was in use.
+    /// With the Linear model unlocking positions have no voting power, so it only removes
+    /// voting power counted before switching models.
+    pub(crate) fn internal_sync_decaying_voting_power(&mut self, voter_id: &VoterId, voter: &mut Voter) {
+        let current = match self.voting_power_model {
+            VotingPowerModel::Linear => 0,
+            VotingPowerModel::Decay => voter
+                .sum_decaying_voting_power(self.voting_power_model, get_current_epoch_millis()),
+        };
+        let counted = self
+            .decaying_voting_power
+            .get(voter_id)
+            .copied()
+            .unwrap_or_default();
+
+        self.internal_update_voting_power(voter_id, voter, counted, current);
+
+        if current == 0 {
+            self.decaying_voting_power.remove(voter_id);
+        } else {
+            self.decaying_voting_power.insert(voter_id.clone(), current);
+        }
+    }
+
+    /// sync and checkpoint the voter voting power before using it
+    pub(crate) fn internal_sync_voting_power(&mut self, voter_id: &VoterId, voter: &mut Voter) {
+        self.internal_ensure_voter_checkpoints(voter_id);
+        self.internal_sync_decaying_voting_power(voter_id, voter);
+        self.internal_checkpoint_voting_power(voter_id, voter);
+    }
+}
+
+#[near_bindgen]
+impl MetaVoteContract {
+    /// Changes the voting power model. Existing unlocking positions are recomputed
+    /// on the next sync of each voter (any operation of the voter or `sync_voting_power`).
+    /// With Decay, total_voting_power, the checkpoints and the streamed rewards only follow
+    /// the decay when each voter is synced: a snapshot is only valid after syncing the
+    /// voters with unlocking positions (`operator_sync_voting_power`).
+    #[payable]
+    pub fn set_voting_power_model(&mut self, voting_power_model: VotingPowerModel) {
+        assert_one_yocto();
+        self.assert_only_owner();
+        self.voting_power_model = voting_power_model;
+    }
+
+    /// Applies the decay of the voter unlocking positions, anyone can call it.
+    pub fn sync_voting_power(&mut self, voter_id: VoterId) {
+        let mut voter = self.internal_get_voter_or_panic(&voter_id);
+        self.internal_sync_voting_power(&voter_id, &mut voter);
+        self.voters.insert(&voter_id, &voter);
+    }
+
+    /// Applies the decay of the voters with unlocking positions, paged over the voters.
+    /// Call it before taking a voting power snapshot. Returns the number of synced voters.
+    pub fn operator_sync_voting_power(&mut self, from_index: u32, limit: u32) -> u32 {
+        self.assert_operator();
+        let now = get_current_epoch_millis();
+        let keys = self.voters.keys_as_vector();
+        let to_index = std::cmp::min(from_index as u64 + limit as u64, keys.len());
+        let voter_ids: Vec<VoterId> = (from_index as u64..to_index)
+            .map(|index| keys.get(index).unwrap())
+            .collect();
+        let mut synced = 0;
+        for voter_id in voter_ids {
+            let mut voter = self.internal_get_voter(&voter_id);
+            if !self.decaying_voting_power.contains_key(&voter_id)
+                && voter.sum_decaying_voting_power(self.voting_power_model, now) == 0
+            {
+                continue;
+            }
+            self.internal_sync_voting_power(&voter_id, &mut voter);
+            self.voters.insert(&voter_id, &voter);
+            synced += 1;
+        }
+        synced
+    }
+
+    // --------
+    // view fns
+    // --------
+
+    pub fn get_voting_power_model(&self) -> VotingPowerModel {
+        self.voting_power_model
+    }
+
+    /// voter voting power at timestamp_ms, projected from the current locking positions.
+    /// For a past timestamp_ms it is the checkpointed voting power, see `get_voting_power_at`.
+    pub fn get_projected_voting_power(&self, voter_id: VoterId, timestamp_ms: EpochMillis) -> U128String {
+        if timestamp_ms < get_current_epoch_millis() {
+            return self.get_voting_power_at(voter_id, timestamp_ms);
+        }
+        match self.voters.get(&voter_id) {
+            Some(voter) => voter
+                .locking_positions
+                .iter()
+                .map(|lp| lp.voting_power_at(self.voting_power_model, timestamp_ms))
+                .sum::<u128>(),
+            None => 0,
+        }
+        .into()
+    }
+
+    /// locking position voting power at timestamp_ms (now or later), projected from its current state
+    pub fn get_locking_position_voting_power_at(
+        &self,
+        voter_id: VoterId,
+        index: PositionIndex,
+        timestamp_ms: EpochMillis,
+    ) -> U128String {
+        require!(
+            timestamp_ms >= get_current_epoch_millis(),
+            "Past voting power is only available per voter, see get_voting_power_at."
+        );
+        self.internal_get_voter_or_panic(&voter_id)
+            .get_position(index)
+            .voting_power_at(self.voting_power_model, timestamp_ms)
+            .into()
+    }
+}
@@ -0,0 +1,254 @@
+use crate::*;
+use near_sdk::near_bindgen;
+
+/// (account, voting power) pairs
+type DelegationList = Vec<(VoterId, u128)>;
+
+fn delegation_amount(list: &DelegationList, account_id: &VoterId) -> u128 {
+    list.iter()
+        .find(|(id, _)| id == account_id)
+        .map_or(0, |(_, amount)| *amount)
+}
+
+/// adds or removes voting power for account_id in the list, removing empty entries
+fn update_delegation_list(
+    map: &mut UnorderedMap<VoterId, DelegationList>,
+    key: &VoterId,
+    account_id: &VoterId,
+    add: u128,
+    remove: u128,
+) {
+    let mut list = map.get(key).unwrap_or_default();
+    let amount = delegation_amount(&list, account_id) + add - remove;
+    list.retain(|(id, _)| id != account_id);
+    if amount > 0 {
+        list.push((account_id.clone(), amount));
+    }
+    if list.is_empty() {
+        map.remove(key);
+    } else {
+        map.insert(key, &list);
+    }
+}
+
+impl MetaVoteContract {
+    /// voting power received by account_id from other voters
+    pub(crate) fn internal_delegated_in(&self, account_id: &VoterId) -> u128 {
+        self.delegated_from
+            .get(account_id)
+            .map_or(0, |list| list.iter().map(|(_, amount)| amount).sum())
+    }
+
+    fn internal_save_or_remove_voter(&mut self, voter_id: &VoterId, voter: &Voter) {
+        if voter.is_empty() {
+            self.voters.remove(voter_id);
+        } else {
+            self.voters.insert(voter_id, voter);
+        }
+    }
+
+    /// Moves `voting_power` delegated to `delegate_id` back to the delegator available
+    /// voting power. Delegate votes are removed until the revoked voting power is free.
+    fn internal_undelegate(
+        &mut self,
+        delegator_id: &VoterId,
+        delegator: &mut Voter,
+        delegate_id: &VoterId,
+        voting_power: u128,
+    ) {
+        let mut delegate = self.internal_get_voter_or_panic(delegate_id);
+        // remove delegate votes if needed
+        let max_used_voting_power =
+            delegate.available_voting_power + delegate.sum_used_votes() - voting_power;
+        self.internal_unvote_until(delegate_id, &mut delegate, max_used_voting_power);
+        delegate.available_voting_power -= voting_power;
+        delegator.available_voting_power += voting_power;
+
+        update_delegation_list(
+            &mut self.delegated_to,
+            delegator_id,
+            delegate_id,
+            0,
+            voting_power,
+        );
+        update_delegation_list(
+            &mut self.delegated_from,
+            delegate_id,
+            delegator_id,
+            0,
+            voting_power,
+        );
+        Event::Undelegate {
+            delegator_id,
+            delegate_id,
+            voting_power: voting_power.into(),
+        }
+        .emit();
+        self.internal_save_or_remove_voter(delegate_id, &delegate);
+    }
+
+    /// Revokes `voting_power` from the voter delegations, when its own voting power
+    /// decreased below the voting power it delegated.
+    pub(crate) fn internal_reduce_delegations(
+        &mut self,
+        voter_id: &VoterId,
+        voter: &mut Voter,
+        voting_power: u128,
+    ) {
+        let mut remaining = voting_power;
+        for (delegate_id, delegated) in self.delegated_to.get(voter_id).unwrap_or_default() {
+            if remaining == 0 {
+                break;
+            }
+            let revoked = std::cmp::min(delegated, remaining);
+            self.internal_undelegate(voter_id, voter, &delegate_id, revoked);
+            remaining -= revoked;
+        }
+        assert!(
+            remaining == 0,
+            "Not enough voting power to remove, missing {}.",
+            remaining
+        );
+    }
+}
+
+#[near_bindgen]
+impl MetaVoteContract {
+    // ************************************
+    // * Delegate voting power (NEAR)     *
+    // ************************************
+
+    /// Moves `voting_power` from the caller available voting power to `delegate_id`,
+    /// who can use it with vote/rebalance/unvote.
+    /// Delegations are one level only: delegates can not delegate.
+    pub fn delegate_voting_power(&mut self, delegate_id: AccountId, voting_power: U128String) {
+        let delegator_id = env::predecessor_account_id().as_str().to_string();
+        let delegate_id = delegate_id.as_str().to_string();
+        let voting_power = voting_power.0;
+        require!(voting_power > 0, "Voting power must be greater than zero.");
+        require!(delegator_id != delegate_id, "Can not delegate to yourself.");
+        require!(
+            self.delegated_from.get(&delegator_id).is_none(),
+            "Delegates can not delegate the voting power they received."
+        );
+        require!(
+            self.delegated_to.get(&delegate_id).is_none(),
+            "The delegate is delegating its voting power."
+        );
+        let delegates = self.delegated_to.get(&delegator_id).unwrap_or_default();
+        require!(
+            delegation_amount(&delegates, &delegate_id) > 0
+                || delegates.len() < MAX_DELEGATES_PER_VOTER,
+            format!("Cannot exceed {} delegates.", MAX_DELEGATES_PER_VOTER)
+        );
+        let delegators = self.delegated_from.get(&delegate_id).unwrap_or_default();
+        require!(
+            delegation_amount(&delegators, &delegator_id) > 0
+                || delegators.len() < MAX_DELEGATORS_PER_DELEGATE,
+            format!(
+                "The delegate cannot exceed {} delegators.",
+                MAX_DELEGATORS_PER_DELEGATE
+            )
+        );
+
+        let mut delegator = self.internal_get_voter_or_panic(&delegator_id);
+        self.internal_sync_voting_power(&delegator_id, &mut delegator);
+        assert!(
+            delegator.available_voting_power >= voting_power,
+            "Not enough free voting power. You have {}, requested {}.",
+            delegator.available_voting_power,
+            voting_power
+        );
+        // the delegation lists and the delegate voter are charged to the delegator
+        let initial_storage_usage = env::storage_usage();
+        self.internal_count_new_voter(&delegate_id);
+        let mut delegate = self.internal_get_voter(&delegate_id);
+        delegator.available_voting_power -= voting_power;
+        delegate.available_voting_power += voting_power;
+
+        update_delegation_list(
+            &mut self.delegated_to,
+            &delegator_id,
+            &delegate_id,
+            voting_power,
+            0,
+        );
+        update_delegation_list(
+            &mut self.delegated_from,
+            &delegate_id,
+            &delegator_id,
+            voting_power,
+            0,
+        );
+        Event::Delegate {
+            delegator_id: &delegator_id,
+            delegate_id: &delegate_id,
+            voting_power: voting_power.into(),
+        }
+        .emit();
+
+        self.voters.insert(&delegator_id, &delegator);
+        self.voters.insert(&delegate_id, &delegate);
+        self.internal_charge_storage(&delegator_id, initial_storage_usage);
+    }
+
+    /// Revokes `voting_power` (all if None) delegated to `delegate_id`.
+    /// If the delegate has not enough free voting power, its votes are removed
+    /// until the revoked voting power is free.
+    pub fn undelegate_voting_power(
+        &mut self,
+        delegate_id: AccountId,
+        voting_power: Option<U128String>,
+    ) {
+        let delegator_id = env::predecessor_account_id().as_str().to_string();
+        let delegate_id = delegate_id.as_str().to_string();
+        let delegated = delegation_amount(
+            &self.delegated_to.get(&delegator_id).unwrap_or_default(),
+            &delegate_id,
+        );
+        require!(delegated > 0, "No voting power delegated to this account.");
+        let voting_power = voting_power.map_or(delegated, |amount| amount.0);
+        require!(voting_power > 0, "Voting power must be greater than zero.");
+        assert!(
+            voting_power <= delegated,
+            "Not enough delegated voting power. You delegated {}, requested {}.",
+            delegated,
+            voting_power
+        );
+
+        let initial_storage_usage = env::storage_usage();
+        let mut delegator = self.internal_get_voter_or_panic(&delegator_id);
+        self.internal_undelegate(&delegator_id, &mut delegator, &delegate_id, voting_power);
+        self.voters.insert(&delegator_id, &delegator);
+        self.internal_charge_storage(&delegator_id, initial_storage_usage);
+    }
+
+    // --------
+    // view fns
+    // --------
+
+    /// accounts the voter delegated voting power to, with amounts
+    pub fn get_voting_power_delegates(&self, delegator_id: AccountId) -> Vec<(String, U128String)> {
+        self.delegated_to
+            .get(&delegator_id.into())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(account_id, amount)| (account_id, amount.into()))
+            .collect()
+    }
+
+    /// accounts that delegated voting power to the delegate, with amounts
+    pub fn get_voting_power_delegators(&self, delegate_id: AccountId) -> Vec<(String, U128String)> {
+        self.delegated_from
+            .get(&delegate_id.into())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(account_id, amount)| (account_id, amount.into()))
+            .collect()
+    }
+
+    /// total voting power received from delegators
+    pub fn get_delegated_voting_power(&self, delegate_id: AccountId) -> U128String {
+        self.internal_delegated_in(&delegate_id.into()).into()
+    }
+}
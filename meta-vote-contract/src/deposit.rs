@@ -1,11 +1,9 @@
 use crate::*;
 use near_sdk::json_types::U128;
-use near_sdk::{env, log, near_bindgen, serde_json, PromiseOrValue};
+use near_sdk::{env, near_bindgen, serde_json, PromiseOrValue};
 
 use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
 
-const E20: Balance = 100_000_000_000_000_000_000;
-
 #[near_bindgen]
 impl FungibleTokenReceiver for MetaVoteContract {
     // receiving mpDAO or stNEAR to distribute
@@ -19,13 +17,60 @@ impl FungibleTokenReceiver for MetaVoteContract {
         let amount = amount.0;
 
         // deposit for-claims, msg == "for-claims" means mpDAO to be later distributed to voters
-        // it could be stNEAR or mpDAO (checked at fn distribute_for_claims)
+        // it could be mpDAO, stNEAR or a registered reward token (checked at fn distribute_for_claims)
         if msg.len() >= 11 && &msg[..11] == "for-claims:" {
             match serde_json::from_str(&msg[11..]) {
                 Ok(info) => self.distribute_for_claims(amount, &info),
                 Err(_) => panic!("Err parsing msg for-claims"),
             };
         }
+        // for-claims with expiry, msg == "for-claims-expiring:{expiry_days,claims}"
+        else if let Some(info) = msg.strip_prefix("for-claims-expiring:") {
+            match serde_json::from_str(info) {
+                Ok(info) => self.internal_distribute_expiring_claims(&sender_id, amount, &info),
+                Err(_) => panic!("Err parsing msg for-claims-expiring"),
+            }
+        }
+        // rewards streamed to all the voting power, msg == "stream-rewards:<days>"
+        else if let Some(days) = msg.strip_prefix("stream-rewards:") {
+            match days.parse::<Days>() {
+                Ok(days) => self.internal_deposit_stream_rewards(
+                    &sender_id,
+                    &env::predecessor_account_id(),
+                    amount,
+                    days,
+                ),
+                Err(_) => panic!("Err parsing days from msg stream-rewards"),
+            }
+        }
+        // incentive for the voters of a votable object, msg == "incentive:{contract_address,votable_object_id,cutoff_ms}"
+        else if let Some(info) = msg.strip_prefix("incentive:") {
+            match serde_json::from_str(info) {
+                Ok(info) => {
+                    self.internal_deposit_incentive(&env::predecessor_account_id(), amount, &info)
+                }
+                Err(_) => panic!("Err parsing msg incentive"),
+            }
+        }
+        // rewards claimed with merkle proofs, msg == "merkle-distribution:{merkle_root,expiry_days}"
+        else if let Some(info) = msg.strip_prefix("merkle-distribution:") {
+            match serde_json::from_str(info) {
+                Ok(info) => self.internal_deposit_merkle_distribution(
+                    &sender_id,
+                    &env::predecessor_account_id(),
+                    amount,
+                    &info,
+                ),
+                Err(_) => panic!("Err parsing msg merkle-distribution"),
+            }
+        }
+        // mpDAO from the swap contract for claim_stnear_and_lock, msg == "swap-lock:<swap_id>"
+        else if let Some(swap_id) = msg.strip_prefix("swap-lock:") {
+            match swap_id.parse::<u64>() {
+                Ok(swap_id) => self.internal_receive_swapped_mpdao(&sender_id, amount, swap_id),
+                Err(_) => panic!("Err parsing swap_id from msg swap-lock"),
+            }
+        }
         // else, user deposit of mpDAO to bond for x days
         else {
             assert_eq!(
@@ -55,7 +100,13 @@ impl FungibleTokenReceiver for MetaVoteContract {
             };
 
             self.assert_min_deposit_amount(amount);
-            log!("DEPOSIT: {} mpDAO deposited from {}", amount, &voter_id,);
+            Event::Deposit {
+                voter_id: &voter_id,
+                amount: amount.into(),
+                locking_period: days,
+            }
+            .emit();
+            self.internal_ensure_voter_checkpoints(&voter_id);
             let mut voter = self.internal_get_voter(&voter_id);
             self.deposit_locking_position(amount, days, &voter_id, &mut voter);
         }
@@ -65,7 +116,7 @@ impl FungibleTokenReceiver for MetaVoteContract {
 }
 
 impl MetaVoteContract {
-    // distributes stNEAR or mpDAO between existent voters
+    // distributes a reward token (mpDAO, stNEAR or a registered reward token) between existent voters
     // called from ft_on_transfer
     pub(crate) fn distribute_for_claims(
         &mut self,
@@ -74,35 +125,25 @@ impl MetaVoteContract {
     ) {
         let mut total_distributed = 0;
         let token_address = env::predecessor_account_id();
-
-        // Meta Token
-        if token_address == self.mpdao_token_contract_address {
-            for item in distribute_info {
-                // in case of mpDAO, item.1 is integer mpDAO - mpDAO has 6 decimals
-                let amount = item.1 as u128 * 1_000_000;
-                self.add_claimable_mpdao(&item.0, amount);
-                total_distributed += amount;
-            }
-            self.accumulated_mpdao_distributed_for_claims += total_distributed;
-
-        // stNear Token
-        } else if token_address == self.stnear_token_contract_address {
-            for item in distribute_info {
-                // in case of stNEAR, item.1 is stNEAR amount * 1e4 (4 decimal places)
-                // so we multiply by 1e20 to get yocto-stNEAR
-                let amount = item.1 as u128 * E20;
-                self.add_claimable_stnear(&item.0, amount);
-                total_distributed += amount;
-            }
-            self.accum_distributed_stnear_for_claims += total_distributed;
-        } else {
-            panic!("Unknown token address: {}", token_address);
+        // amounts in the list are integer units of the token, e.g. integer mpDAO or stNEAR * 1e4
+        let unit = self.internal_reward_unit(&token_address);
+        for item in distribute_info {
+            let amount = item.1 as u128 * unit;
+            self.internal_add_claimable(&token_address, &item.0, amount);
+            total_distributed += amount;
         }
+        self.internal_add_distributed(&token_address, total_distributed);
         assert!(
             total_distributed == total_amount,
             "total to distribute {} != total_amount sent {}",
             total_distributed,
             total_amount
         );
+        Event::DistributeForClaims {
+            token: token_address.as_str(),
+            total_amount: total_amount.into(),
+            accounts: distribute_info.len() as u32,
+        }
+        .emit();
     }
 }
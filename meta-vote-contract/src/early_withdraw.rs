@@ -0,0 +1,200 @@
+use crate::interface::*;
+use crate::*;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, near_bindgen};
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EarlyWithdrawPreviewJSON {
+    pub amount: U128String,
+    pub penalty: U128String,
+    pub net_amount: U128String,
+    pub remaining_unbond_ms: EpochMillis,
+}
+
+impl MetaVoteContract {
+    /// penalty_bps of the amount for the max unbond period remaining,
+    /// proportional to the remaining unbond time of the position
+    fn internal_early_withdraw_penalty(
+        &self,
+        locking_position: &LockingPosition,
+        amount: MpDAOAmount,
+    ) -> (u128, EpochMillis) {
+        let remaining_unbond_ms = match locking_position.unlocking_started_at {
+            None => locking_position.locking_period_millis(),
+            Some(started_at) => (started_at + locking_position.locking_period_millis())
+                .saturating_sub(get_current_epoch_millis()),
+        };
+        let penalty = proportional(
+            proportional(
+                amount,
+                self.early_withdraw_penalty_bps.into(),
+                BASIS_POINTS.into(),
+            ),
+            remaining_unbond_ms.into(),
+            days_to_millis(self.max_unbond_period).into(),
+        );
+        (std::cmp::min(penalty, amount), remaining_unbond_ms)
+    }
+
+    fn internal_pay_penalty(&mut self, penalty: u128) {
+        if penalty == 0 {
+            return;
+        }
+        match self.early_withdraw_penalty_destination {
+            PenaltyDestination::Burn => {
+                // kept in the contract, owed to nobody and not withdrawable by the owner
+                self.total_burned_mpdao += penalty;
+            }
+            PenaltyDestination::Treasury => {
+                // if the transfer fails, the owner can withdraw it as excess balance
+                ext_ft::ext(self.mpdao_token_contract_address.clone())
+                    .with_static_gas(GAS_FOR_FT_TRANSFER)
+                    .with_attached_deposit(1)
+                    .ft_transfer(self.treasury_id.to_string(), penalty.into(), None);
+            }
+            PenaltyDestination::Redistribute => {
+                let token_id = self.mpdao_token_contract_address.clone();
+                self.internal_release_rewards_now(&token_id, penalty);
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl MetaVoteContract {
+    /// `penalty_bps` is the penalty for the max unbond period remaining, 0 disables early withdraws
+    #[payable]
+    pub fn set_early_withdraw_config(
+        &mut self,
+        penalty_bps: u32,
+        penalty_destination: PenaltyDestination,
+    ) {
+        assert_one_yocto();
+        self.assert_only_owner();
+        require!(
+            penalty_bps <= BASIS_POINTS,
+            format!("penalty_bps cannot exceed {}", BASIS_POINTS)
+        );
+        self.early_withdraw_penalty_bps = penalty_bps;
+        self.early_withdraw_penalty_destination = penalty_destination;
+    }
+
+    /// Withdraws `amount` of a locked or unlocking position now, paying a penalty
+    /// proportional to its remaining unbond time.
+    #[payable]
+    pub fn early_withdraw(&mut self, index: PositionIndex, amount: U128String) {
+        assert_one_yocto();
+        require!(
+            self.early_withdraw_penalty_bps > 0,
+            "Early withdraw is not enabled"
+        );
+        let voter_id = env::predecessor_account_id().as_str().to_string();
+        self.internal_ensure_voter_checkpoints(&voter_id);
+        let mut voter = self.internal_get_voter_or_panic(&voter_id);
+        self.internal_sync_voting_power(&voter_id, &mut voter);
+        let mut locking_position = voter.get_position(index);
+        let amount = amount.0;
+        require!(
+            !locking_position.is_unlocked(),
+            "The position is unlocked, use withdraw"
+        );
+        require!(
+            amount > 0 && amount <= locking_position.amount,
+            "Invalid amount"
+        );
+        let full = amount == locking_position.amount;
+        if !full {
+            assert!(
+                (locking_position.amount - amount) >= self.min_deposit_amount,
+                "A locking position cannot have less than {} mpDAO",
+                self.min_deposit_amount
+            );
+        }
+        let (penalty, _) = self.internal_early_withdraw_penalty(&locking_position, amount);
+
+        let remove_voting_power = if full {
+            locking_position.voting_power
+        } else {
+            proportional(
+                locking_position.voting_power,
+                amount,
+                locking_position.amount,
+            )
+        };
+        if locking_position.is_locked() {
+            self.internal_sub_locked_mpdao(&voter_id, amount);
+        } else {
+            self.internal_sub_unbonding_mpdao(&voter_id, &locking_position, amount);
+        }
+        if full {
+            voter.remove_position(index);
+        } else {
+            locking_position.amount -= amount;
+            locking_position.voting_power -= remove_voting_power;
+            voter.locking_positions.replace(index, &locking_position);
+        }
+        if locking_position.is_locked() {
+            assert!(
+                voter.available_voting_power >= remove_voting_power,
+                "Not enough free voting power to withdraw! You have {}, required {}.",
+                voter.available_voting_power,
+                remove_voting_power
+            );
+            voter.available_voting_power -= remove_voting_power;
+            self.total_voting_power = self.total_voting_power.saturating_sub(remove_voting_power);
+        }
+        // with the Decay model the unlocking position voting power is synced
+        self.internal_sync_voting_power(&voter_id, &mut voter);
+
+        Event::EarlyWithdraw {
+            voter_id: &voter_id,
+            index,
+            amount: amount.into(),
+            penalty: penalty.into(),
+            penalty_destination: self.early_withdraw_penalty_destination,
+        }
+        .emit();
+        self.internal_pay_penalty(penalty);
+
+        // the rest is withdrawn from the voter balance
+        let net_amount = amount - penalty;
+        voter.balance += net_amount;
+        self.internal_add_balance_mpdao(&voter_id, net_amount);
+        self.voters.insert(&voter_id, &voter);
+        if net_amount > 0 {
+            self.internal_withdraw(&voter_id, &voter_id, vec![], Some(net_amount), None);
+        }
+    }
+
+    // --------
+    // view fns
+    // --------
+
+    pub fn get_early_withdraw_config(&self) -> (u32, PenaltyDestination) {
+        (
+            self.early_withdraw_penalty_bps,
+            self.early_withdraw_penalty_destination,
+        )
+    }
+
+    /// quote of early_withdraw(index, amount) now
+    pub fn preview_early_withdraw(
+        &self,
+        voter_id: VoterId,
+        index: PositionIndex,
+        amount: U128String,
+    ) -> EarlyWithdrawPreviewJSON {
+        let voter = self.internal_get_voter_or_panic(&voter_id);
+        let locking_position = voter.get_position(index);
+        let amount = std::cmp::min(amount.0, locking_position.amount);
+        let (penalty, remaining_unbond_ms) =
+            self.internal_early_withdraw_penalty(&locking_position, amount);
+        EarlyWithdrawPreviewJSON {
+            amount: amount.into(),
+            penalty: penalty.into(),
+            net_amount: (amount - penalty).into(),
+            remaining_unbond_ms,
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use crate::*;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json;
+
+/// NEP-297 standard name and version for Meta Vote events.
+/// Bump the version when the data of any event changes.
+pub const EVENT_STANDARD: &str = "metavote";
+pub const EVENT_STANDARD_VERSION: &str = "1.1.0";
+
+/// Every Meta Vote state change, logged as `EVENT_JSON:{...}`.
+/// Amounts are mpDAO (6 decimals) or token units, voting power has 24 decimals.
+/// Position indexes follow `Vector::swap_remove` semantics when positions are removed.
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// mpDAO received through ft_on_transfer to be locked
+    Deposit {
+        voter_id: &'a str,
+        amount: U128String,
+        locking_period: Days,
+    },
+    /// a locked position was created (or increased) at `index`
+    Lock {
+        voter_id: &'a str,
+        index: PositionIndex,
+        amount: U128String,
+        locking_period: Days,
+        voting_power: U128String,
+    },
+    Unlock {
+        voter_id: &'a str,
+        index: PositionIndex,
+        amount: U128String,
+        voting_power: U128String,
+    },
+    /// `amount` moved from the locked position `index` into the new unlocking position `unlocking_index`
+    PartialUnlock {
+        voter_id: &'a str,
+        index: PositionIndex,
+        unlocking_index: PositionIndex,
+        amount: U128String,
+        voting_power: U128String,
+    },
+    /// followed by a `lock` event for the new locked position
+    Relock {
+        voter_id: &'a str,
+        index: Option<PositionIndex>,
+        amount_from_position: U128String,
+        amount_from_balance: U128String,
+        locking_period: Days,
+    },
+    Extend {
+        voter_id: &'a str,
+        index: PositionIndex,
+        prev_locking_period: Days,
+        locking_period: Days,
+        prev_voting_power: U128String,
+        voting_power: U128String,
+    },
+    /// a fully unlocked position was removed and its amount added to the voter balance
+    Clear {
+        voter_id: &'a str,
+        index: PositionIndex,
+        amount: U128String,
+    },
+    Withdraw {
+        voter_id: &'a str,
+        receiver_id: &'a str,
+        amount: U128String,
+        voter_removed: bool,
+    },
+    /// the mpDAO transfer failed and `amount` was restored to the voter balance
+    WithdrawFailed {
+        voter_id: &'a str,
+        amount: U128String,
+    },
+    Vote {
+        voter_id: &'a str,
+        contract_address: &'a str,
+        votable_object_id: &'a str,
+        voting_power: U128String,
+    },
+    Rebalance {
+        voter_id: &'a str,
+        contract_address: &'a str,
+        votable_object_id: &'a str,
+        prev_voting_power: U128String,
+        voting_power: U128String,
+    },
+    Unvote {
+        voter_id: &'a str,
+        contract_address: &'a str,
+        votable_object_id: &'a str,
+        voting_power: U128String,
+    },
+    Claim {
+        voter_id: &'a str,
+        receiver_id: &'a str,
+        token: &'a str,
+        amount: U128String,
+    },
+    /// the claim transfer failed and `amount` was restored as claimable
+    ClaimFailed {
+        voter_id: &'a str,
+        token: &'a str,
+        amount: U128String,
+    },
+    /// rewards added to the token stream, released to all the voting power until end_ms
+    StreamRewards {
+        token: &'a str,
+        amount: U128String,
+        end_ms: EpochMillis,
+    },
+    /// reward token added to the incentive pool of a votable object
+    DepositIncentive {
+        pool_id: u64,
+        contract_address: &'a str,
+        votable_object_id: &'a str,
+        token: &'a str,
+        amount: U128String,
+        cutoff_ms: EpochMillis,
+    },
+    /// voter share of the pool added to its claimable rewards
+    ClaimIncentive {
+        pool_id: u64,
+        voter_id: &'a str,
+        token: &'a str,
+        votes: U128String,
+        amount: U128String,
+    },
+    /// pool closed after the claim period, `unclaimed` transferred to the owner
+    CloseIncentivePool {
+        pool_id: u64,
+        token: &'a str,
+        unclaimed: U128String,
+    },
+    /// reward token deposited to be claimed with merkle proofs until expiry_ms
+    MerkleDistribution {
+        distribution_id: u64,
+        token: &'a str,
+        merkle_root: &'a str,
+        amount: U128String,
+        expiry_ms: EpochMillis,
+    },
+    /// leaf amount added to the voter claimable rewards
+    MerkleClaim {
+        distribution_id: u64,
+        index: u64,
+        voter_id: &'a str,
+        token: &'a str,
+        amount: U128String,
+    },
+    /// expired distribution removed, `unclaimed` transferred to the owner
+    SweepMerkleDistribution {
+        distribution_id: u64,
+        token: &'a str,
+        unclaimed: U128String,
+    },
+    /// for-claims distribution that can be swept to the treasury after expiry_ms
+    ExpiringClaims {
+        batch_id: u64,
+        token: &'a str,
+        total_amount: U128String,
+        expiry_ms: EpochMillis,
+    },
+    /// expired claims removed and transferred to the treasury
+    SweepExpiredClaims {
+        batch_id: u64,
+        token: &'a str,
+        amount: U128String,
+        treasury_id: &'a str,
+    },
+    /// mpDAO received for a claim_stnear_and_lock swap
+    SwapLock {
+        swap_id: u64,
+        voter_id: &'a str,
+        stnear_amount: U128String,
+        mpdao_amount: U128String,
+    },
+    /// opted-in voter not compounded, the claimable mpDAO cannot be locked for `locking_period` now
+    AutoCompoundSkipped {
+        voter_id: &'a str,
+        locking_period: Days,
+        amount: U128String,
+    },
+    /// locked or unlocking mpDAO withdrawn before the unbond period, `penalty` included in `amount`
+    EarlyWithdraw {
+        voter_id: &'a str,
+        index: PositionIndex,
+        amount: U128String,
+        penalty: U128String,
+        penalty_destination: PenaltyDestination,
+    },
+    /// per-account amounts are in the `for-claims:` msg of the transfer
+    DistributeForClaims {
+        token: &'a str,
+        total_amount: U128String,
+        accounts: u32,
+    },
+    Mirror {
+        voter_id: &'a str,
+        prev_voting_power: U128String,
+        voting_power: U128String,
+        used_voting_power: U128String,
+    },
+    /// `voting_power` moved from the delegator available voting power to the delegate
+    Delegate {
+        delegator_id: &'a str,
+        delegate_id: &'a str,
+        voting_power: U128String,
+    },
+    /// `voting_power` returned to the delegator, delegate votes removed first if needed
+    Undelegate {
+        delegator_id: &'a str,
+        delegate_id: &'a str,
+        voting_power: U128String,
+    },
+    /// locking positions voting power recomputed with the current formula
+    Recalculate {
+        voter_id: &'a str,
+        prev_voting_power: U128String,
+        voting_power: U128String,
+    },
+    EvmPreDelegate {
+        evm_address: &'a str,
+        delegate_id: &'a str,
+    },
+    EvmDelegate {
+        evm_address: &'a str,
+        delegate_id: &'a str,
+    },
+    EvmUndelegate {
+        evm_address: &'a str,
+        delegate_id: &'a str,
+    },
+    /// excess token balance (balance - owed to voters) transferred to the owner
+    OwnerWithdraw {
+        token: &'a str,
+        amount: U128String,
+        balance: U128String,
+        owed: U128String,
+    },
+    /// owner repair of the voter available voting power
+    RepairVoter {
+        voter_id: &'a str,
+        prev_available_voting_power: U128String,
+        available_voting_power: U128String,
+    },
+    /// owner repair of the total votes of a votable object
+    RepairVotes {
+        contract_address: &'a str,
+        votable_object_id: &'a str,
+        prev_votes: U128String,
+        votes: U128String,
+    },
+    /// owner repair of total_voting_power
+    RepairTotalVotingPower {
+        prev_total_voting_power: U128String,
+        total_voting_power: U128String,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+impl Event<'_> {
+    pub fn to_json_string(&self) -> String {
+        serde_json::to_string(&EventLog {
+            standard: EVENT_STANDARD,
+            version: EVENT_STANDARD_VERSION,
+            event: self,
+        })
+        .unwrap_or_else(|_| env::abort())
+    }
+
+    pub fn emit(&self) {
+        env::log_str(&format!("EVENT_JSON:{}", self.to_json_string()));
+    }
+}
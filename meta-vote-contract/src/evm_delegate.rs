@@ -1,6 +1,25 @@
 use crate::*;
 use near_sdk::{assert_one_yocto, near_bindgen};
 
+impl MetaVoteContract {
+    /// the key of evm_address in evm_delegation_signatures and evm_pre_delegation,
+    /// in the case it was stored with (see utils::find_evm_address_key)
+    fn internal_evm_delegation_key(&self, evm_address: &str) -> EvmAddress {
+        utils::find_evm_address_key(evm_address, |key| {
+            self.evm_delegation_signatures.contains_key(key)
+                || self.evm_pre_delegation.contains_key(key)
+        })
+    }
+
+    /// the pseudo account of evm_address, in the case the voter was created with
+    pub(crate) fn internal_evm_voter_id(&self, evm_address: &str) -> VoterId {
+        let key = utils::find_evm_address_key(evm_address, |key| {
+            self.voters.get(&utils::pseudo_near_address(key)).is_some()
+        });
+        utils::pseudo_near_address(&key)
+    }
+}
+
 #[near_bindgen]
 impl MetaVoteContract {
     // ************************
@@ -8,70 +27,175 @@ impl MetaVoteContract {
     // ************************
 
     #[payable]
-    /// called from the user account, with a ECDSA signature (ethereum signatures & the evm account)
-    /// if confirmed by the operator, it moves to "delegated"
-    pub fn pre_delegate_evm_address(&mut self, evm_address: String, signature: String) {
+    /// called from the user account, with an EIP-712 signature by the evm account of
+    /// Delegation(contract, delegate, nonce, deadline), see utils::eip712_delegation_hash
+    /// nonce must be the current one for evm_address and deadline (unix seconds) not expired
+    /// the signature is verified on-chain and the delegation is confirmed immediately
+    pub fn delegate_evm_address(
+        &mut self,
+        evm_address: String,
+        nonce: u64,
+        deadline: u64,
+        signature: String,
+    ) {
         assert_one_yocto();
-        // minimal checks to avoid common mistakes (e.g. send with .evmp.near)
-        assert!(
-            !evm_address.contains("."),
-            "evm_address can not contain dots"
+        let evm_address = self.internal_evm_delegation_key(&evm_address);
+        require!(
+            env::block_timestamp() / 1_000_000_000 <= deadline,
+            "signature expired"
         );
+        self.internal_use_evm_delegation_nonce(&evm_address, nonce);
         let account_id = env::predecessor_account_id();
-        self.evm_pre_delegation
-            .insert(evm_address, (account_id.into(), signature));
+        let hash = utils::eip712_delegation_hash(
+            env::current_account_id().as_str(),
+            account_id.as_str(),
+            nonce,
+            deadline,
+        );
+        require!(
+            utils::is_evm_signer(&evm_address, &hash, &signature),
+            "invalid signature for evm_address"
+        );
+        // remove a previous pending delegation, if any
+        self.evm_pre_delegation.remove(&evm_address);
+        // keep nonce and deadline with the signature, for external verification
+        let signature = format!("eip712:{}:{}:{}", nonce, deadline, signature);
+        self.internal_confirm_delegated_evm_address(evm_address, account_id.into(), signature);
+    }
+
+    /// nonce to sign in the next EIP-712 delegation of evm_address
+    pub fn get_evm_delegation_nonce(&self, evm_address: String) -> u64 {
+        let evm_address = utils::normalize_evm_address(&evm_address);
+        self.evm_delegation_nonces
+            .get(&evm_address)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    #[payable]
+    /// called from the user account, with a ECDSA signature (ethereum personal_sign
+    /// of "delegate to <account>" by the evm account)
+    /// the signature is verified on-chain and the delegation is confirmed immediately
+    /// the message has no nonce, it is nonce 0: accepted only for the first delegation
+    /// of evm_address. Later delegations use delegate_evm_address.
+    pub fn pre_delegate_evm_address(&mut self, evm_address: String, signature: String) {
+        assert_one_yocto();
+        // also avoids common mistakes (e.g. send with .evmp.near)
+        let evm_address = self.internal_evm_delegation_key(&evm_address);
+        let account_id = env::predecessor_account_id();
+        Event::EvmPreDelegate {
+            evm_address: &evm_address,
+            delegate_id: account_id.as_str(),
+        }
+        .emit();
+        require!(
+            utils::is_valid_evm_delegation_signature(
+                &evm_address,
+                account_id.as_str(),
+                &signature
+            ),
+            "invalid signature for evm_address"
+        );
+        self.internal_use_evm_delegation_nonce(&evm_address, 0);
+        // remove a previous pending delegation, if any
+        self.evm_pre_delegation.remove(&evm_address);
+        self.internal_confirm_delegated_evm_address(evm_address, account_id.into(), signature);
     }
 
     pub fn get_pre_delegate_evm_address(&self, evm_address: String) -> Option<&(String, String)> {
         self.evm_pre_delegation
-            .get(&evm_address)
+            .get(&self.internal_evm_delegation_key(&evm_address))
     }
 
     #[payable]
     pub fn operator_remove_pre_delegate_evm_address(&mut self, evm_address: String) {
         assert_one_yocto();
         self.assert_operator();
+        let evm_address = self.internal_evm_delegation_key(&evm_address);
         self.evm_pre_delegation.remove(&evm_address);
     }
 
     #[payable]
+    /// confirms pre-delegations registered before signatures were verified on-chain,
+    /// the signature is verified anyway, and it is nonce 0 as in pre_delegate_evm_address
     pub fn operator_confirm_delegated_evm_address(&mut self, evm_address: String) {
         assert_one_yocto();
         self.assert_operator();
+        let evm_address = self.internal_evm_delegation_key(&evm_address);
         if let Some(pre_delegation) = self.evm_pre_delegation.remove(&evm_address) {
             let account_id = pre_delegation.0;
             let evm_signature = pre_delegation.1;
-            if let Some(existing_delegation) = self.evm_delegation_signatures.get(&evm_address) {
-                // this evm_address was already delegated
-                if existing_delegation.0.eq(&account_id) {
-                    // to the the same, nothing to do
-                    // just removed the pre-delegation
-                    return;
+            require!(
+                utils::is_valid_evm_delegation_signature(&evm_address, &account_id, &evm_signature),
+                "invalid signature for evm_address"
+            );
+            self.internal_use_evm_delegation_nonce(&evm_address, 0);
+            self.internal_confirm_delegated_evm_address(evm_address, account_id, evm_signature);
+        }
+    }
+
+    /// a signature with `nonce` can be used once, the next one must sign nonce + 1
+    fn internal_use_evm_delegation_nonce(&mut self, evm_address: &EvmAddress, nonce: u64) {
+        let evm_address = &utils::normalize_evm_address(evm_address);
+        let expected_nonce = self
+            .evm_delegation_nonces
+            .get(evm_address)
+            .copied()
+            .unwrap_or_default();
+        assert!(
+            nonce == expected_nonce,
+            "invalid nonce, expected {}",
+            expected_nonce
+        );
+        self.evm_delegation_nonces
+            .insert(evm_address.clone(), nonce + 1);
+    }
+
+    fn internal_confirm_delegated_evm_address(
+        &mut self,
+        evm_address: String,
+        account_id: String,
+        evm_signature: String,
+    ) {
+        if let Some(existing_delegation) = self.evm_delegation_signatures.get(&evm_address) {
+            // this evm_address was already delegated
+            if existing_delegation.0.eq(&account_id) {
+                // to the the same, nothing to do
+                return;
+            } else {
+                // it was delegated to another near address, get the list of all delegations for that address
+                let mut previous_delegate_addresses =
+                    self.evm_delegates.get(&existing_delegation.0).unwrap();
+                // remove evm_address from the previous account id list
+                previous_delegate_addresses.retain(|x| !x.eq_ignore_ascii_case(&evm_address));
+                // update the old delegate addresses list
+                if previous_delegate_addresses.len() == 0 {
+                    self.evm_delegates.remove(&existing_delegation.0);
                 } else {
-                    // it was delegated to another near address, get the list of all delegations for that address
-                    let mut previous_delegate_addresses =
-                        self.evm_delegates.get(&existing_delegation.0).unwrap();
-                    // remove evm_address from the previous account id list
-                    previous_delegate_addresses.retain(|x| !x.eq(&evm_address));
-                    // update the old delegate addresses list
-                    if previous_delegate_addresses.len() == 0 {
-                        self.evm_delegates.remove(&existing_delegation.0);
-                    } else {
-                        self.evm_delegates
-                            .insert(&existing_delegation.0, &previous_delegate_addresses);
-                    }
+                    self.evm_delegates
+                        .insert(&existing_delegation.0, &previous_delegate_addresses);
+                }
+                Event::EvmUndelegate {
+                    evm_address: &evm_address,
+                    delegate_id: &existing_delegation.0,
                 }
+                .emit();
             }
-            // get current delegations for account_id
-            let mut delegated_addresses = self.evm_delegates.get(&account_id).unwrap_or_default();
-            // add this one
-            delegated_addresses.push(evm_address.clone());
-            // save
-            self.evm_delegates.insert(&account_id, &delegated_addresses);
-            // also save & keep the signature
-            self.evm_delegation_signatures
-                .insert(evm_address, (account_id, evm_signature));
         }
+        // get current delegations for account_id
+        let mut delegated_addresses = self.evm_delegates.get(&account_id).unwrap_or_default();
+        // add this one
+        delegated_addresses.push(evm_address.clone());
+        // save
+        self.evm_delegates.insert(&account_id, &delegated_addresses);
+        Event::EvmDelegate {
+            evm_address: &evm_address,
+            delegate_id: &account_id,
+        }
+        .emit();
+        // also save & keep the signature
+        self.evm_delegation_signatures
+            .insert(evm_address, (account_id, evm_signature));
     }
 
     #[payable]
@@ -83,31 +207,34 @@ impl MetaVoteContract {
         // verify delegation and compose the pseudo near account
         let pseudo_account = self.verify_delegate(&evm_address);
         // remove the claim
-        self.remove_claimable_stnear(&pseudo_account, amount.0);
+        let token_id = self.stnear_token_contract_address.clone();
+        let consumed_claims = self.internal_remove_claimable(&token_id, &pseudo_account, amount.0);
         // transfer to delegate
         self.transfer_stnear_to_voter(
             &pseudo_account,
             &env::predecessor_account_id().into(),
             amount.0,
+            consumed_claims,
         )
     }
 
     // local fn: verify delegation and compose pseudo account
     fn verify_delegate(&self, evm_address: &EvmAddress) -> String {
+        utils::normalize_evm_address(evm_address);
         // get delegations for predecessor_account_id
         let delegations = self
             .evm_delegates
             .get(&env::predecessor_account_id().into())
             .unwrap_or_default();
-        // make sure predecessor_account_id() is the delegate
+        // make sure predecessor_account_id() is the delegate, in any case
         assert!(
-            delegations.contains(&evm_address),
+            delegations.iter().any(|x| x.eq_ignore_ascii_case(evm_address)),
             "{} is not delegated to {}",
-            &evm_address,
+            evm_address,
             &env::predecessor_account_id()
         );
         // compose the pseudo near account
-        utils::pseudo_near_address(&evm_address)
+        self.internal_evm_voter_id(evm_address)
     }
 
     pub fn vote_delegated(
@@ -141,6 +268,7 @@ impl MetaVoteContract {
     #[payable]
     pub fn remove_delegated_evm_address(&mut self, evm_address: String) {
         assert_one_yocto();
+        let evm_address = self.internal_evm_delegation_key(&evm_address);
         if let Some(existing_delegation) = self.evm_delegation_signatures.get(&evm_address) {
             let predecessor = env::predecessor_account_id().as_str().to_string();
             // this evm_address is delegated
@@ -150,10 +278,15 @@ impl MetaVoteContract {
                 // remove from delegate's vector
                 let mut delegated_addresses = self.evm_delegates.get(&predecessor).unwrap();
                 // remove this one
-                delegated_addresses.retain(|x| !x.eq(&evm_address));
+                delegated_addresses.retain(|x| !x.eq_ignore_ascii_case(&evm_address));
                 // save
                 self.evm_delegates
                     .insert(&predecessor, &delegated_addresses);
+                Event::EvmUndelegate {
+                    evm_address: &evm_address,
+                    delegate_id: &predecessor,
+                }
+                .emit();
             } else {
                 panic!("note delegated to you");
             }
@@ -197,6 +330,7 @@ impl MetaVoteContract {
 
     // return the delegate (near account) for an specific evm address or null
     pub fn get_delegate(&self, evm_address: EvmAddress) -> Option<String> {
+        let evm_address = self.internal_evm_delegation_key(&evm_address);
         if let Some(delegation) = self.evm_delegation_signatures.get(&evm_address) {
             Some(delegation.0.to_string())
         } else {
@@ -207,7 +341,11 @@ impl MetaVoteContract {
     /// returns [near_account, delegation_signature], e.g: ["alice.near”, ”xxxxxxxxxxxxxxxx”]
     /// for external verification of the validity of delegations
     /// The message to validate against the signature is: “delegate to alice.near”
+    /// or, if the signature is "eip712:<nonce>:<deadline>:<signature>", the EIP-712
+    /// Delegation(contract, delegate, nonce, deadline), see utils::eip712_delegation_hash
     pub fn get_delegation_signature(&self, evm_address: String) -> &(String, String) {
-        self.evm_delegation_signatures.get(&evm_address).unwrap()
+        self.evm_delegation_signatures
+            .get(&self.internal_evm_delegation_key(&evm_address))
+            .unwrap()
     }
 }
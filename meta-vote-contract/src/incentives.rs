@@ -0,0 +1,450 @@
+use crate::interface::*;
+use crate::*;
+use near_sdk::near_bindgen;
+use near_sdk::serde::{Deserialize, Serialize};
+
+/// Reward token deposited for the voters of a votable object, split pro-rata to their
+/// votes on the object at `cutoff_ms`.
+/// Votes are not checkpointed, so the first vote change on the object after the cutoff
+/// records the voter votes and the object total before the change. Voters without a
+/// record still have their votes at the cutoff.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct IncentivePool {
+    pub contract_address: ContractAddress,
+    pub votable_object_id: VotableObjId,
+    pub token_id: AccountId,
+    pub amount: u128,
+    pub cutoff_ms: EpochMillis,
+    /// total votes on the object at the cutoff, None while unchanged since the cutoff
+    pub total_votes_at_cutoff: Option<u128>,
+    pub claimed: u128,
+    /// voters with an incentive_votes record, removed when the pool is closed
+    pub voters: Vector<VoterId>,
+    /// unclaimed rewards returned, the voters records are being removed
+    pub closed: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct IncentivePoolJSON {
+    pub pool_id: u64,
+    pub contract_address: ContractAddress,
+    pub votable_object_id: VotableObjId,
+    pub token_id: String,
+    pub amount: U128String,
+    pub cutoff_ms: EpochMillis,
+    pub total_votes_at_cutoff: U128String,
+    pub claimed: U128String,
+    /// after this date anyone can close the pool, unclaimed rewards go to the owner
+    pub close_after_ms: EpochMillis,
+}
+
+/// msg of the `incentive:` deposit
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct IncentiveDepositMsg {
+    pub contract_address: ContractAddress,
+    pub votable_object_id: VotableObjId,
+    pub cutoff_ms: EpochMillis,
+}
+
+/// voter votes on the object at the cutoff, and if the incentive was claimed
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy)]
+pub struct IncentiveVotes {
+    pub votes: u128,
+    pub claimed: bool,
+}
+
+fn object_key(contract_address: &str, votable_object_id: &str) -> (String, String) {
+    (contract_address.to_string(), votable_object_id.to_string())
+}
+
+/// end of the claim period
+fn close_after_ms(pool: &IncentivePool) -> EpochMillis {
+    pool.cutoff_ms
+        .checked_add(days_to_millis(INCENTIVE_POOL_CLAIM_DAYS))
+        .expect("Invalid incentive pool cutoff")
+}
+
+impl MetaVoteContract {
+    /// Adds `amount` of a reward token to the pool of the object with the same cutoff,
+    /// or opens a new one.
+    pub(crate) fn internal_deposit_incentive(
+        &mut self,
+        token_id: &AccountId,
+        amount: u128,
+        info: &IncentiveDepositMsg,
+    ) {
+        require!(amount > 0, "Amount must be greater than zero");
+        // must be a reward token
+        let unit = self.internal_reward_unit(token_id);
+        let now = get_current_epoch_millis();
+        require!(info.cutoff_ms > now, "The cutoff must be in the future");
+        require!(
+            info.cutoff_ms <= now + days_to_millis(self.max_unbond_period),
+            format!(
+                "The cutoff cannot be more than {} days ahead",
+                self.max_unbond_period
+            )
+        );
+        let key = object_key(&info.contract_address, &info.votable_object_id);
+        let mut pool_ids = self
+            .object_incentive_pools
+            .get(&key)
+            .cloned()
+            .unwrap_or_default();
+        let existing = pool_ids.iter().copied().find(|pool_id| {
+            let pool = self.incentive_pools.get(pool_id).unwrap();
+            pool.token_id == *token_id && pool.cutoff_ms == info.cutoff_ms
+        });
+        let pool_id = match existing {
+            Some(pool_id) => {
+                let mut pool = self.incentive_pools.get(&pool_id).unwrap();
+                pool.amount += amount;
+                self.incentive_pools.insert(&pool_id, &pool);
+                pool_id
+            }
+            None => {
+                require!(
+                    pool_ids.len() < MAX_INCENTIVE_POOLS_PER_OBJECT,
+                    format!(
+                        "Cannot exceed {} incentive pools per votable object",
+                        MAX_INCENTIVE_POOLS_PER_OBJECT
+                    )
+                );
+                assert!(
+                    amount >= MIN_INCENTIVE_POOL_UNITS * unit,
+                    "A new incentive pool cannot have less than {} units of the token",
+                    MIN_INCENTIVE_POOL_UNITS
+                );
+                let pool_id = self.next_incentive_pool_id;
+                self.next_incentive_pool_id += 1;
+                self.incentive_pools.insert(
+                    &pool_id,
+                    &IncentivePool {
+                        contract_address: info.contract_address.clone(),
+                        votable_object_id: info.votable_object_id.clone(),
+                        token_id: token_id.clone(),
+                        amount,
+                        cutoff_ms: info.cutoff_ms,
+                        total_votes_at_cutoff: None,
+                        claimed: 0,
+                        voters: Vector::new(StorageKey::IncentivePoolVoters { pool_id }),
+                        closed: false,
+                    },
+                );
+                pool_ids.push(pool_id);
+                self.object_incentive_pools.insert(key, pool_ids);
+                pool_id
+            }
+        };
+        let unclaimed = self
+            .incentive_pools_unclaimed
+            .get(token_id)
+            .copied()
+            .unwrap_or(0);
+        self.incentive_pools_unclaimed
+            .insert(token_id.clone(), unclaimed + amount);
+
+        Event::DepositIncentive {
+            pool_id,
+            contract_address: &info.contract_address,
+            votable_object_id: &info.votable_object_id,
+            token: token_id.as_str(),
+            amount: amount.into(),
+            cutoff_ms: info.cutoff_ms,
+        }
+        .emit();
+    }
+
+    /// Records the voter votes and the object total at the cutoff of the object pools,
+    /// must be called before the voter votes on the object change.
+    pub(crate) fn internal_snapshot_incentive_votes(
+        &mut self,
+        voter_id: &VoterId,
+        voter: &Voter,
+        contract_address: &ContractAddress,
+        votable_object_id: &VotableObjId,
+    ) {
+        let pool_ids = match self
+            .object_incentive_pools
+            .get(&object_key(contract_address, votable_object_id))
+        {
+            Some(pool_ids) => pool_ids.clone(),
+            None => return,
+        };
+        let now = get_current_epoch_millis();
+        for pool_id in pool_ids {
+            let mut pool = self.incentive_pools.get(&pool_id).unwrap();
+            if now < pool.cutoff_ms {
+                continue;
+            }
+            let mut changed = false;
+            if pool.total_votes_at_cutoff.is_none() {
+                pool.total_votes_at_cutoff = Some(
+                    self.get_total_votes(contract_address.clone(), votable_object_id.clone())
+                        .0,
+                );
+                changed = true;
+            }
+            let key = (pool_id, voter_id.clone());
+            if self.incentive_votes.get(&key).is_none() {
+                let votes = voter
+                    .vote_positions
+                    .get(contract_address)
+                    .and_then(|votes_for_address| votes_for_address.get(votable_object_id))
+                    .unwrap_or(0);
+                self.incentive_votes.insert(
+                    key,
+                    IncentiveVotes {
+                        votes,
+                        claimed: false,
+                    },
+                );
+                pool.voters.push(voter_id);
+                changed = true;
+            }
+            if changed {
+                self.incentive_pools.insert(&pool_id, &pool);
+            }
+        }
+    }
+
+    /// voter votes on the pool object at the cutoff
+    fn internal_incentive_votes(
+        &self,
+        pool_id: u64,
+        pool: &IncentivePool,
+        voter_id: &VoterId,
+    ) -> IncentiveVotes {
+        match self.incentive_votes.get(&(pool_id, voter_id.clone())) {
+            Some(recorded) => *recorded,
+            None => IncentiveVotes {
+                votes: self
+                    .voters
+                    .get(voter_id)
+                    .and_then(|voter| voter.vote_positions.get(&pool.contract_address))
+                    .and_then(|votes_for_address| votes_for_address.get(&pool.votable_object_id))
+                    .unwrap_or(0),
+                claimed: false,
+            },
+        }
+    }
+
+    fn internal_pool_total_votes(&self, pool: &IncentivePool) -> u128 {
+        pool.total_votes_at_cutoff.unwrap_or_else(|| {
+            self.get_total_votes(
+                pool.contract_address.clone(),
+                pool.votable_object_id.clone(),
+            )
+            .0
+        })
+    }
+
+    fn internal_get_incentive_pool(&self, pool_id: u64) -> IncentivePool {
+        match self.incentive_pools.get(&pool_id) {
+            Some(pool) if !pool.closed => pool,
+            _ => panic!("invalid incentive pool {}", pool_id),
+        }
+    }
+
+    /// Removes the pool from its object and the unclaimed total,
+    /// its voters records are removed by internal_clear_incentive_votes.
+    fn internal_close_incentive_pool(&mut self, pool_id: u64, pool: &mut IncentivePool) {
+        pool.closed = true;
+        self.incentive_pools.insert(&pool_id, pool);
+        let key = object_key(&pool.contract_address, &pool.votable_object_id);
+        let mut pool_ids = self
+            .object_incentive_pools
+            .get(&key)
+            .cloned()
+            .unwrap_or_default();
+        pool_ids.retain(|id| *id != pool_id);
+        if pool_ids.is_empty() {
+            self.object_incentive_pools.remove(&key);
+        } else {
+            self.object_incentive_pools.insert(key, pool_ids);
+        }
+        let unclaimed = self
+            .incentive_pools_unclaimed
+            .get(&pool.token_id)
+            .copied()
+            .unwrap_or(0);
+        self.incentive_pools_unclaimed.insert(
+            pool.token_id.clone(),
+            unclaimed - (pool.amount - pool.claimed),
+        );
+    }
+
+    /// Removes up to MAX_INCENTIVE_VOTES_CLEARED_PER_CALL voters records of the closed pool,
+    /// and the pool when there are no more. Returns true if the pool was removed.
+    fn internal_clear_incentive_votes(&mut self, pool_id: u64, pool: &mut IncentivePool) -> bool {
+        for _ in 0..MAX_INCENTIVE_VOTES_CLEARED_PER_CALL {
+            match pool.voters.pop() {
+                Some(voter_id) => {
+                    self.incentive_votes.remove(&(pool_id, voter_id));
+                }
+                None => break,
+            }
+        }
+        if pool.voters.is_empty() {
+            self.incentive_pools.remove(&pool_id);
+            true
+        } else {
+            self.incentive_pools.insert(&pool_id, pool);
+            false
+        }
+    }
+
+    /// incentives deposited and not yet claimed or closed, owed to voters
+    pub(crate) fn internal_get_incentive_pools_unclaimed(&self, token_id: &AccountId) -> u128 {
+        self.incentive_pools_unclaimed
+            .get(token_id)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn incentive_pool_to_json(&self, pool_id: u64, pool: &IncentivePool) -> IncentivePoolJSON {
+        IncentivePoolJSON {
+            pool_id,
+            contract_address: pool.contract_address.clone(),
+            votable_object_id: pool.votable_object_id.clone(),
+            token_id: pool.token_id.to_string(),
+            amount: pool.amount.into(),
+            cutoff_ms: pool.cutoff_ms,
+            total_votes_at_cutoff: self.internal_pool_total_votes(pool).into(),
+            claimed: pool.claimed.into(),
+            close_after_ms: close_after_ms(pool),
+        }
+    }
+}
+
+#[near_bindgen]
+impl MetaVoteContract {
+    /// Adds the voter share of the pool to its claimable rewards of the pool token.
+    /// The share is the pool amount * voter votes / total votes on the object at the cutoff.
+    pub fn claim_incentive(&mut self, pool_id: u64) -> U128String {
+        let voter_id = env::predecessor_account_id().to_string();
+        let mut pool = self.internal_get_incentive_pool(pool_id);
+        require!(
+            get_current_epoch_millis() >= pool.cutoff_ms,
+            "The incentive pool cutoff has not been reached"
+        );
+        let voter_votes = self.internal_incentive_votes(pool_id, &pool, &voter_id);
+        require!(!voter_votes.claimed, "Incentive already claimed");
+        require!(
+            voter_votes.votes > 0,
+            "No votes on the object at the cutoff"
+        );
+        // freeze the total, unchanged since the cutoff if it was not recorded
+        let total_votes = self.internal_pool_total_votes(&pool);
+        pool.total_votes_at_cutoff = Some(total_votes);
+        let amount = proportional(pool.amount, voter_votes.votes, total_votes);
+        pool.claimed += amount;
+        let recorded = self
+            .incentive_votes
+            .insert(
+                (pool_id, voter_id.clone()),
+                IncentiveVotes {
+                    votes: voter_votes.votes,
+                    claimed: true,
+                },
+            )
+            .is_some();
+        if !recorded {
+            pool.voters.push(&voter_id);
+        }
+        self.incentive_pools.insert(&pool_id, &pool);
+        let unclaimed = self.internal_get_incentive_pools_unclaimed(&pool.token_id);
+        self.incentive_pools_unclaimed
+            .insert(pool.token_id.clone(), unclaimed - amount);
+        if amount > 0 {
+            self.internal_add_claimable(&pool.token_id, &voter_id, amount);
+            self.internal_add_distributed(&pool.token_id, amount);
+        }
+
+        Event::ClaimIncentive {
+            pool_id,
+            voter_id: &voter_id,
+            token: pool.token_id.as_str(),
+            votes: voter_votes.votes.into(),
+            amount: amount.into(),
+        }
+        .emit();
+        amount.into()
+    }
+
+    /// After the claim period, anyone can close the pool.
+    /// The unclaimed rewards are transferred to the owner, and the voters records removed
+    /// in batches: call again until it returns true.
+    pub fn close_incentive_pool(&mut self, pool_id: u64) -> bool {
+        let mut pool = match self.incentive_pools.get(&pool_id) {
+            Some(pool) => pool,
+            None => panic!("invalid incentive pool {}", pool_id),
+        };
+        if pool.closed {
+            return self.internal_clear_incentive_votes(pool_id, &mut pool);
+        }
+        require!(
+            get_current_epoch_millis() >= close_after_ms(&pool),
+            "The incentive pool claim period has not ended"
+        );
+        self.internal_close_incentive_pool(pool_id, &mut pool);
+        let unclaimed = pool.amount - pool.claimed;
+        Event::CloseIncentivePool {
+            pool_id,
+            token: pool.token_id.as_str(),
+            unclaimed: unclaimed.into(),
+        }
+        .emit();
+        if unclaimed > 0 {
+            // if the transfer fails, the owner can withdraw it as excess balance
+            ext_ft::ext(pool.token_id.clone())
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .with_attached_deposit(1)
+                .ft_transfer(self.owner_id.to_string(), unclaimed.into(), None);
+        }
+        self.internal_clear_incentive_votes(pool_id, &mut pool)
+    }
+
+    // --------
+    // view fns
+    // --------
+
+    pub fn get_incentive_pool(&self, pool_id: u64) -> Option<IncentivePoolJSON> {
+        self.incentive_pools
+            .get(&pool_id)
+            .filter(|pool| !pool.closed)
+            .map(|pool| self.incentive_pool_to_json(pool_id, &pool))
+    }
+
+    pub fn get_incentive_pools(
+        &self,
+        contract_address: ContractAddress,
+        votable_object_id: VotableObjId,
+    ) -> Vec<IncentivePoolJSON> {
+        self.object_incentive_pools
+            .get(&object_key(&contract_address, &votable_object_id))
+            .map_or(Vec::new(), |pool_ids| {
+                pool_ids
+                    .iter()
+                    .map(|pool_id| {
+                        let pool = self.incentive_pools.get(pool_id).unwrap();
+                        self.incentive_pool_to_json(*pool_id, &pool)
+                    })
+                    .collect()
+            })
+    }
+
+    /// voter share of the pool, zero if already claimed. Before the cutoff it is
+    /// an estimate with the current votes.
+    pub fn get_claimable_incentive(&self, pool_id: u64, voter_id: VoterId) -> U128String {
+        let pool = self.internal_get_incentive_pool(pool_id);
+        let voter_votes = self.internal_incentive_votes(pool_id, &pool, &voter_id);
+        let total_votes = self.internal_pool_total_votes(&pool);
+        if voter_votes.claimed || total_votes == 0 {
+            return 0.into();
+        }
+        proportional(pool.amount, voter_votes.votes, total_votes).into()
+    }
+}
@@ -17,6 +17,8 @@ pub trait FungibleTokenCore {
         amount: U128,
         memo: Option<String>
     );
+
+    fn ft_balance_of(&self, account_id: AccountId) -> U128;
 }
 
 #[ext_contract(ext_self)]
@@ -35,7 +35,35 @@ impl MetaVoteContract {
         }
     }
 
-    fn internal_get_total_votes_for_address(
+    /// Applies a change of the voter own voting power to available_voting_power and the contract total.
+    /// Votes are removed if the voting power that decreased was in use, and then delegations
+    /// if it was delegated.
+    pub(crate) fn internal_update_voting_power(
+        &mut self,
+        voter_id: &VoterId,
+        voter: &mut Voter,
+        prev_voting_power: u128,
+        new_voting_power: u128,
+    ) {
+        if new_voting_power > prev_voting_power {
+            let increase = new_voting_power - prev_voting_power;
+            voter.available_voting_power += increase;
+            self.total_voting_power += increase;
+        } else if prev_voting_power > new_voting_power {
+            let decrease = prev_voting_power - new_voting_power;
+            let max_used_voting_power =
+                (voter.available_voting_power + voter.sum_used_votes()).saturating_sub(decrease);
+            self.internal_unvote_until(voter_id, voter, max_used_voting_power);
+            if voter.available_voting_power < decrease {
+                let delegated = decrease - voter.available_voting_power;
+                self.internal_reduce_delegations(voter_id, voter, delegated);
+            }
+            voter.available_voting_power -= decrease;
+            self.total_voting_power = self.total_voting_power.saturating_sub(decrease);
+        }
+    }
+
+    pub(crate) fn internal_get_total_votes_for_address(
         &self,
         contract_address: &String,
     ) -> UnorderedMap<VotableObjId, u128> {
@@ -90,7 +118,7 @@ impl MetaVoteContract {
     // * Claimable Meta & stNear *
     // ***************************
 
-    fn add_claimable(
+    pub(crate) fn add_claimable(
         claimable_map: &mut UnorderedMap<String, u128>,
         total_unclaimed: &mut u128,
         account: &String,
@@ -102,7 +130,7 @@ impl MetaVoteContract {
         *total_unclaimed += amount;
     }
 
-    fn remove_claimable(
+    pub(crate) fn remove_claimable(
         claimable_map: &mut UnorderedMap<String, u128>,
         total_unclaimed: &mut u128,
         account: &String,
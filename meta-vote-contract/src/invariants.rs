@@ -0,0 +1,155 @@
+use crate::*;
+use near_sdk::near_bindgen;
+use near_sdk::serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InvariantDiscrepancyJSON {
+    /// "voter:<voter_id>", "total_voting_power" or "votes:<contract>:<votable_object_id>"
+    pub subject: String,
+    pub expected: U128String,
+    pub actual: U128String,
+}
+
+/// totals of the voters already checked, pass it to the next call
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InvariantsAccumulatorJSON {
+    pub voting_power: U128String,
+    pub votes: Vec<VotableObjectJSON>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InvariantsReportJSON {
+    pub next_index: u32,
+    pub total_voters: u32,
+    pub done: bool,
+    /// per voter discrepancies in this batch, and contract totals discrepancies when done
+    pub discrepancies: Vec<InvariantDiscrepancyJSON>,
+    pub accumulator: InvariantsAccumulatorJSON,
+}
+
+impl Default for InvariantsAccumulatorJSON {
+    fn default() -> Self {
+        Self {
+            voting_power: 0.into(),
+            votes: Vec::new(),
+        }
+    }
+}
+
+fn add_votes(votes: &mut Vec<VotableObjectJSON>, contract_address: &str, id: &str, amount: u128) {
+    match votes
+        .iter_mut()
+        .find(|v| v.votable_contract == contract_address && v.id == id)
+    {
+        Some(v) => v.current_votes = (v.current_votes.0 + amount).into(),
+        None => votes.push(VotableObjectJSON {
+            votable_contract: contract_address.to_string(),
+            id: id.to_string(),
+            current_votes: amount.into(),
+        }),
+    }
+}
+
+#[near_bindgen]
+impl MetaVoteContract {
+    /// Audits the voting power accounting, in batches of `limit` voters starting at `from_index`.
+    /// For each voter: available_voting_power + used votes == own voting power + delegated in - delegated out.
+    /// When done: total_voting_power == sum of the voters own voting power,
+    /// and `votes` == sum of the voters vote positions.
+    /// Call again with the returned next_index and accumulator until done.
+    pub fn check_invariants(
+        &self,
+        from_index: u32,
+        limit: u32,
+        accumulator: Option<InvariantsAccumulatorJSON>,
+    ) -> InvariantsReportJSON {
+        let mut accumulator = accumulator.unwrap_or_default();
+        let mut discrepancies = Vec::new();
+        let keys = self.voters.keys_as_vector();
+        let total_voters = keys.len() as u32;
+        let next_index = std::cmp::min(from_index.saturating_add(limit), total_voters);
+
+        for index in from_index..next_index {
+            let voter_id = keys.get(index as u64).unwrap();
+            let voter = self.voters.get(&voter_id).unwrap();
+            let own_voting_power = self.internal_get_counted_voting_power(&voter_id, &voter);
+            let delegated_out: u128 = self
+                .delegated_to
+                .get(&voter_id)
+                .map_or(0, |list| list.iter().map(|(_, amount)| amount).sum());
+            // None if more was delegated out than the voter has, reported as expected 0
+            let expected = own_voting_power
+                .checked_add(self.internal_delegated_in(&voter_id))
+                .and_then(|total| total.checked_sub(delegated_out));
+            let actual = voter.available_voting_power + voter.sum_used_votes();
+            if expected != Some(actual) {
+                discrepancies.push(InvariantDiscrepancyJSON {
+                    subject: format!("voter:{}", voter_id),
+                    expected: expected.unwrap_or(0).into(),
+                    actual: actual.into(),
+                });
+            }
+
+            accumulator.voting_power = (accumulator.voting_power.0 + own_voting_power).into();
+            for (contract_address, votes_for_address) in voter.vote_positions.iter() {
+                for (votable_object_id, amount) in votes_for_address.iter() {
+                    add_votes(
+                        &mut accumulator.votes,
+                        &contract_address,
+                        &votable_object_id,
+                        amount,
+                    );
+                }
+            }
+        }
+
+        let done = next_index >= total_voters;
+        if done {
+            if accumulator.voting_power.0 != self.total_voting_power {
+                discrepancies.push(InvariantDiscrepancyJSON {
+                    subject: "total_voting_power".into(),
+                    expected: accumulator.voting_power,
+                    actual: self.total_voting_power.into(),
+                });
+            }
+            // every object in the contract votes, plus the ones only in vote positions
+            let mut all_votes = Vec::<VotableObjectJSON>::new();
+            for (contract_address, votes_for_address) in self.votes.iter() {
+                for (votable_object_id, _) in votes_for_address.iter() {
+                    add_votes(&mut all_votes, &contract_address, &votable_object_id, 0);
+                }
+            }
+            for v in accumulator.votes.iter() {
+                add_votes(&mut all_votes, &v.votable_contract, &v.id, 0);
+            }
+            for v in all_votes {
+                let expected = accumulator
+                    .votes
+                    .iter()
+                    .find(|a| a.votable_contract == v.votable_contract && a.id == v.id)
+                    .map_or(0, |a| a.current_votes.0);
+                let actual = self
+                    .get_total_votes(v.votable_contract.clone(), v.id.clone())
+                    .0;
+                if expected != actual {
+                    discrepancies.push(InvariantDiscrepancyJSON {
+                        subject: format!("votes:{}:{}", v.votable_contract, v.id),
+                        expected: expected.into(),
+                        actual: actual.into(),
+                    });
+                }
+            }
+        }
+
+        InvariantsReportJSON {
+            next_index,
+            total_voters,
+            done,
+            discrepancies,
+            accumulator,
+        }
+    }
+}
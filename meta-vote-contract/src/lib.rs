@@ -1,22 +1,49 @@
 use crate::{constants::*, locking_position::*, utils::*};
+use checkpoint::VotingPowerCheckpoint;
+use events::Event;
 use near_sdk::{
     assert_one_yocto,
     borsh::{self, BorshDeserialize, BorshSerialize},
     collections::{unordered_map::UnorderedMap, Vector},
     env, log, near_bindgen, require,
-    store::LookupMap,
+    store::{LookupMap, LookupSet},
     AccountId, Balance, PanicOnDefault, Promise,
 };
 use types::*;
+use claim_batches::{ClaimBatch, ConsumedClaim};
+use incentives::{IncentivePool, IncentiveVotes};
+use merkle::MerkleDistribution;
+use reward_tokens::RewardToken;
+use storage::VoterStorage;
+use streaming::RewardStream;
+use swap::PendingSwap;
 use voter::Voter;
 
+mod checkpoint;
+mod claim_batches;
+mod compound;
 mod constants;
+mod curve;
+mod decay;
+mod delegation;
 mod deposit;
+mod early_withdraw;
+mod events;
 mod evm_delegate;
+mod incentives;
 mod interface;
 mod internal;
+mod invariants;
 mod locking_position;
+mod merkle;
 mod migrate;
+mod recalculate;
+mod repair;
+mod reward_tokens;
+mod storage;
+mod streaming;
+mod swap;
+mod totals;
 mod types;
 mod utils;
 mod view;
@@ -63,6 +90,95 @@ pub struct MetaVoteContract {
     pub lock_votes_in_end_timestamp_ms: u64,
     pub lock_votes_in_address: Option<String>,
     pub lock_votes_in_numeric_id: u16,
+
+    // voting power history, for snapshot-based governance
+    pub voter_checkpoints: LookupMap<VoterId, Vector<VotingPowerCheckpoint>>,
+    pub total_voting_power_checkpoints: Vector<VotingPowerCheckpoint>,
+
+    // NEAR accounts voting power delegation, delegator => [(delegate, vp)] and delegate => [(delegator, vp)]
+    pub delegated_to: UnorderedMap<VoterId, Vec<(VoterId, u128)>>,
+    pub delegated_from: UnorderedMap<VoterId, Vec<(VoterId, u128)>>,
+
+    // next EIP-712 delegation nonce for each evm address
+    pub evm_delegation_nonces: LookupMap<EvmAddress, u64>,
+
+    // voting power model, and the decaying voting power of unlocking positions counted per voter
+    pub voting_power_model: VotingPowerModel,
+    pub decaying_voting_power: LookupMap<VoterId, u128>,
+
+    // (days, multiplier_bps) points for new locking positions, empty for the default days/60
+    pub voting_power_curve: Vec<(Days, u32)>,
+
+    // NEP-145 storage deposits, charged when locking and vote positions are created
+    pub storage_accounts: LookupMap<VoterId, VoterStorage>,
+    pub storage_deposit_required: bool,
+
+    // mpDAO held for voters (mirrored accounts excluded): locked positions, unlocking or
+    // unlocked positions not yet cleared, and balances
+    pub total_locked_mpdao: u128,
+    pub total_unbonding_mpdao: u128,
+    pub total_balance_mpdao: u128,
+    // unbonding mpDAO by the day the unbonding period ends
+    pub unbonding_schedule: UnorderedMap<EpochMillis, u128>,
+    // after an upgrade, voters are counted in the totals from the last index down (see
+    // operator_backfill_mpdao_totals), only the counted ones are updated until it is done
+    pub mpdao_totals_backfill_index: Option<u64>,
+    pub mpdao_counted_voters: LookupSet<VoterId>,
+    // transfers to voters not yet resolved, still owed if they fail
+    pub mpdao_in_transit: u128,
+    pub stnear_in_transit: u128,
+    // owner withdraw not yet resolved, only one at a time
+    pub owner_withdraw_in_transit: Option<(AccountId, u128)>,
+
+    // whitelisted reward tokens for claims, besides mpDAO and stNEAR
+    pub reward_tokens: UnorderedMap<AccountId, RewardToken>,
+
+    // rewards streamed to all the voting power, by reward token
+    pub reward_streams: UnorderedMap<AccountId, RewardStream>,
+    // (voter, token) => reward_per_voting_power at the last settlement
+    pub stream_rewards_paid: LookupMap<(VoterId, AccountId), u128>,
+    // total_voting_power at the last voting power change, rewards are released to it
+    pub rewards_total_voting_power: u128,
+
+    // reward tokens deposited for the voters of a votable object, by pool id
+    pub incentive_pools: UnorderedMap<u64, IncentivePool>,
+    pub next_incentive_pool_id: u64,
+    // (contract_address, votable_object_id) => pool ids
+    pub object_incentive_pools: LookupMap<(ContractAddress, VotableObjId), Vec<u64>>,
+    // (pool_id, voter) => votes at the cutoff, recorded on the first change after it
+    pub incentive_votes: LookupMap<(u64, VoterId), IncentiveVotes>,
+    // pools amount not yet claimed, by token
+    pub incentive_pools_unclaimed: LookupMap<AccountId, u128>,
+
+    // reward tokens claimed with merkle proofs, by distribution id
+    pub merkle_distributions: UnorderedMap<u64, MerkleDistribution>,
+    pub next_merkle_distribution_id: u64,
+    // (distribution_id, index / 128) => claimed bitmap word
+    pub merkle_claimed: LookupMap<(u64, u64), u128>,
+    // merkle distributions amount not yet claimed, by token
+    pub merkle_unclaimed: LookupMap<AccountId, u128>,
+
+    // for-claims distributions with expiry, by batch id
+    pub claim_batches: UnorderedMap<u64, ClaimBatch>,
+    pub next_claim_batch_id: u64,
+    // (voter, token) => batches with claimable amounts of the voter
+    pub voter_claim_batches: LookupMap<(VoterId, AccountId), Vec<u64>>,
+    // receives the expired claims
+    pub treasury_id: AccountId,
+
+    // swaps claimed stNEAR to mpDAO for claim_stnear_and_lock
+    pub swap_contract_id: Option<AccountId>,
+    pub pending_swaps: UnorderedMap<u64, PendingSwap>,
+    pub next_swap_id: u64,
+
+    // voters that opted-in to have their claimable mpDAO locked, voter => locking period
+    pub auto_compound: UnorderedMap<VoterId, Days>,
+
+    // penalty of early withdraws for the max unbond period remaining, 0 = disabled
+    pub early_withdraw_penalty_bps: u32,
+    pub early_withdraw_penalty_destination: PenaltyDestination,
+    // early withdraw penalties kept in the contract
+    pub total_burned_mpdao: u128,
 }
 
 #[near_bindgen]
@@ -87,7 +203,7 @@ impl MetaVoteContract {
             "Review the min and max locking period"
         );
         Self {
-            owner_id,
+            owner_id: owner_id.clone(),
             operator_id,
             voters: UnorderedMap::new(StorageKey::Voters),
             votes: UnorderedMap::new(StorageKey::Votes),
@@ -114,6 +230,49 @@ impl MetaVoteContract {
             lock_votes_in_end_timestamp_ms: 0,
             lock_votes_in_address: None,
             lock_votes_in_numeric_id: 0,
+            voter_checkpoints: LookupMap::new(StorageKey::VoterCheckpoints),
+            total_voting_power_checkpoints: Vector::new(StorageKey::TotalVotingPowerCheckpoints),
+            delegated_to: UnorderedMap::new(StorageKey::DelegatedTo),
+            delegated_from: UnorderedMap::new(StorageKey::DelegatedFrom),
+            evm_delegation_nonces: LookupMap::new(StorageKey::EvmDelegationNonces),
+            voting_power_model: VotingPowerModel::Linear,
+            decaying_voting_power: LookupMap::new(StorageKey::DecayingVotingPower),
+            voting_power_curve: Vec::new(),
+            storage_accounts: LookupMap::new(StorageKey::StorageAccounts),
+            storage_deposit_required: false,
+            total_locked_mpdao: 0,
+            total_unbonding_mpdao: 0,
+            total_balance_mpdao: 0,
+            unbonding_schedule: UnorderedMap::new(StorageKey::UnbondingSchedule),
+            mpdao_totals_backfill_index: None,
+            mpdao_counted_voters: LookupSet::new(StorageKey::MpdaoCountedVoters),
+            mpdao_in_transit: 0,
+            stnear_in_transit: 0,
+            owner_withdraw_in_transit: None,
+            reward_tokens: UnorderedMap::new(StorageKey::RewardTokens),
+            reward_streams: UnorderedMap::new(StorageKey::RewardStreams),
+            stream_rewards_paid: LookupMap::new(StorageKey::StreamRewardsPaid),
+            rewards_total_voting_power: 0,
+            incentive_pools: UnorderedMap::new(StorageKey::IncentivePools),
+            next_incentive_pool_id: 0,
+            object_incentive_pools: LookupMap::new(StorageKey::ObjectIncentivePools),
+            incentive_votes: LookupMap::new(StorageKey::IncentiveVotes),
+            incentive_pools_unclaimed: LookupMap::new(StorageKey::IncentivePoolsUnclaimed),
+            merkle_distributions: UnorderedMap::new(StorageKey::MerkleDistributions),
+            next_merkle_distribution_id: 0,
+            merkle_claimed: LookupMap::new(StorageKey::MerkleClaimed),
+            merkle_unclaimed: LookupMap::new(StorageKey::MerkleUnclaimed),
+            claim_batches: UnorderedMap::new(StorageKey::ClaimBatches),
+            next_claim_batch_id: 0,
+            voter_claim_batches: LookupMap::new(StorageKey::VoterClaimBatches),
+            treasury_id: owner_id,
+            swap_contract_id: None,
+            pending_swaps: UnorderedMap::new(StorageKey::PendingSwaps),
+            next_swap_id: 0,
+            auto_compound: UnorderedMap::new(StorageKey::AutoCompound),
+            early_withdraw_penalty_bps: 0,
+            early_withdraw_penalty_destination: PenaltyDestination::Burn,
+            total_burned_mpdao: 0,
         }
     }
 
@@ -133,6 +292,12 @@ impl MetaVoteContract {
         self.operator_id = operator_id;
     }
     #[payable]
+    pub fn set_treasury_id(&mut self, treasury_id: AccountId) {
+        assert_one_yocto();
+        self.assert_only_owner();
+        self.treasury_id = treasury_id;
+    }
+    #[payable]
     pub fn set_owner_id(&mut self, owner_id: AccountId) {
         assert_one_yocto();
         self.assert_only_owner();
@@ -206,22 +371,71 @@ impl MetaVoteContract {
 
     // claim mpDAO and create/update a locking position
     pub fn claim_and_lock(&mut self, amount: U128String, locking_period: u16) {
-        let amount = amount.0;
-        self.assert_min_deposit_amount(amount);
         let voter_id: String = env::predecessor_account_id().into();
-        self.remove_claimable_mpdao(&voter_id, amount);
-        let mut voter = self.internal_get_voter_or_panic(&voter_id);
+        self.internal_claim_and_lock(&voter_id, &voter_id, amount.0, locking_period);
+    }
+
+    // claim mpDAO and create/update a locking position of another account
+    pub fn claim_mpdao_and_lock_for(
+        &mut self,
+        beneficiary_id: AccountId,
+        amount: U128String,
+        locking_period: u16,
+    ) {
+        let voter_id: String = env::predecessor_account_id().into();
+        self.internal_claim_and_lock(&voter_id, &beneficiary_id.into(), amount.0, locking_period);
+    }
+
+    pub(crate) fn internal_claim_and_lock(
+        &mut self,
+        voter_id: &VoterId,
+        beneficiary_id: &VoterId,
+        amount: u128,
+        locking_period: u16,
+    ) {
+        self.assert_min_deposit_amount(amount);
+        self.internal_settle_stream_rewards(voter_id);
+        let token_id = self.mpdao_token_contract_address.clone();
+        self.internal_remove_claimable(&token_id, voter_id, amount);
+        Event::Claim {
+            voter_id,
+            receiver_id: beneficiary_id,
+            token: self.mpdao_token_contract_address.as_str(),
+            amount: amount.into(),
+        }
+        .emit();
+        self.internal_ensure_voter_checkpoints(beneficiary_id);
+        let mut voter = if voter_id == beneficiary_id {
+            self.internal_get_voter_or_panic(beneficiary_id)
+        } else {
+            self.internal_get_voter(beneficiary_id)
+        };
         // create/update locking position
-        self.deposit_locking_position(amount, locking_period, &voter_id, &mut voter);
+        self.deposit_locking_position(amount, locking_period, beneficiary_id, &mut voter);
     }
 
     // claim stNear
     pub fn claim_stnear(&mut self, amount: U128String) -> Promise {
-        let amount = amount.0;
         let voter_id = env::predecessor_account_id().to_string();
-        self.remove_claimable_stnear(&voter_id, amount);
-        let receiver = voter_id.clone();
-        self.transfer_stnear_to_voter(&voter_id, &receiver, amount)
+        self.internal_claim_stnear_to(&voter_id, &voter_id, amount.0)
+    }
+
+    // claim stNear, transferred to another account
+    pub fn claim_stnear_to(&mut self, receiver_id: AccountId, amount: U128String) -> Promise {
+        let voter_id = env::predecessor_account_id().to_string();
+        self.internal_claim_stnear_to(&voter_id, &receiver_id.into(), amount.0)
+    }
+
+    fn internal_claim_stnear_to(
+        &mut self,
+        voter_id: &VoterId,
+        receiver_id: &String,
+        amount: u128,
+    ) -> Promise {
+        self.internal_settle_stream_rewards(voter_id);
+        let token_id = self.stnear_token_contract_address.clone();
+        let consumed_claims = self.internal_remove_claimable(&token_id, voter_id, amount);
+        self.transfer_stnear_to_voter(voter_id, receiver_id, amount, consumed_claims)
     }
 
     // *************
@@ -230,31 +444,40 @@ impl MetaVoteContract {
 
     pub fn unlock_position(&mut self, index: PositionIndex) {
         let voter_id: String = env::predecessor_account_id().as_str().to_string();
+        self.internal_ensure_voter_checkpoints(&voter_id);
         let mut voter = self.internal_get_voter_or_panic(&voter_id);
         let mut locking_position = voter.get_position(index);
 
         let voting_power = locking_position.voting_power;
+
+        Event::Unlock {
+            voter_id: &voter_id,
+            index,
+            amount: locking_position.amount.into(),
+            voting_power: voting_power.into(),
+        }
+        .emit();
+        locking_position.unlocking_started_at = Some(get_current_epoch_millis());
+        voter.locking_positions.replace(index, &locking_position);
+        self.internal_sub_locked_mpdao(&voter_id, locking_position.amount);
+        self.internal_add_unbonding_mpdao(&voter_id, &locking_position, locking_position.amount);
+        // with the Decay model the unlocking position keeps its voting power
+        self.internal_sync_decaying_voting_power(&voter_id, &mut voter);
         assert!(
             voter.available_voting_power >= voting_power,
             "Not enough free voting power to unlock! You have {}, required {}.",
             voter.available_voting_power,
             voting_power
         );
-
-        log!(
-            "UNLOCK: {} unlocked position {}.",
-            &voter_id.to_string(),
-            index
-        );
-        locking_position.unlocking_started_at = Some(get_current_epoch_millis());
-        voter.locking_positions.replace(index, &locking_position);
         voter.available_voting_power -= voting_power;
         self.total_voting_power = self.total_voting_power.saturating_sub(voting_power);
+        self.internal_checkpoint_voting_power(&voter_id, &voter);
         self.voters.insert(&voter_id, &voter);
     }
 
     pub fn unlock_partial_position(&mut self, index: PositionIndex, amount: U128String) {
         let voter_id = env::predecessor_account_id().as_str().to_string();
+        self.internal_ensure_voter_checkpoints(&voter_id);
         let mut voter = self.internal_get_voter_or_panic(&voter_id);
         let mut locking_position = voter.get_position(index);
 
@@ -272,27 +495,35 @@ impl MetaVoteContract {
             self.min_deposit_amount
         );
         assert_at_least_1_mpdao(amount);
-        let remove_voting_power = utils::calculate_voting_power(amount, locking_period);
-        assert!(
-            locking_position.voting_power >= remove_voting_power,
-            "Not enough free voting power to unlock! Locking position has {}, required {}.",
+        let initial_storage_usage = env::storage_usage();
+        // the position keeps the voting power it was locked with, even if the curve changed
+        let remove_voting_power = proportional(
             locking_position.voting_power,
-            remove_voting_power
+            amount,
+            locking_position.amount,
         );
+
+        // Create a NEW unlocking position
+        self.create_unlocking_position(&mut voter, amount, locking_period, remove_voting_power);
+        let unlocking_position = voter.get_position(voter.locking_positions.len() - 1);
+        self.internal_sub_locked_mpdao(&voter_id, amount);
+        self.internal_add_unbonding_mpdao(&voter_id, &unlocking_position, amount);
+        // with the Decay model the unlocking position keeps its voting power
+        self.internal_sync_decaying_voting_power(&voter_id, &mut voter);
         assert!(
             voter.available_voting_power >= remove_voting_power,
             "Not enough free voting power to unlock! You have {}, required {}.",
             voter.available_voting_power,
             remove_voting_power
         );
-
-        log!(
-            "UNLOCK: {} partially unlocked position {}.",
-            &voter_id.to_string(),
-            index
-        );
-        // Create a NEW unlocking position
-        self.create_unlocking_position(&mut voter, amount, locking_period, remove_voting_power);
+        Event::PartialUnlock {
+            voter_id: &voter_id,
+            index,
+            unlocking_index: voter.locking_positions.len() - 1,
+            amount: amount.into(),
+            voting_power: remove_voting_power.into(),
+        }
+        .emit();
 
         // Decrease current locking position
         locking_position.voting_power -= remove_voting_power;
@@ -302,7 +533,9 @@ impl MetaVoteContract {
 
         voter.available_voting_power -= remove_voting_power;
         self.total_voting_power = self.total_voting_power.saturating_sub(remove_voting_power);
+        self.internal_checkpoint_voting_power(&voter_id, &voter);
         self.voters.insert(&voter_id, &voter);
+        self.internal_charge_storage(&voter_id, initial_storage_usage);
     }
 
     // ********************************
@@ -311,6 +544,7 @@ impl MetaVoteContract {
 
     pub fn locking_position_extend_days(&mut self, index: PositionIndex, new_locking_period: Days) {
         let voter_id = env::predecessor_account_id().as_str().to_string();
+        self.internal_ensure_voter_checkpoints(&voter_id);
         let mut voter = self.internal_get_voter_or_panic(&voter_id);
         let mut locking_position = voter.get_position(index);
 
@@ -324,16 +558,19 @@ impl MetaVoteContract {
             "new auto-lock period should be greater than previous one"
         );
 
-        log!(
-            "EXTEND-TIME: {} position #{} {} days",
-            &voter_id.to_string(),
-            index,
-            new_locking_period
-        );
-
         let old_voting_power = locking_position.voting_power;
         let new_voting_power =
-            utils::calculate_voting_power(locking_position.amount, new_locking_period);
+            self.internal_calculate_voting_power(locking_position.amount, new_locking_period);
+
+        Event::Extend {
+            voter_id: &voter_id,
+            index,
+            prev_locking_period: locking_position.locking_period,
+            locking_period: new_locking_period,
+            prev_voting_power: old_voting_power.into(),
+            voting_power: new_voting_power.into(),
+        }
+        .emit();
 
         // update to new total-voting-power (add delta)
         self.total_voting_power += new_voting_power - old_voting_power;
@@ -347,6 +584,7 @@ impl MetaVoteContract {
 
         // save
         voter.locking_positions.replace(index, &locking_position);
+        self.internal_checkpoint_voting_power(&voter_id, &voter);
         self.voters.insert(&voter_id, &voter);
     }
 
@@ -361,6 +599,7 @@ impl MetaVoteContract {
         amount_from_balance: U128String,
     ) {
         let voter_id = env::predecessor_account_id().as_str().to_string();
+        self.internal_ensure_voter_checkpoints(&voter_id);
         let mut voter = self.internal_get_voter_or_panic(&voter_id);
         let locking_position = voter.get_position(index);
 
@@ -392,14 +631,19 @@ impl MetaVoteContract {
             );
         }
 
-        log!(
-            "RELOCK: {} relocked position {}.",
-            &voter_id.to_string(),
-            index
-        );
+        Event::Relock {
+            voter_id: &voter_id,
+            index: Some(index),
+            amount_from_position: locking_position.amount.into(),
+            amount_from_balance: amount_from_balance.into(),
+            locking_period,
+        }
+        .emit();
         let amount = locking_position.amount + amount_from_balance;
         voter.remove_position(index);
         voter.balance -= amount_from_balance;
+        self.internal_sub_unbonding_mpdao(&voter_id, &locking_position, locking_position.amount);
+        self.internal_sub_balance_mpdao(&voter_id, amount_from_balance);
         self.deposit_locking_position(amount, locking_period, &voter_id, &mut voter);
     }
 
@@ -411,6 +655,7 @@ impl MetaVoteContract {
         amount_from_balance: U128String,
     ) {
         let voter_id = env::predecessor_account_id().as_str().to_string();
+        self.internal_ensure_voter_checkpoints(&voter_id);
         let mut voter = self.internal_get_voter_or_panic(&voter_id);
         let mut locking_position = voter.get_position(index);
 
@@ -464,24 +709,35 @@ impl MetaVoteContract {
 
             locking_position.amount = new_amount;
             locking_position.voting_power =
-                utils::calculate_voting_power(new_amount, locking_position.locking_period);
+                self.internal_calculate_voting_power(new_amount, locking_position.locking_period);
             voter.locking_positions.replace(index, &locking_position);
+            self.internal_sub_unbonding_mpdao(&voter_id, &locking_position, amount_from_position);
         } else {
             voter.balance += locking_position.amount - amount_from_position;
             voter.remove_position(index);
+            self.internal_sub_unbonding_mpdao(&voter_id, &locking_position, locking_position.amount);
+            self.internal_add_balance_mpdao(
+                &voter_id,
+                locking_position.amount - amount_from_position,
+            );
         }
 
-        log!(
-            "RELOCK: {} partially relocked position {}.",
-            &voter_id.to_string(),
-            index
-        );
+        Event::Relock {
+            voter_id: &voter_id,
+            index: Some(index),
+            amount_from_position: amount_from_position.into(),
+            amount_from_balance: amount_from_balance.into(),
+            locking_period,
+        }
+        .emit();
         voter.balance -= amount_from_balance;
+        self.internal_sub_balance_mpdao(&voter_id, amount_from_balance);
         self.deposit_locking_position(amount, locking_period, &voter_id, &mut voter);
     }
 
     pub fn relock_from_balance(&mut self, locking_period: Days, amount_from_balance: U128String) {
         let voter_id = env::predecessor_account_id().as_str().to_string();
+        self.internal_ensure_voter_checkpoints(&voter_id);
         let mut voter = self.internal_get_voter_or_panic(&voter_id);
 
         let amount = amount_from_balance.0;
@@ -497,8 +753,16 @@ impl MetaVoteContract {
             self.min_deposit_amount
         );
 
-        log!("RELOCK: {} relocked position.", &voter_id.to_string());
+        Event::Relock {
+            voter_id: &voter_id,
+            index: None,
+            amount_from_position: 0.into(),
+            amount_from_balance: amount.into(),
+            locking_period,
+        }
+        .emit();
         voter.balance -= amount;
+        self.internal_sub_balance_mpdao(&voter_id, amount);
         self.deposit_locking_position(amount, locking_period, &voter_id, &mut voter);
     }
 
@@ -511,8 +775,11 @@ impl MetaVoteContract {
     pub fn clear_locking_position(&mut self, position_index_list: Vec<PositionIndex>) {
         require!(position_index_list.len() > 0, "Index list is empty.");
         let voter_id = env::predecessor_account_id().as_str().to_string();
+        self.internal_ensure_voter_checkpoints(&voter_id);
         let mut voter = self.internal_get_voter_or_panic(&voter_id);
-        voter.clear_fully_unlocked_positions(position_index_list);
+        let cleared_positions = voter.clear_fully_unlocked_positions(&voter_id, position_index_list);
+        self.internal_cleared_positions(&voter_id, &cleared_positions);
+        self.internal_sync_voting_power(&voter_id, &mut voter);
         self.voters.insert(&voter_id, &voter);
     }
 
@@ -541,7 +808,9 @@ impl MetaVoteContract {
         contract_address: ContractAddress,
         votable_object_id: VotableObjId,
     ) {
+        let initial_storage_usage = env::storage_usage();
         let mut voter = self.internal_get_voter_or_panic(&voter_id);
+        self.internal_sync_voting_power(voter_id, &mut voter);
         let voting_power = u128::from(voting_power);
 
         self.internal_create_voting_position(
@@ -554,14 +823,7 @@ impl MetaVoteContract {
 
         // save voter info
         self.voters.insert(&voter_id, &voter);
-
-        log!(
-            "VOTE: {} gave {} votes for object {} at address {}.",
-            &voter_id,
-            voting_power.to_string(),
-            &votable_object_id,
-            contract_address.as_str()
-        );
+        self.internal_charge_storage(voter_id, initial_storage_usage);
     }
 
     fn internal_create_voting_position(
@@ -584,6 +846,7 @@ impl MetaVoteContract {
             self.max_voting_positions
         );
 
+        self.internal_snapshot_incentive_votes(voter_id, voter, contract_address, votable_object_id);
         let mut votes_for_address =
             voter.get_vote_position_for_address(&voter_id, &contract_address);
         let mut votes = votes_for_address.get(&votable_object_id).unwrap_or(0_u128);
@@ -597,6 +860,14 @@ impl MetaVoteContract {
 
         // Update Meta Vote state.
         self.internal_increase_total_votes(voting_power, &contract_address, &votable_object_id);
+
+        Event::Vote {
+            voter_id,
+            contract_address,
+            votable_object_id,
+            voting_power: voting_power.into(),
+        }
+        .emit();
     }
 
     pub fn rebalance(
@@ -607,6 +878,7 @@ impl MetaVoteContract {
     ) {
         let voter_id = env::predecessor_account_id().to_string();
         let mut voter = self.internal_get_voter_or_panic(&voter_id);
+        self.internal_sync_voting_power(&voter_id, &mut voter);
         let voting_power = u128::from(voting_power);
 
         let mut votes_for_address =
@@ -622,6 +894,20 @@ impl MetaVoteContract {
         if voting_power == 0 {
             return self.unvote(contract_address, votable_object_id);
         }
+        Event::Rebalance {
+            voter_id: &voter_id,
+            contract_address: &contract_address,
+            votable_object_id: &votable_object_id,
+            prev_voting_power: votes.into(),
+            voting_power: voting_power.into(),
+        }
+        .emit();
+        self.internal_snapshot_incentive_votes(
+            &voter_id,
+            &voter,
+            &contract_address,
+            &votable_object_id,
+        );
 
         if votes < voting_power {
             // Increase votes.
@@ -635,14 +921,6 @@ impl MetaVoteContract {
             voter.available_voting_power -= additional_votes;
             votes += additional_votes;
 
-            log!(
-                "VOTE: {} increased to {} votes for object {} at address {}.",
-                &voter_id,
-                voting_power.to_string(),
-                &votable_object_id,
-                contract_address.as_str()
-            );
-
             self.internal_increase_total_votes(
                 additional_votes,
                 &contract_address,
@@ -654,14 +932,6 @@ impl MetaVoteContract {
             voter.available_voting_power += remove_votes;
             votes -= remove_votes;
 
-            log!(
-                "VOTE: {} decreased to {} votes for object {} at address {}.",
-                &voter_id,
-                voting_power.to_string(),
-                &votable_object_id,
-                contract_address.as_str()
-            );
-
             self.state_internal_decrease_total_votes_for_address(
                 remove_votes,
                 &contract_address,
@@ -682,6 +952,7 @@ impl MetaVoteContract {
         contract_address: &ContractAddress,
         votable_object_id: &VotableObjId,
     ) {
+        self.internal_snapshot_incentive_votes(voter_id, voter, contract_address, votable_object_id);
         // update this voter struct
         let mut user_votes_for_app =
             voter.get_vote_position_for_address(&voter_id, &contract_address);
@@ -706,12 +977,39 @@ impl MetaVoteContract {
             &votable_object_id,
         );
 
-        log!(
-            "UNVOTE: {} unvoted object {} at address {}.",
-            &voter_id,
-            &votable_object_id,
-            contract_address.as_str()
-        );
+        Event::Unvote {
+            voter_id,
+            contract_address,
+            votable_object_id,
+            voting_power: user_vote_for_object.into(),
+        }
+        .emit();
+    }
+
+    /// Removes vote positions (first ones first) while the voter used voting power
+    /// is greater than max_used_voting_power.
+    fn internal_unvote_until(
+        &mut self,
+        voter_id: &String,
+        voter: &mut Voter,
+        max_used_voting_power: u128,
+    ) {
+        let mut used_voting_power = voter.sum_used_votes();
+        while used_voting_power > max_used_voting_power {
+            let first_voted_app_key: String = voter.vote_positions.keys_as_vector().get(0).unwrap();
+            let first_voted_app_data = voter.vote_positions.get(&first_voted_app_key).unwrap();
+            let first_voted_object_key = first_voted_app_data.keys_as_vector().get(0).unwrap();
+            let used_voting_power_to_remove =
+                first_voted_app_data.get(&first_voted_object_key).unwrap();
+            // this fn manages all other accumulators that need to be updated when removing votes
+            self.internal_remove_voting_position(
+                voter_id,
+                voter,
+                &first_voted_app_key,
+                &first_voted_object_key,
+            );
+            used_voting_power -= used_voting_power_to_remove;
+        }
     }
 
     pub fn unvote(&mut self, contract_address: ContractAddress, votable_object_id: VotableObjId) {
@@ -740,6 +1038,7 @@ impl MetaVoteContract {
             }
         }
 
+        let initial_storage_usage = env::storage_usage();
         let mut voter = self.internal_get_voter_or_panic(&voter_id);
         self.internal_remove_voting_position(
             &voter_id,
@@ -749,6 +1048,7 @@ impl MetaVoteContract {
         );
         // save voter
         self.voters.insert(&voter_id, &voter);
+        self.internal_charge_storage(voter_id, initial_storage_usage);
     }
 
     // *********
@@ -806,6 +1106,7 @@ impl MetaVoteContract {
             env::predecessor_account_id().to_string() == self.prev_governance_contract,
             "Only the old gov contract can call this function."
         );
+        self.internal_ensure_voter_checkpoints(voter_id);
         let mut voter = self.internal_get_voter(&voter_id);
         // create locking positions
         for lp in &locking_positions {
@@ -838,41 +1139,33 @@ impl MetaVoteContract {
     ) {
         self.assert_operator();
         // external mirrored addresses are in the form of [address].evmp.near
-        // example for an eth based address: eth.f1552d1d7CD279A7B766F431c5FaC49A2fb6e361.evmp.near
+        // example for an eth based address: 0xf1552d1d7CD279A7B766F431c5FaC49A2fb6e361.evmp.near
         // evmp.near is controlled by the dao. No external user can create a xxx.evmp.near account
-        let voter_id = utils::pseudo_near_address(&external_address);
+        // the same pseudo account as the evm delegations, in any case
+        let voter_id = self.internal_evm_voter_id(&external_address);
+        self.internal_ensure_voter_checkpoints(&voter_id);
         let mut voter = self.internal_get_voter(&voter_id);
 
         // HANDLE VOTING POWER
-        let mut used_voting_power = voter.sum_used_votes();
-        let prev_voting_power = voter.available_voting_power + used_voting_power;
+        // voting power delegated to this address is kept
+        let delegated_in = self.internal_delegated_in(&voter_id);
+        let used_voting_power = voter.sum_used_votes();
+        let prev_voting_power = voter.available_voting_power + used_voting_power - delegated_in;
         // check if the new voting power is enough for all existing votes
         let new_voting_power: u128 = locking_positions
             .iter()
-            .map(|i| calculate_voting_power(i.1 .0, i.0))
+            .map(|i| self.internal_calculate_voting_power(i.1 .0, i.0))
             .sum();
-        log!(
-            "MIRROR: prev_vp {} new_vp {} used_vp {}.",
-            prev_voting_power,
-            new_voting_power,
-            used_voting_power
-        );
-        // while more votes than voting power, remove votes
-        while used_voting_power > new_voting_power {
-            let first_voted_app_key: String = voter.vote_positions.keys_as_vector().get(0).unwrap();
-            let first_voted_app_data = voter.vote_positions.get(&first_voted_app_key).unwrap();
-            let first_voted_object_key = first_voted_app_data.keys_as_vector().get(0).unwrap();
-            let used_voting_power_to_remove =
-                first_voted_app_data.get(&first_voted_object_key).unwrap();
-            // this fn manages all other accumulators that need to be updated when removing votes
-            self.internal_remove_voting_position(
-                &voter_id,
-                &mut voter,
-                &first_voted_app_key,
-                &first_voted_object_key,
-            );
-            used_voting_power -= used_voting_power_to_remove;
+        Event::Mirror {
+            voter_id: &voter_id,
+            prev_voting_power: prev_voting_power.into(),
+            voting_power: new_voting_power.into(),
+            used_voting_power: used_voting_power.into(),
         }
+        .emit();
+        // while more votes than voting power, remove votes
+        self.internal_unvote_until(&voter_id, &mut voter, new_voting_power + delegated_in);
+        let used_voting_power = voter.sum_used_votes();
         // HANDLE LOCKING POSITIONS
         // first clear all
         voter.locking_positions.clear();
@@ -886,15 +1179,17 @@ impl MetaVoteContract {
             // amount is in META w/24 decimals, convert to mpDAO w/6 decimals
             let unbond_days = lp.0;
             let mpdao_amount = lp.1 .0;
-            self.internal_create_locking_position(&mut voter, mpdao_amount, unbond_days);
+            self.internal_create_locking_position(&voter_id, &mut voter, mpdao_amount, unbond_days);
         }
 
         // update user available_voting_power (to the amount added, remove the used)
+        voter.available_voting_power += delegated_in;
         voter.available_voting_power -= used_voting_power;
         // also update contract total (new vp was already added, remove old only)
         self.total_voting_power = self.total_voting_power - prev_voting_power;
 
         // save voter
+        self.internal_checkpoint_voting_power(&voter_id, &voter);
         self.voters.insert(&voter_id, &voter);
     }
 }
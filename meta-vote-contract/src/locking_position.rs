@@ -47,6 +47,31 @@ impl LockingPosition {
         }
     }
 
+    /// voting power of the position at timestamp_ms, projected from its current state
+    pub(crate) fn voting_power_at(
+        &self,
+        model: VotingPowerModel,
+        timestamp_ms: EpochMillis,
+    ) -> u128 {
+        match (self.unlocking_started_at, model) {
+            (None, _) => self.voting_power,
+            (Some(_), VotingPowerModel::Linear) => 0,
+            (Some(started_at), VotingPowerModel::Decay) => {
+                let end = started_at + self.locking_period_millis();
+                let timestamp_ms = timestamp_ms.max(started_at);
+                if timestamp_ms >= end {
+                    0
+                } else {
+                    proportional(
+                        self.voting_power,
+                        (end - timestamp_ms).into(),
+                        self.locking_period_millis().into(),
+                    )
+                }
+            }
+        }
+    }
+
     pub(crate) fn to_json(&self, index: Option<PositionIndex>) -> LockingPositionJSON {
         LockingPositionJSON {
             index,
@@ -65,12 +90,13 @@ impl MetaVoteContract {
 
     fn increase_locking_position(
         &mut self,
+        voter_id: &VoterId,
         voter: &mut Voter,
         index: u64,
         mpdao_amount: MpDAOAmount,
         unbond_days: Days,
     ) {
-        let voting_power = utils::calculate_voting_power(mpdao_amount, unbond_days);
+        let voting_power = self.internal_calculate_voting_power(mpdao_amount, unbond_days);
         let mut current_position = voter.get_position(index);
         current_position.amount += mpdao_amount;
         current_position.voting_power += voting_power;
@@ -78,10 +104,20 @@ impl MetaVoteContract {
         voter.locking_positions.replace(index, &current_position);
         voter.available_voting_power += voting_power;
         self.total_voting_power += voting_power;
+        self.internal_add_locked_mpdao(voter_id, mpdao_amount);
+        Event::Lock {
+            voter_id,
+            index,
+            amount: mpdao_amount.into(),
+            locking_period: unbond_days,
+            voting_power: voting_power.into(),
+        }
+        .emit();
     }
 
     pub(crate) fn internal_create_locking_position(
         &mut self,
+        voter_id: &VoterId,
         voter: &mut Voter,
         mpdao_amount: MpDAOAmount,
         unbond_days: Days,
@@ -97,11 +133,35 @@ impl MetaVoteContract {
             "a locking-position for {} days already exists",
             unbond_days
         );
-        let voting_power = utils::calculate_voting_power(mpdao_amount, unbond_days);
+        let voting_power = self.internal_calculate_voting_power(mpdao_amount, unbond_days);
         let locking_position = LockingPosition::new(mpdao_amount, unbond_days, voting_power, None);
         voter.locking_positions.push(&locking_position);
         voter.available_voting_power += voting_power;
         self.total_voting_power += voting_power;
+        self.internal_add_locked_mpdao(voter_id, mpdao_amount);
+        Event::Lock {
+            voter_id,
+            index: voter.locking_positions.len() - 1,
+            amount: mpdao_amount.into(),
+            locking_period: unbond_days,
+            voting_power: voting_power.into(),
+        }
+        .emit();
+    }
+
+    /// True if deposit_locking_position(_, unbond_days, voter_id, voter) does not panic,
+    /// for deposits that cannot be returned to the voter.
+    pub(crate) fn internal_can_deposit_locking_position(
+        &self,
+        unbond_days: Days,
+        voter_id: &VoterId,
+        voter: &Voter,
+    ) -> bool {
+        unbond_days >= self.min_unbond_period
+            && unbond_days <= self.max_unbond_period
+            && (voter.find_locked_position(unbond_days).is_some()
+                || (voter.locking_positions.len() as u8) < self.max_locking_positions)
+            && self.internal_has_storage_available(voter_id, LOCKING_POSITION_STORAGE_BYTES)
     }
 
     pub(crate) fn deposit_locking_position(
@@ -117,17 +177,22 @@ impl MetaVoteContract {
             self.min_unbond_period,
             self.max_unbond_period
         );
+        let initial_storage_usage = env::storage_usage();
 
         match voter.find_locked_position(unbond_days) {
             Some(index) => {
                 // Deposit into existing locking position.
-                self.increase_locking_position(voter, index, mpdao_amount, unbond_days);
+                self.increase_locking_position(voter_id, voter, index, mpdao_amount, unbond_days);
             }
             None => {
-                self.internal_create_locking_position(voter, mpdao_amount, unbond_days);
+                self.internal_create_locking_position(voter_id, voter, mpdao_amount, unbond_days);
             }
         };
+        // unlocking positions could have been relocked
+        self.internal_sync_decaying_voting_power(voter_id, voter);
+        self.internal_checkpoint_voting_power(voter_id, voter);
         self.voters.insert(&voter_id, &voter);
+        self.internal_charge_storage(voter_id, initial_storage_usage);
     }
 
     pub(crate) fn create_unlocking_position(
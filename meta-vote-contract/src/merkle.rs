@@ -0,0 +1,301 @@
+use crate::interface::*;
+use crate::*;
+use near_sdk::near_bindgen;
+use near_sdk::serde::{Deserialize, Serialize};
+
+/// Reward token deposited with the merkle root of the (index, account, amount) leaves,
+/// for lists of accounts too large for `for-claims:`.
+/// Each account claims its leaf with a proof, see utils::merkle_leaf and utils::verify_merkle_proof.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct MerkleDistribution {
+    pub token_id: AccountId,
+    pub merkle_root: [u8; 32],
+    pub amount: u128,
+    pub claimed: u128,
+    /// after this date the claims end and anyone can sweep the unclaimed rewards to the owner
+    pub expiry_ms: EpochMillis,
+    /// merkle_claimed words written, removed when the distribution is swept
+    pub claimed_words: Vector<u64>,
+    /// unclaimed rewards returned, the claimed words are being removed
+    pub swept: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MerkleDistributionJSON {
+    pub distribution_id: u64,
+    pub token_id: String,
+    pub merkle_root: String,
+    pub amount: U128String,
+    pub claimed: U128String,
+    pub expiry_ms: EpochMillis,
+}
+
+/// msg of the `merkle-distribution:` deposit
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MerkleDistributionMsg {
+    /// hex
+    pub merkle_root: String,
+    pub expiry_days: Days,
+}
+
+/// claimed flags are kept in 128 bits words
+const CLAIMED_BITMAP_WORD_BITS: u64 = 128;
+
+impl MerkleDistribution {
+    fn to_json(&self, distribution_id: u64) -> MerkleDistributionJSON {
+        MerkleDistributionJSON {
+            distribution_id,
+            token_id: self.token_id.to_string(),
+            merkle_root: hex_encode(&self.merkle_root),
+            amount: self.amount.into(),
+            claimed: self.claimed.into(),
+            expiry_ms: self.expiry_ms,
+        }
+    }
+}
+
+impl MetaVoteContract {
+    pub(crate) fn internal_deposit_merkle_distribution(
+        &mut self,
+        sender_id: &AccountId,
+        token_id: &AccountId,
+        amount: u128,
+        info: &MerkleDistributionMsg,
+    ) {
+        require!(
+            *sender_id == self.owner_id || *sender_id == self.operator_id,
+            "Only the owner or the operator can deposit merkle distributions"
+        );
+        require!(
+            amount > 0 && info.expiry_days > 0,
+            "Amount and expiry_days must be greater than zero"
+        );
+        // must be a reward token
+        self.internal_reward_unit(token_id);
+        let merkle_root = hex_decode_32(&info.merkle_root).expect("Invalid merkle_root");
+
+        let distribution_id = self.next_merkle_distribution_id;
+        self.next_merkle_distribution_id += 1;
+        let expiry_ms = get_current_epoch_millis() + days_to_millis(info.expiry_days);
+        self.merkle_distributions.insert(
+            &distribution_id,
+            &MerkleDistribution {
+                token_id: token_id.clone(),
+                merkle_root,
+                amount,
+                claimed: 0,
+                expiry_ms,
+                claimed_words: Vector::new(StorageKey::MerkleClaimedWords { distribution_id }),
+                swept: false,
+            },
+        );
+        let unclaimed = self.internal_get_merkle_unclaimed(token_id);
+        self.merkle_unclaimed
+            .insert(token_id.clone(), unclaimed + amount);
+
+        Event::MerkleDistribution {
+            distribution_id,
+            token: token_id.as_str(),
+            merkle_root: &info.merkle_root,
+            amount: amount.into(),
+            expiry_ms,
+        }
+        .emit();
+    }
+
+    fn internal_get_merkle_distribution(&self, distribution_id: u64) -> MerkleDistribution {
+        match self.merkle_distributions.get(&distribution_id) {
+            Some(distribution) if !distribution.swept => distribution,
+            _ => panic!("invalid merkle distribution {}", distribution_id),
+        }
+    }
+
+    fn internal_is_merkle_claimed(&self, distribution_id: u64, index: u64) -> bool {
+        let word = self
+            .merkle_claimed
+            .get(&(distribution_id, index / CLAIMED_BITMAP_WORD_BITS))
+            .copied()
+            .unwrap_or(0);
+        word & (1_u128 << (index % CLAIMED_BITMAP_WORD_BITS)) != 0
+    }
+
+    fn internal_set_merkle_claimed(
+        &mut self,
+        distribution_id: u64,
+        distribution: &mut MerkleDistribution,
+        index: u64,
+    ) {
+        let word_index = index / CLAIMED_BITMAP_WORD_BITS;
+        let key = (distribution_id, word_index);
+        let word = match self.merkle_claimed.get(&key) {
+            Some(word) => *word,
+            None => {
+                distribution.claimed_words.push(&word_index);
+                0
+            }
+        };
+        self.merkle_claimed
+            .insert(key, word | (1_u128 << (index % CLAIMED_BITMAP_WORD_BITS)));
+    }
+
+    /// Removes up to MAX_MERKLE_WORDS_CLEARED_PER_CALL claimed words of the swept distribution,
+    /// and the distribution when there are no more. Returns true if it was removed.
+    fn internal_clear_merkle_claimed(
+        &mut self,
+        distribution_id: u64,
+        distribution: &mut MerkleDistribution,
+    ) -> bool {
+        for _ in 0..MAX_MERKLE_WORDS_CLEARED_PER_CALL {
+            match distribution.claimed_words.pop() {
+                Some(word_index) => {
+                    self.merkle_claimed.remove(&(distribution_id, word_index));
+                }
+                None => break,
+            }
+        }
+        if distribution.claimed_words.is_empty() {
+            self.merkle_distributions.remove(&distribution_id);
+            true
+        } else {
+            self.merkle_distributions.insert(&distribution_id, distribution);
+            false
+        }
+    }
+
+    /// merkle distributions not yet claimed or swept, owed to voters
+    pub(crate) fn internal_get_merkle_unclaimed(&self, token_id: &AccountId) -> u128 {
+        self.merkle_unclaimed.get(token_id).copied().unwrap_or(0)
+    }
+}
+
+#[near_bindgen]
+impl MetaVoteContract {
+    /// Adds the leaf amount to the caller claimable rewards of the distribution token.
+    /// The leaf is (index, caller account, amount), `proof` are the hex sibling hashes.
+    pub fn claim_merkle(
+        &mut self,
+        distribution_id: u64,
+        index: u64,
+        amount: U128String,
+        proof: Vec<String>,
+    ) {
+        let voter_id = env::predecessor_account_id().to_string();
+        let amount = amount.0;
+        let mut distribution = self.internal_get_merkle_distribution(distribution_id);
+        require!(
+            get_current_epoch_millis() < distribution.expiry_ms,
+            "The merkle distribution has expired"
+        );
+        require!(
+            !self.internal_is_merkle_claimed(distribution_id, index),
+            "Already claimed"
+        );
+        let proof: Vec<[u8; 32]> = proof
+            .iter()
+            .map(|node| hex_decode_32(node).expect("Invalid merkle proof"))
+            .collect();
+        require!(
+            verify_merkle_proof(
+                merkle_leaf(index, &voter_id, amount),
+                &proof,
+                &distribution.merkle_root
+            ),
+            "Invalid merkle proof"
+        );
+        require!(
+            distribution.claimed + amount <= distribution.amount,
+            "The merkle distribution has not enough balance"
+        );
+        self.internal_set_merkle_claimed(distribution_id, &mut distribution, index);
+        distribution.claimed += amount;
+        self.merkle_distributions
+            .insert(&distribution_id, &distribution);
+        let token_id = distribution.token_id;
+        let unclaimed = self.internal_get_merkle_unclaimed(&token_id);
+        self.merkle_unclaimed
+            .insert(token_id.clone(), unclaimed - amount);
+        if amount > 0 {
+            self.internal_add_claimable(&token_id, &voter_id, amount);
+            self.internal_add_distributed(&token_id, amount);
+        }
+
+        Event::MerkleClaim {
+            distribution_id,
+            index,
+            voter_id: &voter_id,
+            token: token_id.as_str(),
+            amount: amount.into(),
+        }
+        .emit();
+    }
+
+    /// After the expiry, anyone can remove the distribution.
+    /// The unclaimed rewards are transferred to the owner, and the claimed flags removed
+    /// in batches: call again until it returns true.
+    pub fn sweep_merkle_distribution(&mut self, distribution_id: u64) -> bool {
+        let mut distribution = match self.merkle_distributions.get(&distribution_id) {
+            Some(distribution) => distribution,
+            None => panic!("invalid merkle distribution {}", distribution_id),
+        };
+        if distribution.swept {
+            return self.internal_clear_merkle_claimed(distribution_id, &mut distribution);
+        }
+        require!(
+            get_current_epoch_millis() >= distribution.expiry_ms,
+            "The merkle distribution has not expired"
+        );
+        distribution.swept = true;
+        let unclaimed = distribution.amount - distribution.claimed;
+        let token_id = distribution.token_id.clone();
+        let total_unclaimed = self.internal_get_merkle_unclaimed(&token_id);
+        self.merkle_unclaimed
+            .insert(token_id.clone(), total_unclaimed - unclaimed);
+
+        Event::SweepMerkleDistribution {
+            distribution_id,
+            token: token_id.as_str(),
+            unclaimed: unclaimed.into(),
+        }
+        .emit();
+        if unclaimed > 0 {
+            // if the transfer fails, the owner can withdraw it as excess balance
+            ext_ft::ext(token_id)
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .with_attached_deposit(1)
+                .ft_transfer(self.owner_id.to_string(), unclaimed.into(), None);
+        }
+        self.internal_clear_merkle_claimed(distribution_id, &mut distribution)
+    }
+
+    // --------
+    // view fns
+    // --------
+
+    pub fn get_merkle_distribution(&self, distribution_id: u64) -> Option<MerkleDistributionJSON> {
+        self.merkle_distributions
+            .get(&distribution_id)
+            .filter(|distribution| !distribution.swept)
+            .map(|distribution| distribution.to_json(distribution_id))
+    }
+
+    pub fn get_merkle_distributions(
+        &self,
+        from_index: u32,
+        limit: u32,
+    ) -> Vec<MerkleDistributionJSON> {
+        let keys = self.merkle_distributions.keys_as_vector();
+        let values = self.merkle_distributions.values_as_vector();
+        let until = std::cmp::min(from_index.saturating_add(limit) as u64, keys.len());
+        (from_index as u64..until)
+            .map(|index| (keys.get(index).unwrap(), values.get(index).unwrap()))
+            .filter(|(_, distribution)| !distribution.swept)
+            .map(|(distribution_id, distribution)| distribution.to_json(distribution_id))
+            .collect()
+    }
+
+    pub fn is_merkle_claimed(&self, distribution_id: u64, index: u64) -> bool {
+        self.internal_is_merkle_claimed(distribution_id, index)
+    }
+}
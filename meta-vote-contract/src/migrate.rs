@@ -35,6 +35,10 @@ pub struct OldState {
     pub evm_delegates: UnorderedMap<String, Vec<EvmAddress>>,
     pub evm_pre_delegation: LookupMap<EvmAddress, (String, EvmSignature)>,
     pub evm_delegation_signatures: LookupMap<EvmAddress, (String, EvmSignature)>,
+
+    pub lock_votes_in_end_timestamp_ms: u64,
+    pub lock_votes_in_address: Option<String>,
+    pub lock_votes_in_numeric_id: u16,
 }
 
 #[near_bindgen]
@@ -44,9 +48,15 @@ impl MetaVoteContract {
     pub fn migrate() -> Self {
         // retrieve the current state from the contract
         let old: OldState = env::state_read().expect("failed");
-        // return the new state
+        // mpDAO running totals of the existing voters, see operator_backfill_mpdao_totals
+        let voters_count = old.voters.len();
+        // voting power before the upgrade, as baseline for snapshots
+        let mut total_voting_power_checkpoints =
+            Vector::new(StorageKey::TotalVotingPowerCheckpoints);
+        total_voting_power_checkpoints
+            .push(&VotingPowerCheckpoint::baseline(old.total_voting_power));
         Self {
-            owner_id: old.owner_id,
+            owner_id: old.owner_id.clone(),
             operator_id: old.operator_id,
             voters: old.voters,
             votes: old.votes,
@@ -78,9 +88,52 @@ impl MetaVoteContract {
             evm_delegation_signatures: old.evm_delegation_signatures,
             evm_pre_delegation: old.evm_pre_delegation,
 
-            lock_votes_in_end_timestamp_ms: 0,
-            lock_votes_in_address: None,
-            lock_votes_in_numeric_id: 0,
+            lock_votes_in_end_timestamp_ms: old.lock_votes_in_end_timestamp_ms,
+            lock_votes_in_address: old.lock_votes_in_address,
+            lock_votes_in_numeric_id: old.lock_votes_in_numeric_id,
+            voter_checkpoints: LookupMap::new(StorageKey::VoterCheckpoints),
+            total_voting_power_checkpoints,
+            delegated_to: UnorderedMap::new(StorageKey::DelegatedTo),
+            delegated_from: UnorderedMap::new(StorageKey::DelegatedFrom),
+            evm_delegation_nonces: LookupMap::new(StorageKey::EvmDelegationNonces),
+            voting_power_model: VotingPowerModel::Linear,
+            decaying_voting_power: LookupMap::new(StorageKey::DecayingVotingPower),
+            voting_power_curve: Vec::new(),
+            storage_accounts: LookupMap::new(StorageKey::StorageAccounts),
+            storage_deposit_required: false,
+            total_locked_mpdao: 0,
+            total_unbonding_mpdao: 0,
+            total_balance_mpdao: 0,
+            unbonding_schedule: UnorderedMap::new(StorageKey::UnbondingSchedule),
+            mpdao_totals_backfill_index: Some(voters_count),
+            mpdao_counted_voters: LookupSet::new(StorageKey::MpdaoCountedVoters),
+            mpdao_in_transit: 0,
+            stnear_in_transit: 0,
+            owner_withdraw_in_transit: None,
+            reward_tokens: UnorderedMap::new(StorageKey::RewardTokens),
+            reward_streams: UnorderedMap::new(StorageKey::RewardStreams),
+            stream_rewards_paid: LookupMap::new(StorageKey::StreamRewardsPaid),
+            rewards_total_voting_power: old.total_voting_power,
+            incentive_pools: UnorderedMap::new(StorageKey::IncentivePools),
+            next_incentive_pool_id: 0,
+            object_incentive_pools: LookupMap::new(StorageKey::ObjectIncentivePools),
+            incentive_votes: LookupMap::new(StorageKey::IncentiveVotes),
+            incentive_pools_unclaimed: LookupMap::new(StorageKey::IncentivePoolsUnclaimed),
+            merkle_distributions: UnorderedMap::new(StorageKey::MerkleDistributions),
+            next_merkle_distribution_id: 0,
+            merkle_claimed: LookupMap::new(StorageKey::MerkleClaimed),
+            merkle_unclaimed: LookupMap::new(StorageKey::MerkleUnclaimed),
+            claim_batches: UnorderedMap::new(StorageKey::ClaimBatches),
+            next_claim_batch_id: 0,
+            voter_claim_batches: LookupMap::new(StorageKey::VoterClaimBatches),
+            treasury_id: old.owner_id,
+            swap_contract_id: None,
+            pending_swaps: UnorderedMap::new(StorageKey::PendingSwaps),
+            next_swap_id: 0,
+            auto_compound: UnorderedMap::new(StorageKey::AutoCompound),
+            early_withdraw_penalty_bps: 0,
+            early_withdraw_penalty_destination: PenaltyDestination::Burn,
+            total_burned_mpdao: 0,
         }
     }
 }
@@ -0,0 +1,84 @@
+use crate::*;
+use near_sdk::near_bindgen;
+use near_sdk::serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RecalculationProgressJSON {
+    /// index to use as from_index in the next call
+    pub next_index: u32,
+    pub total_voters: u32,
+    /// voters whose voting power changed in this batch
+    pub updated_voters: u32,
+    pub done: bool,
+}
+
+impl MetaVoteContract {
+    /// Recomputes the voting power of every locking position of the voter with the current formula.
+    /// Returns true if the voter voting power changed.
+    fn internal_recalculate_voter(&mut self, voter_id: &VoterId) -> bool {
+        self.internal_ensure_voter_checkpoints(voter_id);
+        let mut voter = self.internal_get_voter_or_panic(voter_id);
+        let prev_voting_power = voter.sum_locked_voting_power();
+        let mut changed = false;
+        for index in 0..voter.locking_positions.len() {
+            let mut locking_position = voter.get_position(index);
+            let voting_power = self
+                .internal_calculate_voting_power(locking_position.amount, locking_position.locking_period);
+            if voting_power != locking_position.voting_power {
+                locking_position.voting_power = voting_power;
+                voter.locking_positions.replace(index, &locking_position);
+                changed = true;
+            }
+        }
+        if !changed {
+            return false;
+        }
+        let voting_power = voter.sum_locked_voting_power();
+        Event::Recalculate {
+            voter_id,
+            prev_voting_power: prev_voting_power.into(),
+            voting_power: voting_power.into(),
+        }
+        .emit();
+        self.internal_update_voting_power(voter_id, &mut voter, prev_voting_power, voting_power);
+        // unlocking positions, with the Decay model
+        self.internal_sync_decaying_voting_power(voter_id, &mut voter);
+        self.internal_checkpoint_voting_power(voter_id, &voter);
+        self.voters.insert(voter_id, &voter);
+        true
+    }
+}
+
+#[near_bindgen]
+impl MetaVoteContract {
+    /// Recomputes the voting power of existing locking positions after the formula changed
+    /// (see set_voting_power_curve), in batches of `limit` voters starting at `from_index`.
+    /// Votes exceeding the new voting power are removed, as in operator_mirror_lps.
+    /// Call again with the returned next_index until done.
+    pub fn operator_recalculate_voting_power(
+        &mut self,
+        from_index: u32,
+        limit: u32,
+    ) -> RecalculationProgressJSON {
+        self.assert_operator();
+        let keys = self.voters.keys_as_vector();
+        let total_voters = keys.len() as u32;
+        let next_index = std::cmp::min(from_index.saturating_add(limit), total_voters);
+        let voter_ids: Vec<VoterId> = (from_index..next_index)
+            .map(|index| keys.get(index as u64).unwrap())
+            .collect();
+        let mut updated_voters = 0;
+        for voter_id in voter_ids {
+            if self.internal_recalculate_voter(&voter_id) {
+                updated_voters += 1;
+            }
+        }
+        RecalculationProgressJSON {
+            next_index,
+            total_voters,
+            updated_voters,
+            done: next_index >= total_voters,
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use crate::checkpoint::push_checkpoint;
+use crate::*;
+use near_sdk::near_bindgen;
+
+// ****************************************
+// * Owner repair of broken accounting    *
+// * use check_invariants to find issues  *
+// ****************************************
+
+#[near_bindgen]
+impl MetaVoteContract {
+    /// Recomputes the voter available voting power from the locking positions,
+    /// the delegations and the vote positions.
+    /// If the votes exceed the voter voting power they are removed, so fix the
+    /// `votes` of the voted objects first (repair_votes).
+    #[payable]
+    pub fn repair_voter_voting_power(&mut self, voter_id: VoterId) {
+        assert_one_yocto();
+        self.assert_only_owner();
+        let mut voter = self.internal_get_voter_or_panic(&voter_id);
+        self.internal_sync_voting_power(&voter_id, &mut voter);
+        let prev_available_voting_power = voter.available_voting_power;
+
+        let delegated_out: u128 = self
+            .delegated_to
+            .get(&voter_id)
+            .map_or(0, |list| list.iter().map(|(_, amount)| amount).sum());
+        let voting_power = (self.internal_get_counted_voting_power(&voter_id, &voter)
+            + self.internal_delegated_in(&voter_id))
+        .saturating_sub(delegated_out);
+        self.internal_unvote_until(&voter_id, &mut voter, voting_power);
+        voter.available_voting_power = voting_power - voter.sum_used_votes();
+
+        Event::RepairVoter {
+            voter_id: &voter_id,
+            prev_available_voting_power: prev_available_voting_power.into(),
+            available_voting_power: voter.available_voting_power.into(),
+        }
+        .emit();
+        self.voters.insert(&voter_id, &voter);
+    }
+
+    /// Sets the total votes of a votable object, for example to the sum of the
+    /// voters vote positions reported by check_invariants.
+    #[payable]
+    pub fn repair_votes(
+        &mut self,
+        contract_address: ContractAddress,
+        votable_object_id: VotableObjId,
+        votes: U128String,
+    ) {
+        assert_one_yocto();
+        self.assert_only_owner();
+        let prev_votes = self
+            .get_total_votes(contract_address.clone(), votable_object_id.clone())
+            .0;
+        let mut votes_for_address = self.internal_get_total_votes_for_address(&contract_address);
+        if votes.0 == 0 {
+            votes_for_address.remove(&votable_object_id);
+        } else {
+            votes_for_address.insert(&votable_object_id, &votes.0);
+        }
+        if votes_for_address.is_empty() {
+            self.votes.remove(&contract_address);
+        } else {
+            self.votes.insert(&contract_address, &votes_for_address);
+        }
+
+        Event::RepairVotes {
+            contract_address: &contract_address,
+            votable_object_id: &votable_object_id,
+            prev_votes: prev_votes.into(),
+            votes,
+        }
+        .emit();
+    }
+
+    /// Sets total_voting_power, for example to the sum of the voters voting power
+    /// reported by check_invariants.
+    #[payable]
+    pub fn repair_total_voting_power(&mut self, total_voting_power: U128String) {
+        assert_one_yocto();
+        self.assert_only_owner();
+        Event::RepairTotalVotingPower {
+            prev_total_voting_power: self.total_voting_power.into(),
+            total_voting_power,
+        }
+        .emit();
+        // streamed rewards up to now are for the previous total
+        self.internal_update_reward_streams();
+        self.total_voting_power = total_voting_power.0;
+        self.rewards_total_voting_power = self.total_voting_power;
+        push_checkpoint(
+            &mut self.total_voting_power_checkpoints,
+            VotingPowerCheckpoint::now(self.total_voting_power),
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use crate::interface::*;
+use crate::*;
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, near_bindgen, Promise, PromiseResult};
+
+/// NEP-141 token whitelisted by the owner to be distributed for claims.
+/// mpDAO and stNEAR are built-in reward tokens, with their own claimable maps.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct RewardToken {
+    /// amounts in the `for-claims:` list are multiplied by unit
+    pub unit: u128,
+    pub claimable: UnorderedMap<VoterId, u128>,
+    pub accum_distributed: u128, // accumulated total distributed
+    pub total_unclaimed: u128,   // currently unclaimed
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RewardTokenJSON {
+    pub token_id: String,
+    pub unit: U128String,
+    pub accum_distributed: U128String,
+    pub total_unclaimed: U128String,
+}
+
+impl MetaVoteContract {
+    fn internal_get_reward_token(&self, token_id: &AccountId) -> RewardToken {
+        self.reward_tokens
+            .get(token_id)
+            .unwrap_or_else(|| panic!("Unknown token address: {}", token_id))
+    }
+
+    pub(crate) fn internal_reward_unit(&self, token_id: &AccountId) -> u128 {
+        if *token_id == self.mpdao_token_contract_address {
+            // mpDAO has 6 decimals, amounts are integer mpDAO
+            ONE_MPDAO
+        } else if *token_id == self.stnear_token_contract_address {
+            // amounts are stNEAR * 1e4 (4 decimal places)
+            E20
+        } else {
+            self.internal_get_reward_token(token_id).unit
+        }
+    }
+
+    pub(crate) fn internal_get_claimable(&self, token_id: &AccountId, account: &VoterId) -> u128 {
+        if *token_id == self.mpdao_token_contract_address {
+            self.claimable_mpdao.get(account).unwrap_or_default()
+        } else if *token_id == self.stnear_token_contract_address {
+            self.claimable_stnear.get(account).unwrap_or_default()
+        } else {
+            self.reward_tokens
+                .get(token_id)
+                .and_then(|reward_token| reward_token.claimable.get(account))
+                .unwrap_or_default()
+        }
+    }
+
+    pub(crate) fn internal_add_claimable(
+        &mut self,
+        token_id: &AccountId,
+        account: &VoterId,
+        amount: u128,
+    ) {
+        if *token_id == self.mpdao_token_contract_address {
+            self.add_claimable_mpdao(account, amount);
+        } else if *token_id == self.stnear_token_contract_address {
+            self.add_claimable_stnear(account, amount);
+        } else {
+            assert!(amount > 0);
+            let mut reward_token = self.internal_get_reward_token(token_id);
+            Self::add_claimable(
+                &mut reward_token.claimable,
+                &mut reward_token.total_unclaimed,
+                account,
+                amount,
+            );
+            self.reward_tokens.insert(token_id, &reward_token);
+        }
+    }
+
+    /// removes a claimed amount, from the claim batches first (see internal_consume_claim_batches)
+    pub(crate) fn internal_remove_claimable(
+        &mut self,
+        token_id: &AccountId,
+        account: &VoterId,
+        amount: u128,
+    ) -> Vec<ConsumedClaim> {
+        self.internal_sub_claimable(token_id, account, amount);
+        self.internal_consume_claim_batches(token_id, account, amount)
+    }
+
+    pub(crate) fn internal_sub_claimable(
+        &mut self,
+        token_id: &AccountId,
+        account: &VoterId,
+        amount: u128,
+    ) {
+        if *token_id == self.mpdao_token_contract_address {
+            self.remove_claimable_mpdao(account, amount);
+        } else if *token_id == self.stnear_token_contract_address {
+            self.remove_claimable_stnear(account, amount);
+        } else {
+            let mut reward_token = self.internal_get_reward_token(token_id);
+            Self::remove_claimable(
+                &mut reward_token.claimable,
+                &mut reward_token.total_unclaimed,
+                account,
+                amount,
+                token_id.as_str(),
+            );
+            self.reward_tokens.insert(token_id, &reward_token);
+        }
+    }
+
+    pub(crate) fn internal_add_distributed(&mut self, token_id: &AccountId, amount: u128) {
+        if *token_id == self.mpdao_token_contract_address {
+            self.accumulated_mpdao_distributed_for_claims += amount;
+        } else if *token_id == self.stnear_token_contract_address {
+            self.accum_distributed_stnear_for_claims += amount;
+        } else {
+            let mut reward_token = self.internal_get_reward_token(token_id);
+            reward_token.accum_distributed += amount;
+            self.reward_tokens.insert(token_id, &reward_token);
+        }
+    }
+
+    /// removes the claim and transfers it, mpDAO claims can only be locked
+    pub(crate) fn internal_claim(
+        &mut self,
+        token_id: &AccountId,
+        voter_id: &VoterId,
+        receiver_id: &VoterId,
+        amount: u128,
+    ) -> Promise {
+        require!(
+            *token_id != self.mpdao_token_contract_address,
+            "mpDAO claims can only be locked, use claim_and_lock"
+        );
+        require!(amount > 0, "Amount must be greater than zero.");
+        let consumed_claims = self.internal_remove_claimable(token_id, voter_id, amount);
+        if *token_id == self.stnear_token_contract_address {
+            return self.transfer_stnear_to_voter(voter_id, receiver_id, amount, consumed_claims);
+        }
+        Event::Claim {
+            voter_id,
+            receiver_id,
+            token: token_id.as_str(),
+            amount: amount.into(),
+        }
+        .emit();
+        ext_ft::ext(token_id.clone())
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .with_attached_deposit(1)
+            .ft_transfer(receiver_id.clone(), U128::from(amount), None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .after_transfer_reward_callback(
+                        token_id.clone(),
+                        voter_id.clone(),
+                        U128::from(amount),
+                        consumed_claims,
+                    ),
+            )
+    }
+
+    /// reward tokens the voter can claim with `claim`, mpDAO excluded
+    fn internal_transferable_reward_tokens(&self) -> Vec<AccountId> {
+        let mut tokens = vec![self.stnear_token_contract_address.clone()];
+        tokens.extend(self.reward_tokens.keys());
+        tokens
+    }
+}
+
+#[near_bindgen]
+impl MetaVoteContract {
+    // *****************
+    // * Reward tokens *
+    // *****************
+
+    #[payable]
+    pub fn register_reward_token(&mut self, token_id: AccountId, unit: U128String) {
+        assert_one_yocto();
+        self.assert_only_owner();
+        require!(
+            token_id != self.mpdao_token_contract_address
+                && token_id != self.stnear_token_contract_address,
+            "mpDAO and stNEAR are built-in reward tokens"
+        );
+        require!(
+            self.reward_tokens.get(&token_id).is_none(),
+            "Reward token already registered"
+        );
+        require!(
+            (self.reward_tokens.len() as usize) < MAX_REWARD_TOKENS,
+            format!("Cannot exceed {} reward tokens.", MAX_REWARD_TOKENS)
+        );
+        require!(unit.0 > 0, "Unit must be greater than zero.");
+        self.reward_tokens.insert(
+            &token_id,
+            &RewardToken {
+                unit: unit.0,
+                claimable: UnorderedMap::new(StorageKey::RewardTokenClaimable {
+                    hash_id: generate_hash_id(&token_id.to_string()),
+                }),
+                accum_distributed: 0,
+                total_unclaimed: 0,
+            },
+        );
+    }
+
+    /// Only tokens without unclaimed rewards, nor streamed rewards still to be
+    /// released or settled, can be removed.
+    #[payable]
+    pub fn unregister_reward_token(&mut self, token_id: AccountId) {
+        assert_one_yocto();
+        self.assert_only_owner();
+        let reward_token = self.internal_get_reward_token(&token_id);
+        require!(
+            reward_token.total_unclaimed == 0,
+            "The reward token has unclaimed rewards"
+        );
+        require!(
+            self.internal_get_stream_owed(&token_id) == 0,
+            "The reward token has streamed rewards not yet settled"
+        );
+        require!(
+            self.internal_get_incentive_pools_unclaimed(&token_id) == 0,
+            "The reward token has incentive pools not yet claimed or closed"
+        );
+        require!(
+            self.internal_get_merkle_unclaimed(&token_id) == 0,
+            "The reward token has merkle distributions not yet claimed or swept"
+        );
+        self.reward_streams.remove(&token_id);
+        self.reward_tokens.remove(&token_id);
+    }
+
+    /// Claims `amount` of a reward token, mpDAO must be claimed with claim_and_lock.
+    pub fn claim(&mut self, token_id: AccountId, amount: U128String) -> Promise {
+        let voter_id = env::predecessor_account_id().to_string();
+        self.internal_settle_stream_rewards(&voter_id);
+        self.internal_claim(&token_id, &voter_id, &voter_id, amount.0)
+    }
+
+    /// Claims all the reward tokens but mpDAO.
+    pub fn claim_all(&mut self) {
+        let voter_id = env::predecessor_account_id().to_string();
+        self.internal_settle_stream_rewards(&voter_id);
+        let mut claimed = false;
+        for token_id in self.internal_transferable_reward_tokens() {
+            let amount = self.internal_get_claimable(&token_id, &voter_id);
+            if amount > 0 {
+                self.internal_claim(&token_id, &voter_id, &voter_id, amount);
+                claimed = true;
+            }
+        }
+        require!(claimed, "Nothing to claim.");
+    }
+
+    #[private]
+    pub fn after_transfer_reward_callback(
+        &mut self,
+        token_id: AccountId,
+        voter_id: VoterId,
+        amount: U128,
+        consumed_claims: Vec<ConsumedClaim>,
+    ) {
+        let amount = amount.0;
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => {
+                log!("{} WITHDRAW {} {}", voter_id, amount, token_id);
+            }
+            PromiseResult::Failed => {
+                log!(
+                    "FAILED: {} {} not transferred. Recovering {} state.",
+                    amount,
+                    token_id,
+                    voter_id
+                );
+                self.internal_add_claimable(&token_id, &voter_id, amount);
+                self.internal_restore_claim_batches(&token_id, &voter_id, &consumed_claims, amount);
+                Event::ClaimFailed {
+                    voter_id: &voter_id,
+                    token: token_id.as_str(),
+                    amount: amount.into(),
+                }
+                .emit();
+            }
+        };
+    }
+
+    // --------
+    // view fns
+    // --------
+
+    /// all reward tokens, mpDAO and stNEAR first
+    pub fn get_reward_tokens(&self) -> Vec<RewardTokenJSON> {
+        let mut result = vec![
+            RewardTokenJSON {
+                token_id: self.mpdao_token_contract_address.to_string(),
+                unit: ONE_MPDAO.into(),
+                accum_distributed: self.accumulated_mpdao_distributed_for_claims.into(),
+                total_unclaimed: self.total_unclaimed_mpdao.into(),
+            },
+            RewardTokenJSON {
+                token_id: self.stnear_token_contract_address.to_string(),
+                unit: E20.into(),
+                accum_distributed: self.accum_distributed_stnear_for_claims.into(),
+                total_unclaimed: self.total_unclaimed_stnear.into(),
+            },
+        ];
+        for (token_id, reward_token) in self.reward_tokens.iter() {
+            result.push(RewardTokenJSON {
+                token_id: token_id.to_string(),
+                unit: reward_token.unit.into(),
+                accum_distributed: reward_token.accum_distributed.into(),
+                total_unclaimed: reward_token.total_unclaimed.into(),
+            });
+        }
+        result
+    }
+
+    pub fn get_claimable(&self, voter_id: VoterId, token_id: AccountId) -> U128String {
+        self.internal_get_claimable(&token_id, &voter_id).into()
+    }
+
+    /// [(token_id, amount)] of every reward token the voter can claim, mpDAO included
+    pub fn get_claimable_tokens(&self, voter_id: VoterId) -> Vec<(String, U128String)> {
+        let mut tokens = vec![self.mpdao_token_contract_address.clone()];
+        tokens.extend(self.internal_transferable_reward_tokens());
+        tokens
+            .into_iter()
+            .map(|token_id| {
+                let amount = self.internal_get_claimable(&token_id, &voter_id);
+                (token_id.to_string(), amount)
+            })
+            .filter(|(_, amount)| *amount > 0)
+            .map(|(token_id, amount)| (token_id, amount.into()))
+            .collect()
+    }
+
+    /// all claims of a registered reward token
+    pub fn get_reward_token_claims(
+        &self,
+        token_id: AccountId,
+        from_index: u32,
+        limit: u32,
+    ) -> Vec<(String, U128String)> {
+        let reward_token = self.internal_get_reward_token(&token_id);
+        let keys = reward_token.claimable.keys_as_vector();
+        let start = from_index as u64;
+        let limit = limit as u64;
+        (start..std::cmp::min(start + limit, keys.len()))
+            .map(|index| {
+                let voter_id = keys.get(index).unwrap();
+                let amount = reward_token.claimable.get(&voter_id).unwrap();
+                (voter_id, amount.into())
+            })
+            .collect()
+    }
+}
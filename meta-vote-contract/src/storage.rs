@@ -0,0 +1,237 @@
+use crate::*;
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, near_bindgen, Promise, StorageUsage};
+
+/// NEAR deposited by the account for storage, and the storage bytes charged to it.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+pub struct VoterStorage {
+    pub deposit: Balance,
+    pub used_bytes: StorageUsage,
+}
+
+impl VoterStorage {
+    fn used_balance(&self) -> Balance {
+        self.used_bytes as Balance * env::storage_byte_cost()
+    }
+
+    fn available_balance(&self) -> Balance {
+        self.deposit.saturating_sub(self.used_balance())
+    }
+
+    fn to_storage_balance(&self) -> StorageBalance {
+        StorageBalance {
+            total: self.deposit.into(),
+            available: self.available_balance().into(),
+        }
+    }
+}
+
+impl MetaVoteContract {
+    fn min_storage_balance() -> Balance {
+        MIN_VOTER_STORAGE_BYTES as Balance * env::storage_byte_cost()
+    }
+
+    /// Charges to the voter the storage used since `initial_storage_usage` (or refunds it if released).
+    /// Accounts without storage deposit are only charged when `storage_deposit_required`.
+    pub(crate) fn internal_charge_storage(
+        &mut self,
+        voter_id: &VoterId,
+        initial_storage_usage: StorageUsage,
+    ) {
+        self.internal_update_storage(voter_id, initial_storage_usage, true);
+    }
+
+    /// Like `internal_charge_storage`, without checking the deposit. For callbacks and
+    /// internal records that must not fail, the deposit is checked on the next charge.
+    pub(crate) fn internal_record_storage(
+        &mut self,
+        voter_id: &VoterId,
+        initial_storage_usage: StorageUsage,
+    ) {
+        self.internal_update_storage(voter_id, initial_storage_usage, false);
+    }
+
+    fn internal_update_storage(
+        &mut self,
+        voter_id: &VoterId,
+        initial_storage_usage: StorageUsage,
+        check_deposit: bool,
+    ) {
+        let storage = self.storage_accounts.get(voter_id).cloned();
+        if storage.is_none() && !self.storage_deposit_required {
+            return;
+        }
+        let mut storage = storage.unwrap_or_default();
+        let storage_usage = env::storage_usage();
+        if storage_usage > initial_storage_usage {
+            storage.used_bytes += storage_usage - initial_storage_usage;
+        } else {
+            storage.used_bytes = storage
+                .used_bytes
+                .saturating_sub(initial_storage_usage - storage_usage);
+        }
+        if check_deposit && self.storage_deposit_required {
+            assert!(
+                storage.deposit >= storage.used_balance(),
+                "Not enough storage balance. Required {} yoctoNEAR, deposited {}. Use storage_deposit.",
+                storage.used_balance(),
+                storage.deposit
+            );
+        }
+        self.storage_accounts.insert(voter_id.clone(), storage);
+    }
+
+    /// True if the voter can be charged `bytes` more without failing the deposit check.
+    pub(crate) fn internal_has_storage_available(&self, voter_id: &VoterId, bytes: u64) -> bool {
+        if !self.storage_deposit_required {
+            return true;
+        }
+        self.storage_accounts.get(voter_id).is_some_and(|storage| {
+            storage.available_balance() >= bytes as Balance * env::storage_byte_cost()
+        })
+    }
+
+    /// Returns the storage deposit to the account, called when the voter is removed.
+    /// The deposit for the records kept after the voter (voting power checkpoints,
+    /// streamed rewards paid, claim batches) stays with the account.
+    pub(crate) fn internal_refund_storage(&mut self, voter_id: &VoterId) {
+        if let Some(mut storage) = self.storage_accounts.get(voter_id).cloned() {
+            let refund = storage.available_balance();
+            if storage.used_bytes == 0 {
+                self.storage_accounts.remove(voter_id);
+            } else {
+                storage.deposit -= refund;
+                self.storage_accounts.insert(voter_id.clone(), storage);
+            }
+            if refund > 0 {
+                Promise::new(voter_id.parse().unwrap()).transfer(refund);
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl MetaVoteContract {
+    /// When true, creating locking or vote positions requires enough storage deposit.
+    #[payable]
+    pub fn set_storage_deposit_required(&mut self, storage_deposit_required: bool) {
+        assert_one_yocto();
+        self.assert_only_owner();
+        self.storage_deposit_required = storage_deposit_required;
+    }
+
+    pub fn get_storage_deposit_required(&self) -> bool {
+        self.storage_deposit_required
+    }
+}
+
+// ****************************
+// * NEP-145 storage management
+// ****************************
+
+#[near_bindgen]
+impl StorageManagement for MetaVoteContract {
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let amount = env::attached_deposit();
+        let account_id: VoterId = account_id
+            .unwrap_or_else(env::predecessor_account_id)
+            .into();
+        let mut storage = self.storage_accounts.get(&account_id).cloned();
+        let min_balance = Self::min_storage_balance();
+
+        let refund = if registration_only.unwrap_or(false) {
+            if storage.is_some() {
+                amount
+            } else {
+                require!(amount >= min_balance, "The attached deposit is less than the minimum storage balance");
+                storage = Some(VoterStorage {
+                    deposit: min_balance,
+                    used_bytes: 0,
+                });
+                amount - min_balance
+            }
+        } else {
+            let mut current = storage.unwrap_or_default();
+            require!(
+                current.deposit + amount >= min_balance,
+                "The attached deposit is less than the minimum storage balance"
+            );
+            current.deposit += amount;
+            storage = Some(current);
+            0
+        };
+        let storage = storage.unwrap();
+        self.storage_accounts.insert(account_id, storage.clone());
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+        storage.to_storage_balance()
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id: VoterId = env::predecessor_account_id().into();
+        let mut storage = self
+            .storage_accounts
+            .get(&account_id)
+            .cloned()
+            .expect("The account is not registered");
+        let available = storage.available_balance();
+        let amount = amount.map_or(available, |amount| amount.0);
+        assert!(
+            amount <= available,
+            "Not enough available storage balance. You have {}, requested {}.",
+            available,
+            amount
+        );
+        require!(
+            storage.deposit - amount >= Self::min_storage_balance(),
+            "Cannot withdraw the minimum storage balance, use storage_unregister"
+        );
+        storage.deposit -= amount;
+        self.storage_accounts.insert(account_id, storage.clone());
+        if amount > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(amount);
+        }
+        storage.to_storage_balance()
+    }
+
+    /// Only accounts without a voter record can unregister, `force` is not supported.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        require!(!force.unwrap_or(false), "Force unregister is not supported");
+        let account_id: VoterId = env::predecessor_account_id().into();
+        if self.storage_accounts.get(&account_id).is_none() {
+            return false;
+        }
+        require!(
+            self.voters.get(&account_id).is_none(),
+            "Withdraw all your mpDAO before unregistering"
+        );
+        self.internal_refund_storage(&account_id);
+        true
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: Self::min_storage_balance().into(),
+            max: None,
+        }
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_accounts
+            .get(account_id.as_str())
+            .map(|storage| storage.to_storage_balance())
+    }
+}
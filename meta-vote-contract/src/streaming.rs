@@ -0,0 +1,268 @@
+use crate::*;
+use near_sdk::near_bindgen;
+use near_sdk::serde::{Deserialize, Serialize};
+
+/// Reward token deposited to be released linearly until `end_ms` to all the voting power.
+/// Released rewards accumulate in `reward_per_voting_power`, each voter gets
+/// its voting power * the accumulator increase since its last settlement.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct RewardStream {
+    /// rewards not yet released
+    pub remaining: u128,
+    pub last_update_ms: EpochMillis,
+    pub end_ms: EpochMillis,
+    /// accumulated rewards per voting power, scaled by REWARD_PER_VOTING_POWER_PRECISION
+    pub reward_per_voting_power: u128,
+    pub accum_streamed: u128,
+    /// released rewards moved to the voters claimable rewards
+    pub accum_settled: u128,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RewardStreamJSON {
+    pub token_id: String,
+    pub remaining: U128String,
+    pub end_ms: EpochMillis,
+    pub accum_streamed: U128String,
+}
+
+impl RewardStream {
+    /// releases the rewards up to now to `total_voting_power`
+    fn update(&mut self, total_voting_power: u128) {
+        let now = get_current_epoch_millis();
+        if now <= self.last_update_ms {
+            return;
+        }
+        if total_voting_power > 0 && self.remaining > 0 {
+            let until = std::cmp::min(now, self.end_ms);
+            let released = if until >= self.end_ms {
+                self.remaining
+            } else {
+                proportional(
+                    self.remaining,
+                    (until - self.last_update_ms) as u128,
+                    (self.end_ms - self.last_update_ms) as u128,
+                )
+            };
+            self.reward_per_voting_power += (U256::from(released)
+                * U256::from(REWARD_PER_VOTING_POWER_PRECISION)
+                / U256::from(total_voting_power))
+            .as_u128();
+            self.remaining -= released;
+            self.accum_streamed += released;
+        }
+        // without voting power the rewards are kept for the rest of the period
+        self.last_update_ms = now;
+    }
+
+    /// capped at the released rewards not yet settled, with the Decay model the voters
+    /// checkpoints can add up to more than the total voting power the rewards were released to
+    /// until every voter is synced
+    fn earned(&self, voting_power: u128, reward_per_voting_power_paid: u128) -> u128 {
+        let earned = (U256::from(voting_power)
+            * U256::from(self.reward_per_voting_power - reward_per_voting_power_paid)
+            / U256::from(REWARD_PER_VOTING_POWER_PRECISION))
+        .as_u128();
+        std::cmp::min(
+            earned,
+            self.accum_streamed.saturating_sub(self.accum_settled),
+        )
+    }
+
+    fn to_json(&self, token_id: &AccountId) -> RewardStreamJSON {
+        RewardStreamJSON {
+            token_id: token_id.to_string(),
+            remaining: self.remaining.into(),
+            end_ms: self.end_ms,
+            accum_streamed: self.accum_streamed.into(),
+        }
+    }
+}
+
+impl MetaVoteContract {
+    /// Adds rewards to the token stream, released from now until now + days
+    /// (or the current end of the stream if it is later).
+    pub(crate) fn internal_deposit_stream_rewards(
+        &mut self,
+        sender_id: &AccountId,
+        token_id: &AccountId,
+        amount: u128,
+        days: Days,
+    ) {
+        require!(
+            *sender_id == self.owner_id || *sender_id == self.operator_id,
+            "Only the owner or the operator can stream rewards"
+        );
+        require!(amount > 0 && days > 0, "Amount and days must be greater than zero");
+        // must be a reward token
+        self.internal_reward_unit(token_id);
+        let total_voting_power = self.rewards_total_voting_power;
+        let mut stream = self.reward_streams.get(token_id).unwrap_or(RewardStream {
+            remaining: 0,
+            last_update_ms: get_current_epoch_millis(),
+            end_ms: 0,
+            reward_per_voting_power: 0,
+            accum_streamed: 0,
+            accum_settled: 0,
+        });
+        stream.update(total_voting_power);
+        stream.remaining += amount;
+        stream.end_ms = std::cmp::max(
+            stream.end_ms,
+            get_current_epoch_millis() + days_to_millis(days),
+        );
+        Event::StreamRewards {
+            token: token_id.as_str(),
+            amount: amount.into(),
+            end_ms: stream.end_ms,
+        }
+        .emit();
+        self.reward_streams.insert(token_id, &stream);
+    }
+
+    /// Releases `amount` now to the current voting power, settled to the voters claimable rewards.
+    /// Kept for the next release if there is no voting power.
+    pub(crate) fn internal_release_rewards_now(&mut self, token_id: &AccountId, amount: u128) {
+        let total_voting_power = self.rewards_total_voting_power;
+        let now = get_current_epoch_millis();
+        let mut stream = self.reward_streams.get(token_id).unwrap_or(RewardStream {
+            remaining: 0,
+            last_update_ms: now,
+            end_ms: now,
+            reward_per_voting_power: 0,
+            accum_streamed: 0,
+            accum_settled: 0,
+        });
+        stream.update(total_voting_power);
+        if total_voting_power > 0 {
+            stream.reward_per_voting_power += (U256::from(amount)
+                * U256::from(REWARD_PER_VOTING_POWER_PRECISION)
+                / U256::from(total_voting_power))
+            .as_u128();
+            stream.accum_streamed += amount;
+        } else {
+            stream.remaining += amount;
+            stream.end_ms = std::cmp::max(stream.end_ms, now);
+        }
+        self.reward_streams.insert(token_id, &stream);
+    }
+
+    /// voting power of the voter since its last change (its last checkpoint)
+    fn internal_get_last_voting_power(&self, voter_id: &VoterId) -> u128 {
+        match self.voter_checkpoints.get(voter_id) {
+            Some(checkpoints) if !checkpoints.is_empty() => {
+                checkpoints.get(checkpoints.len() - 1).unwrap().voting_power
+            }
+            Some(_) => 0,
+            None => self
+                .voters
+                .get(voter_id)
+                .map_or(0, |voter| self.internal_get_counted_voting_power(voter_id, &voter)),
+        }
+    }
+
+    /// Moves the streamed rewards earned by the voter to its claimable rewards.
+    /// Called before the voter voting power changes (see internal_checkpoint_voting_power) and before claims.
+    pub(crate) fn internal_settle_stream_rewards(&mut self, voter_id: &VoterId) {
+        if self.reward_streams.is_empty() {
+            return;
+        }
+        let voting_power = self.internal_get_last_voting_power(voter_id);
+        let total_voting_power = self.rewards_total_voting_power;
+        let token_ids: Vec<AccountId> = self.reward_streams.keys().collect();
+        for token_id in token_ids {
+            let mut stream = self.reward_streams.get(&token_id).unwrap();
+            stream.update(total_voting_power);
+            let key = (voter_id.clone(), token_id.clone());
+            let paid = self.stream_rewards_paid.get(&key).copied().unwrap_or(0);
+            let earned = stream.earned(voting_power, paid);
+            if stream.reward_per_voting_power != paid {
+                self.stream_rewards_paid.insert(key, stream.reward_per_voting_power);
+            }
+            stream.accum_settled += earned;
+            self.reward_streams.insert(&token_id, &stream);
+            if earned > 0 {
+                self.internal_add_claimable(&token_id, voter_id, earned);
+                self.internal_add_distributed(&token_id, earned);
+            }
+        }
+    }
+
+    /// Releases the streamed rewards up to now, before rewards_total_voting_power changes
+    /// without a voter checkpoint.
+    pub(crate) fn internal_update_reward_streams(&mut self) {
+        let total_voting_power = self.rewards_total_voting_power;
+        let token_ids: Vec<AccountId> = self.reward_streams.keys().collect();
+        for token_id in token_ids {
+            let mut stream = self.reward_streams.get(&token_id).unwrap();
+            stream.update(total_voting_power);
+            self.reward_streams.insert(&token_id, &stream);
+        }
+    }
+
+    /// streamed rewards earned by the voter and not yet settled
+    fn internal_pending_stream_rewards(&self, voter_id: &VoterId, token_id: &AccountId) -> u128 {
+        let mut stream = match self.reward_streams.get(token_id) {
+            Some(stream) => stream,
+            None => return 0,
+        };
+        stream.update(self.rewards_total_voting_power);
+        let paid = self
+            .stream_rewards_paid
+            .get(&(voter_id.clone(), token_id.clone()))
+            .copied()
+            .unwrap_or(0);
+        stream.earned(self.internal_get_last_voting_power(voter_id), paid)
+    }
+
+    /// rewards still to be released or released and not yet settled, owed to voters
+    pub(crate) fn internal_get_stream_owed(&self, token_id: &AccountId) -> u128 {
+        self.reward_streams.get(token_id).map_or(0, |stream| {
+            stream.remaining + stream.accum_streamed - stream.accum_settled
+        })
+    }
+}
+
+#[near_bindgen]
+impl MetaVoteContract {
+    /// Moves the streamed rewards earned by the voter to its claimable rewards, anyone can call it.
+    /// Nothing to settle without voting power, it is settled when the voting power changes.
+    /// The decay of the voter unlocking positions is applied first.
+    pub fn settle_stream_rewards(&mut self, voter_id: VoterId) {
+        if self.internal_get_last_voting_power(&voter_id) == 0 {
+            return;
+        }
+        match self.voters.get(&voter_id) {
+            // the checkpoint settles the rewards with the synced voting power
+            Some(mut voter) => {
+                self.internal_sync_voting_power(&voter_id, &mut voter);
+                self.voters.insert(&voter_id, &voter);
+            }
+            None => self.internal_settle_stream_rewards(&voter_id),
+        }
+    }
+
+    // --------
+    // view fns
+    // --------
+
+    pub fn get_reward_streams(&self) -> Vec<RewardStreamJSON> {
+        self.reward_streams
+            .iter()
+            .map(|(token_id, stream)| stream.to_json(&token_id))
+            .collect()
+    }
+
+    /// [(token_id, amount)] streamed to the voter and not yet claimable, settled on the
+    /// next voting power change or claim
+    pub fn get_pending_stream_rewards(&self, voter_id: VoterId) -> Vec<(String, U128String)> {
+        self.reward_streams
+            .keys()
+            .map(|token_id| {
+                let pending = self.internal_pending_stream_rewards(&voter_id, &token_id);
+                (token_id.to_string(), pending.into())
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,238 @@
+use crate::interface::*;
+use crate::*;
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, near_bindgen, serde_json, PromiseResult};
+
+/// stNEAR claim sent to the swap contract, waiting for the mpDAO to lock
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PendingSwap {
+    pub voter_id: VoterId,
+    pub stnear_amount: u128,
+    pub locking_period: Days,
+    pub min_mpdao_out: u128,
+    /// the stNEAR transfer was resolved, `stnear_amount` is the amount used
+    pub stnear_resolved: bool,
+    pub mpdao_received: bool,
+    /// expiring claims of the stNEAR, restored if it is refunded
+    pub consumed_claims: Vec<ConsumedClaim>,
+}
+
+/// msg of the stNEAR ft_transfer_call to the swap contract.
+/// The swap contract must send at least `min_amount_out` of `token_out` back to the sender
+/// with ft_transfer_call and `msg`, or refund the stNEAR.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SwapRequest {
+    pub token_out: AccountId,
+    pub min_amount_out: U128String,
+    pub msg: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingSwapJSON {
+    pub swap_id: u64,
+    pub voter_id: VoterId,
+    pub stnear_amount: U128String,
+    pub locking_period: Days,
+    pub min_mpdao_out: U128String,
+    pub stnear_resolved: bool,
+    pub mpdao_received: bool,
+}
+
+impl MetaVoteContract {
+    /// mpDAO from the swap contract, msg == "swap-lock:<swap_id>".
+    /// Locked for the voter, or added to its claimable mpDAO if below min_mpdao_out,
+    /// the min deposit amount, or if the voter cannot lock it now (see internal_can_deposit_locking_position).
+    pub(crate) fn internal_receive_swapped_mpdao(
+        &mut self,
+        sender_id: &AccountId,
+        amount: u128,
+        swap_id: u64,
+    ) {
+        require!(
+            env::predecessor_account_id() == self.mpdao_token_contract_address,
+            "Only mpDAO can be received from a swap"
+        );
+        require!(
+            self.swap_contract_id.as_ref() == Some(sender_id),
+            "Only the swap contract can send swapped mpDAO"
+        );
+        let swap = match self.pending_swaps.get(&swap_id) {
+            Some(swap) => swap,
+            None => panic!("invalid swap {}", swap_id),
+        };
+        require!(!swap.mpdao_received, "Swap already received");
+        if swap.stnear_resolved {
+            self.pending_swaps.remove(&swap_id);
+        } else {
+            // removed by the stNEAR transfer callback
+            self.pending_swaps.insert(
+                &swap_id,
+                &PendingSwap {
+                    voter_id: swap.voter_id.clone(),
+                    mpdao_received: true,
+                    ..swap
+                },
+            );
+        }
+        Event::SwapLock {
+            swap_id,
+            voter_id: &swap.voter_id,
+            stnear_amount: swap.stnear_amount.into(),
+            mpdao_amount: amount.into(),
+        }
+        .emit();
+        let mut voter = self.internal_get_voter(&swap.voter_id);
+        if amount < swap.min_mpdao_out
+            || amount < self.min_deposit_amount
+            || !self.internal_can_deposit_locking_position(swap.locking_period, &swap.voter_id, &voter)
+        {
+            self.add_claimable_mpdao(&swap.voter_id, amount);
+            self.accumulated_mpdao_distributed_for_claims += amount;
+        } else {
+            self.internal_ensure_voter_checkpoints(&swap.voter_id);
+            self.deposit_locking_position(amount, swap.locking_period, &swap.voter_id, &mut voter);
+        }
+    }
+}
+
+#[near_bindgen]
+impl MetaVoteContract {
+    #[payable]
+    pub fn set_swap_contract(&mut self, swap_contract_id: Option<AccountId>) {
+        assert_one_yocto();
+        self.assert_only_owner();
+        self.swap_contract_id = swap_contract_id;
+    }
+
+    /// Sends claimable stNEAR to the swap contract, the mpDAO received is locked for
+    /// `locking_period` days. The stNEAR not used by the swap is claimable again.
+    pub fn claim_stnear_and_lock(
+        &mut self,
+        amount: U128String,
+        locking_period: Days,
+        min_mpdao_out: U128String,
+    ) -> Promise {
+        let amount = amount.0;
+        let swap_contract_id = self
+            .swap_contract_id
+            .clone()
+            .expect("No swap contract configured");
+        require!(amount > 0, "Amount must be greater than zero.");
+        require!(
+            locking_period >= self.min_unbond_period && locking_period <= self.max_unbond_period,
+            format!(
+                "Unbound period must be between {} and {} days",
+                self.min_unbond_period, self.max_unbond_period
+            )
+        );
+        self.assert_min_deposit_amount(min_mpdao_out.0);
+        let voter_id = env::predecessor_account_id().to_string();
+        self.internal_settle_stream_rewards(&voter_id);
+        let token_id = self.stnear_token_contract_address.clone();
+        let consumed_claims = self.internal_remove_claimable(&token_id, &voter_id, amount);
+
+        let swap_id = self.next_swap_id;
+        self.next_swap_id += 1;
+        self.pending_swaps.insert(
+            &swap_id,
+            &PendingSwap {
+                voter_id: voter_id.clone(),
+                stnear_amount: amount,
+                locking_period,
+                min_mpdao_out: min_mpdao_out.0,
+                stnear_resolved: false,
+                mpdao_received: false,
+                consumed_claims,
+            },
+        );
+        Event::Claim {
+            voter_id: &voter_id,
+            receiver_id: swap_contract_id.as_str(),
+            token: token_id.as_str(),
+            amount: amount.into(),
+        }
+        .emit();
+        self.stnear_in_transit += amount;
+        let request = SwapRequest {
+            token_out: self.mpdao_token_contract_address.clone(),
+            min_amount_out: min_mpdao_out,
+            msg: format!("swap-lock:{}", swap_id),
+        };
+        ext_ft::ext(token_id)
+            .with_static_gas(GAS_FOR_SWAP)
+            .with_attached_deposit(1)
+            .ft_transfer_call(
+                swap_contract_id,
+                U128::from(amount),
+                None,
+                serde_json::to_string(&request).unwrap(),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_SWAP)
+                    .after_swap_stnear_callback(swap_id, U128::from(amount)),
+            )
+    }
+
+    /// ft_transfer_call returns the stNEAR used by the swap contract, the rest is claimable again
+    #[private]
+    pub fn after_swap_stnear_callback(&mut self, swap_id: u64, amount: U128) -> U128 {
+        let amount = amount.0;
+        self.stnear_in_transit -= amount;
+        let used = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(value) => serde_json::from_slice::<U128>(&value)
+                .map_or(amount, |used| std::cmp::min(used.0, amount)),
+            PromiseResult::Failed => 0,
+        };
+        let mut swap = self.pending_swaps.get(&swap_id).unwrap();
+        let refund = amount - used;
+        if refund > 0 {
+            self.add_claimable_stnear(&swap.voter_id, refund);
+            let token_id = self.stnear_token_contract_address.clone();
+            let voter_id = swap.voter_id.clone();
+            self.internal_restore_claim_batches(&token_id, &voter_id, &swap.consumed_claims, refund);
+            Event::ClaimFailed {
+                voter_id: &swap.voter_id,
+                token: self.stnear_token_contract_address.as_str(),
+                amount: refund.into(),
+            }
+            .emit();
+        }
+        if swap.mpdao_received || used == 0 {
+            // mpDAO already locked, or nothing to wait for
+            self.pending_swaps.remove(&swap_id);
+        } else {
+            // mpDAO not received yet, locked when the swap contract sends it
+            swap.stnear_amount = used;
+            swap.stnear_resolved = true;
+            self.pending_swaps.insert(&swap_id, &swap);
+        }
+        U128::from(used)
+    }
+
+    // --------
+    // view fns
+    // --------
+
+    pub fn get_swap_contract(&self) -> Option<AccountId> {
+        self.swap_contract_id.clone()
+    }
+
+    pub fn get_pending_swap(&self, swap_id: u64) -> Option<PendingSwapJSON> {
+        self.pending_swaps
+            .get(&swap_id)
+            .map(|swap| PendingSwapJSON {
+                swap_id,
+                voter_id: swap.voter_id,
+                stnear_amount: swap.stnear_amount.into(),
+                locking_period: swap.locking_period,
+                min_mpdao_out: swap.min_mpdao_out.into(),
+                stnear_resolved: swap.stnear_resolved,
+                mpdao_received: swap.mpdao_received,
+            })
+    }
+}
@@ -1,5 +1,6 @@
 use super::*;
 use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_contract_standards::storage_management::StorageManagement;
 use near_sdk::json_types::U128;
 use near_sdk::serde_json;
 use near_sdk::testing_env;
@@ -73,6 +74,30 @@ fn test_single_deposit() {
     assert_eq!(contract.total_voting_power, vote_power);
 }
 
+#[test]
+fn test_deposit_emits_events() {
+    let mut contract = setup_new_test();
+
+    let sender_id: AccountId = voter_account();
+    contract.ft_on_transfer(sender_id.clone(), U128::from(2 * E6), "60".to_owned());
+
+    let logs = near_sdk::test_utils::get_logs();
+    assert_eq!(logs.len(), 2);
+    let events: Vec<serde_json::Value> = logs
+        .iter()
+        .map(|log| serde_json::from_str(log.strip_prefix("EVENT_JSON:").unwrap()).unwrap())
+        .collect();
+    assert_eq!(events[0]["standard"], "metavote");
+    assert_eq!(events[0]["version"], "1.1.0");
+    assert_eq!(events[0]["event"], "deposit");
+    assert_eq!(events[0]["data"]["voter_id"], sender_id.as_str());
+    assert_eq!(events[0]["data"]["amount"], (2 * E6).to_string());
+    assert_eq!(events[1]["event"], "lock");
+    assert_eq!(events[1]["data"]["index"], 0);
+    assert_eq!(events[1]["data"]["locking_period"], 60);
+    assert_eq!(events[1]["data"]["voting_power"], (2 * E24).to_string());
+}
+
 #[test]
 fn test_multiple_deposit_same_locking_period() {
     let mut contract = setup_new_test();
@@ -340,12 +365,13 @@ fn test_unlock_partial_position() {
     );
 
     let voter = contract.internal_get_voter(&sender_id.as_str().to_string());
+    // the unlocked voting power is proportional to the position voting power
+    let position_vote_power =
+        calculate_voting_power(MpDAOAmount::from(new_amount), new_msg.parse::<Days>().unwrap());
     let total_vote_power =
         calculate_voting_power(MpDAOAmount::from(amount), msg.parse::<Days>().unwrap())
-            + calculate_voting_power(
-                MpDAOAmount::from(new_amount) - MpDAOAmount::from(third_amount),
-                new_msg.parse::<Days>().unwrap(),
-            );
+            + position_vote_power
+            - crate::utils::proportional(position_vote_power, third_amount.0, new_amount.0);
     assert_eq!(
         voter.available_voting_power, total_vote_power,
         "Voting power was not removed!"
@@ -1417,3 +1443,1491 @@ fn test_transfer_unlock_relock() {
         "res.voting_power Calculation error"
     );
 }
+
+#[test]
+fn test_voting_power_checkpoints() {
+    let mut contract = setup_new_test();
+    let sender_id: AccountId = voter_account();
+    let voter_id = sender_id.as_str().to_string();
+    let t0 = nanos_to_millis(to_ts(GENESIS_TIME_IN_DAYS));
+    let t1 = nanos_to_millis(to_ts(GENESIS_TIME_IN_DAYS + 2));
+    let t2 = nanos_to_millis(to_ts(GENESIS_TIME_IN_DAYS + 4));
+
+    contract.ft_on_transfer(sender_id.clone(), U128::from(2 * E6), "60".to_owned());
+
+    let mut context = get_context(
+        &mpdao_token_account(),
+        ntoy(TEST_INITIAL_BALANCE),
+        0,
+        to_ts(GENESIS_TIME_IN_DAYS + 2),
+    );
+    context.block_index = 2;
+    testing_env!(context);
+    contract.ft_on_transfer(sender_id.clone(), U128::from(3 * E6), "120".to_owned());
+
+    let mut context = get_context(&sender_id, ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + 4));
+    context.block_index = 3;
+    testing_env!(context);
+    contract.unlock_position(0);
+
+    assert_eq!(contract.get_voting_power_at(voter_id.clone(), t0 - 1).0, 0);
+    assert_eq!(contract.get_voting_power_at(voter_id.clone(), t0).0, 2 * E24);
+    assert_eq!(contract.get_voting_power_at(voter_id.clone(), t1 - 1).0, 2 * E24);
+    assert_eq!(contract.get_voting_power_at(voter_id.clone(), t1).0, 8 * E24);
+    assert_eq!(contract.get_voting_power_at(voter_id.clone(), t2).0, 6 * E24);
+    assert_eq!(contract.get_total_voting_power_at(t0).0, 2 * E24);
+    assert_eq!(contract.get_total_voting_power_at(t1 + 1).0, 8 * E24);
+    assert_eq!(contract.get_total_voting_power_at(t2).0, contract.total_voting_power);
+    assert_eq!(contract.get_voting_power_at("nobody.near".into(), t2).0, 0);
+}
+
+#[test]
+fn test_delegate_voting_power() {
+    let mut contract = setup_new_test();
+    let delegator: AccountId = voter_account();
+    let delegate: AccountId = voter_account_id(1);
+    let delegator_id = delegator.as_str().to_string();
+    let delegate_id = delegate.as_str().to_string();
+    let votable = votable_account().as_str().to_string();
+
+    // 6 mpDAO for 60 days => 6 voting power
+    contract.ft_on_transfer(delegator.clone(), U128::from(6 * E6), "60".to_owned());
+
+    set_context_caller(&delegator);
+    contract.delegate_voting_power(delegate.clone(), U128::from(4 * E24));
+    assert_eq!(contract.internal_get_voter(&delegator_id).available_voting_power, 2 * E24);
+    assert_eq!(contract.internal_get_voter(&delegate_id).available_voting_power, 4 * E24);
+    assert_eq!(
+        contract.get_voting_power_delegators(delegate.clone()),
+        vec![(delegator_id.clone(), U128::from(4 * E24))]
+    );
+    assert_eq!(contract.get_delegated_voting_power(delegate.clone()).0, 4 * E24);
+
+    // the delegate votes with the delegated voting power
+    set_context_caller(&delegate);
+    contract.vote(U128::from(3 * E24), votable.clone(), "1".to_owned());
+    assert_eq!(contract.get_total_votes(votable.clone(), "1".to_owned()).0, 3 * E24);
+
+    // partial revoke, the delegate has only 1 free so its vote position is removed
+    set_context_caller(&delegator);
+    contract.undelegate_voting_power(delegate.clone(), Some(U128::from(2 * E24)));
+    assert_eq!(contract.get_total_votes(votable.clone(), "1".to_owned()).0, 0);
+    assert_eq!(contract.internal_get_voter(&delegate_id).available_voting_power, 2 * E24);
+    assert_eq!(contract.internal_get_voter(&delegator_id).available_voting_power, 4 * E24);
+
+    // full revoke removes the delegate voter
+    contract.undelegate_voting_power(delegate.clone(), None);
+    assert!(contract.voters.get(&delegate_id).is_none());
+    assert_eq!(contract.internal_get_voter(&delegator_id).available_voting_power, 6 * E24);
+    assert!(contract.get_voting_power_delegates(delegator.clone()).is_empty());
+    assert_eq!(contract.total_voting_power, 6 * E24);
+
+    // a delegate can not exceed the max delegators, the existing ones can add more
+    let delegators: Vec<(String, u128)> = (0..MAX_DELEGATORS_PER_DELEGATE)
+        .map(|n| (format!("delegator-{}.near", n), E24))
+        .collect();
+    contract.delegated_from.insert(&delegate_id, &delegators);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.delegate_voting_power(delegate.clone(), U128::from(E24));
+    }));
+    assert!(result.is_err());
+    contract.delegated_from.remove(&delegate_id);
+    contract.delegate_voting_power(delegate.clone(), U128::from(E24));
+}
+
+// personal_sign of "delegate to voter.metavote.near"
+const TEST_EVM_ADDRESS: &str = "0x16f66de04c7146643c31bb2e20e78eab3eba3b86";
+const TEST_EVM_SIGNATURE: &str = "0x83ebaa124c32a7af42f12878e84d77379570eb261271c651884ef719c1fbc8425247c2c3a2bbf05140397074045c8de8c74f04462ce96469c99cbdd53e47f91c1c";
+
+#[test]
+fn test_evm_delegation_signature_verified() {
+    let mut contract = setup_new_test();
+    let mut context = get_context(&voter_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context);
+
+    contract.pre_delegate_evm_address(TEST_EVM_ADDRESS.into(), TEST_EVM_SIGNATURE.into());
+    // confirmed without the operator
+    assert!(contract.get_pre_delegate_evm_address(TEST_EVM_ADDRESS.into()).is_none());
+    assert_eq!(
+        contract.get_delegate(TEST_EVM_ADDRESS.into()),
+        Some(voter_account().to_string())
+    );
+    assert_eq!(
+        contract.get_delegating_evm_addresses(voter_account()),
+        vec![crate::utils::normalize_evm_address(TEST_EVM_ADDRESS)]
+    );
+}
+
+#[test]
+fn test_evm_address_keys_in_any_case() {
+    // EIP-55 test vectors
+    for checksummed in [
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+        "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+    ] {
+        assert_eq!(crate::utils::normalize_evm_address(&checksummed.to_lowercase()), checksummed);
+    }
+    let mut contract = setup_new_test();
+    let checksummed = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".to_string();
+    let lowercase = checksummed.to_lowercase();
+    let votable = votable_account().as_str().to_string();
+    // a delegation stored before the upgrade, with the checksum case
+    contract.evm_delegation_signatures.insert(checksummed.clone(), (voter_account().to_string(), "legacy".to_string()));
+    contract.evm_delegates.insert(&voter_account().to_string(), &vec![checksummed.clone()]);
+    assert_eq!(contract.get_delegate(lowercase.clone()), Some(voter_account().to_string()));
+    assert_eq!(contract.get_delegation_signature(lowercase.clone()).1, "legacy");
+
+    // mirrored and delegated votes use the same pseudo account, in any case
+    set_context_caller(&owner_account());
+    contract.operator_mirror_lps(lowercase.clone(), vec![(60, U128::from(6 * E6))]);
+    contract.operator_mirror_lps(checksummed.clone(), vec![(60, U128::from(6 * E6))]);
+    assert_eq!(contract.get_voters_count().0, 1);
+    let pseudo_account = format!("{}.evmp.near", checksummed);
+    assert!(contract.voters.get(&pseudo_account).is_some());
+    set_context_caller(&voter_account());
+    contract.vote_delegated(lowercase.clone(), U128::from(E24), votable.clone(), "1".to_owned());
+    assert_eq!(contract.get_total_votes(votable, "1".to_owned()).0, E24);
+    assert_eq!(contract.internal_get_voter(&pseudo_account).vote_positions.len(), 1);
+
+    // a voter mirrored before the upgrade in lowercase keeps its pseudo account
+    let legacy_address = "0xfb6916095ca1df60bb79ce92ce3ea74c37c5d359".to_string();
+    set_context_caller(&owner_account());
+    contract.operator_mirror_lps(legacy_address.clone(), vec![(60, U128::from(6 * E6))]);
+    let legacy_pseudo = format!("{}.evmp.near", legacy_address);
+    let mut legacy_voter = contract.voters.remove(&format!("{}.evmp.near", crate::utils::normalize_evm_address(&legacy_address))).unwrap();
+    contract.voters.insert(&legacy_pseudo, &legacy_voter);
+    contract.operator_mirror_lps("0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359".to_string(), vec![(60, U128::from(12 * E6))]);
+    legacy_voter = contract.internal_get_voter(&legacy_pseudo);
+    assert_eq!(legacy_voter.get_position(0).amount, 12 * E6);
+    assert_eq!(contract.get_voters_count().0, 2);
+}
+
+// EIP-712 Delegation("contract.metavote.near", "voter.metavote.near", nonce, 1_576_368_000)
+const TEST_EIP712_DEADLINE: u64 = 1_576_368_000;
+const TEST_EIP712_SIGNATURE_NONCE_0: &str = "0x8f16c5d1ee946c61ea9928a5c92d7d1c64701d9fe2b3ba36ea44c226417a981b5c7c68b8ba12d885e878df04136bc4d95eb463caa2bfeea250bf333c922ee6511b";
+const TEST_EIP712_SIGNATURE_NONCE_1: &str = "0xdb8a28f85438ddbf3724635579b119eeef971060d4e1ca7ca6cce631afa1e3d019550558409becd38cb89fc07975825f48e2c3dd9e99f4a4a0638a89e6147cc91c";
+
+fn setup_eip712_delegation() -> MetaVoteContract {
+    let mut contract = setup_new_test();
+    let mut context = get_context(&voter_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context);
+    contract.delegate_evm_address(
+        TEST_EVM_ADDRESS.into(),
+        0,
+        TEST_EIP712_DEADLINE,
+        TEST_EIP712_SIGNATURE_NONCE_0.into(),
+    );
+    contract
+}
+
+#[test]
+fn test_evm_delegation_eip712() {
+    let mut contract = setup_eip712_delegation();
+    // confirmed without the operator
+    assert_eq!(
+        contract.get_delegate(TEST_EVM_ADDRESS.into()),
+        Some(voter_account().to_string())
+    );
+    assert_eq!(contract.get_evm_delegation_nonce(TEST_EVM_ADDRESS.into()), 1);
+
+    // remove and delegate again with the next nonce
+    contract.remove_delegated_evm_address(TEST_EVM_ADDRESS.into());
+    assert_eq!(contract.get_delegate(TEST_EVM_ADDRESS.into()), None);
+    contract.delegate_evm_address(
+        TEST_EVM_ADDRESS.into(),
+        1,
+        TEST_EIP712_DEADLINE,
+        TEST_EIP712_SIGNATURE_NONCE_1.into(),
+    );
+    assert_eq!(
+        contract.get_delegation_signature(TEST_EVM_ADDRESS.into()).1,
+        format!("eip712:1:{}:{}", TEST_EIP712_DEADLINE, TEST_EIP712_SIGNATURE_NONCE_1)
+    );
+}
+
+#[test]
+#[should_panic(expected = "invalid nonce, expected 1")]
+fn test_evm_delegation_eip712_replay() {
+    let mut contract = setup_eip712_delegation();
+    contract.remove_delegated_evm_address(TEST_EVM_ADDRESS.into());
+    contract.delegate_evm_address(
+        TEST_EVM_ADDRESS.into(),
+        0,
+        TEST_EIP712_DEADLINE,
+        TEST_EIP712_SIGNATURE_NONCE_0.into(),
+    );
+}
+
+#[test]
+#[should_panic(expected = "signature expired")]
+fn test_evm_delegation_eip712_expired() {
+    let mut contract = setup_new_test();
+    let mut context = get_context(&voter_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + 2));
+    context.attached_deposit = 1;
+    testing_env!(context);
+    contract.delegate_evm_address(
+        TEST_EVM_ADDRESS.into(),
+        0,
+        TEST_EIP712_DEADLINE,
+        TEST_EIP712_SIGNATURE_NONCE_0.into(),
+    );
+}
+
+#[test]
+#[should_panic(expected = "invalid signature for evm_address")]
+fn test_evm_delegation_signature_other_account() {
+    let mut contract = setup_new_test();
+    // the signature is for voter.metavote.near
+    let mut context = get_context(&voter_account_id(1), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context);
+
+    contract.pre_delegate_evm_address(TEST_EVM_ADDRESS.into(), TEST_EVM_SIGNATURE.into());
+}
+
+#[test]
+fn test_decay_voting_power_model() {
+    let mut contract = setup_new_test();
+    let sender_id: AccountId = voter_account();
+    let voter_id = sender_id.as_str().to_string();
+    let votable = votable_account().as_str().to_string();
+
+    let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context);
+    contract.set_voting_power_model(VotingPowerModel::Decay);
+
+    // 6 mpDAO for 60 days => 6 voting power
+    set_context_caller(&mpdao_token_account());
+    contract.ft_on_transfer(sender_id.clone(), U128::from(6 * E6), "60".to_owned());
+    set_context_caller(&sender_id);
+    contract.vote(U128::from(6 * E24), votable.clone(), "1".to_owned());
+
+    // unlocking keeps the voting power and the votes
+    contract.unlock_position(0);
+    assert_eq!(contract.total_voting_power, 6 * E24);
+    assert_eq!(contract.get_total_votes(votable.clone(), "1".to_owned()).0, 6 * E24);
+    let half_way = nanos_to_millis(to_ts(GENESIS_TIME_IN_DAYS + 30));
+    assert_eq!(contract.get_projected_voting_power(voter_id.clone(), half_way).0, 3 * E24);
+
+    // half way, the decayed voting power is not enough for the votes
+    let mut context = get_context(&sender_id, ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + 30));
+    context.block_index = 2;
+    testing_env!(context);
+    contract.sync_voting_power(voter_id.clone());
+    assert_eq!(contract.get_total_votes(votable.clone(), "1".to_owned()).0, 0);
+    assert_eq!(contract.internal_get_voter(&voter_id).available_voting_power, 3 * E24);
+    assert_eq!(contract.total_voting_power, 3 * E24);
+    assert_eq!(contract.get_voting_power_at(voter_id.clone(), half_way).0, 3 * E24);
+
+    // past timestamps use the checkpoints, not the current positions
+    let start = nanos_to_millis(to_ts(GENESIS_TIME_IN_DAYS));
+    assert_eq!(contract.get_projected_voting_power(voter_id.clone(), start).0, 6 * E24);
+
+    // the operator syncs every decaying voter before a snapshot
+    let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + 45));
+    context.block_index = 3;
+    testing_env!(context);
+    assert_eq!(contract.operator_sync_voting_power(0, 10), 1);
+    let three_quarters = nanos_to_millis(to_ts(GENESIS_TIME_IN_DAYS + 45));
+    assert_eq!(contract.total_voting_power, 3 * E24 / 2);
+    assert_eq!(contract.get_total_voting_power_at(three_quarters).0, 3 * E24 / 2);
+    assert_eq!(contract.get_voting_power_at(voter_id.clone(), three_quarters).0, 3 * E24 / 2);
+
+    // unlocked, no voting power left
+    let mut context = get_context(&sender_id, ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + 61));
+    context.block_index = 4;
+    testing_env!(context);
+    contract.sync_voting_power(voter_id.clone());
+    assert_eq!(contract.internal_get_voter(&voter_id).available_voting_power, 0);
+    assert_eq!(contract.total_voting_power, 0);
+    // nothing left to sync
+    let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + 61));
+    context.block_index = 4;
+    testing_env!(context);
+    assert_eq!(contract.operator_sync_voting_power(0, 10), 0);
+}
+
+#[test]
+fn test_voting_power_curve() {
+    let mut contract = setup_new_test();
+    let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context);
+    // default curve
+    assert_eq!(contract.preview_voting_power(U128::from(6 * E6), 180).0, 18 * E24);
+
+    contract.set_voting_power_curve(vec![(30, 5_000), (60, 10_000), (300, 20_000)]);
+    assert_eq!(contract.preview_voting_power(U128::from(6 * E6), 30).0, 3 * E24);
+    // 60..300 goes from 1x to 2x, 180 days is 1.5x
+    assert_eq!(contract.preview_voting_power(U128::from(6 * E6), 180).0, 9 * E24);
+    assert_eq!(contract.preview_voting_power(U128::from(6 * E6), 400).0, 12 * E24);
+
+    set_context_caller(&mpdao_token_account());
+    contract.ft_on_transfer(voter_account(), U128::from(6 * E6), "180".to_owned());
+    assert_eq!(contract.total_voting_power, 9 * E24);
+
+    // a partial unlock removes the voting power the position was locked with
+    let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context);
+    contract.set_voting_power_curve(vec![(30, 10_000), (300, 10_000)]);
+    set_context_caller(&voter_account());
+    contract.unlock_partial_position(0, U128::from(2 * E6));
+    let voter = contract.internal_get_voter(&voter_account().as_str().to_string());
+    assert_eq!(voter.get_position(0).voting_power, 6 * E24);
+    assert_eq!(voter.available_voting_power, 6 * E24);
+    assert_eq!(contract.total_voting_power, 6 * E24);
+}
+
+#[test]
+#[should_panic(expected = "Curve multipliers can not decrease for longer periods")]
+fn test_voting_power_curve_decreasing() {
+    let mut contract = setup_new_test();
+    let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context);
+    contract.set_voting_power_curve(vec![(30, 5_000), (60, 4_000)]);
+}
+
+#[test]
+fn test_recalculate_voting_power() {
+    let mut contract = setup_new_test();
+    let votable = votable_account().as_str().to_string();
+    // 6 mpDAO for 180 days => 18 voting power, for 2 voters
+    for id in 1..=2 {
+        contract.ft_on_transfer(voter_account_id(id), U128::from(6 * E6), "180".to_owned());
+    }
+    set_context_caller(&voter_account_id(1));
+    contract.vote(U128::from(18 * E24), votable.clone(), "1".to_owned());
+
+    let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context.clone());
+    // max 1.5x
+    contract.set_voting_power_curve(vec![(30, 5_000), (60, 10_000), (300, 15_000)]);
+    context.attached_deposit = 0;
+    testing_env!(context);
+
+    let progress = contract.operator_recalculate_voting_power(0, 1);
+    assert_eq!(progress.next_index, 1);
+    assert_eq!(progress.updated_voters, 1);
+    assert!(!progress.done);
+    let progress = contract.operator_recalculate_voting_power(progress.next_index, 1);
+    assert!(progress.done);
+
+    // 180 days is 1.25x => 7.5 voting power each
+    let voting_power = 75 * E24 / 10;
+    assert_eq!(contract.total_voting_power, 2 * voting_power);
+    // the votes exceeded the new voting power
+    assert_eq!(contract.get_total_votes(votable.clone(), "1".to_owned()).0, 0);
+    let voter = contract.internal_get_voter(&voter_account_id(1).to_string());
+    assert_eq!(voter.available_voting_power, voting_power);
+    assert_eq!(voter.get_position(0).voting_power, voting_power);
+
+    // nothing left to update
+    let progress = contract.operator_recalculate_voting_power(0, 10);
+    assert_eq!(progress.updated_voters, 0);
+    assert!(progress.done);
+}
+
+#[test]
+fn test_check_invariants() {
+    let mut contract = setup_new_test();
+    let votable = votable_account().as_str().to_string();
+    for id in 1..=3 {
+        contract.ft_on_transfer(voter_account_id(id), U128::from(6 * E6), "60".to_owned());
+    }
+    set_context_caller(&voter_account_id(1));
+    contract.vote(U128::from(2 * E24), votable.clone(), "1".to_owned());
+    set_context_caller(&voter_account_id(2));
+    contract.delegate_voting_power(voter_account_id(3), U128::from(3 * E24));
+    set_context_caller(&voter_account_id(3));
+    contract.vote(U128::from(8 * E24), votable.clone(), "2".to_owned());
+
+    let mut report = contract.check_invariants(0, 2, None);
+    assert_eq!(report.total_voters, 3);
+    assert_eq!(report.next_index, 2);
+    assert!(!report.done);
+    assert!(report.discrepancies.is_empty());
+    report = contract.check_invariants(report.next_index, 2, Some(report.accumulator));
+    assert!(report.done);
+    assert!(report.discrepancies.is_empty());
+    assert_eq!(report.accumulator.voting_power.0, 18 * E24);
+    assert_eq!(report.accumulator.votes.len(), 2);
+
+    // break the accounting
+    contract.total_voting_power += 1;
+    let mut voter = contract.internal_get_voter(&voter_account_id(1).to_string());
+    voter.available_voting_power += 1;
+    contract.voters.insert(&voter_account_id(1).to_string(), &voter);
+    let report = contract.check_invariants(0, 10, None);
+    assert!(report.done);
+    let subjects: Vec<String> = report.discrepancies.into_iter().map(|d| d.subject).collect();
+    assert_eq!(
+        subjects,
+        vec![
+            format!("voter:{}", voter_account_id(1)),
+            "total_voting_power".to_string(),
+        ]
+    );
+
+    // more delegated out than the voter has is reported, not a panic
+    let delegator_id = voter_account_id(2).to_string();
+    contract.delegated_to.insert(
+        &delegator_id,
+        &vec![(voter_account_id(3).to_string(), 100 * E24)],
+    );
+    let report = contract.check_invariants(1, 1, None);
+    assert_eq!(report.discrepancies.len(), 1);
+    assert_eq!(report.discrepancies[0].subject, format!("voter:{}", delegator_id));
+    assert_eq!(report.discrepancies[0].expected.0, 0);
+}
+
+#[test]
+fn test_repair_accounting() {
+    let mut contract = setup_new_test();
+    let votable = votable_account().as_str().to_string();
+    contract.ft_on_transfer(voter_account_id(1), U128::from(6 * E6), "60".to_owned());
+    set_context_caller(&voter_account_id(1));
+    contract.vote(U128::from(2 * E24), votable.clone(), "1".to_owned());
+    // 10 stNEAR streamed for 10 days
+    set_context_caller(&meta_pool_account());
+    contract.ft_on_transfer(owner_account(), U128::from(10 * E24), "stream-rewards:10".to_owned());
+
+    // corrupt the accounting
+    let voter_id = voter_account_id(1).to_string();
+    let mut voter = contract.internal_get_voter(&voter_id);
+    voter.available_voting_power = 10 * E24;
+    contract.voters.insert(&voter_id, &voter);
+    contract.total_voting_power = 1;
+    contract.internal_increase_total_votes(5, &votable, &"1".to_owned());
+    assert_eq!(contract.check_invariants(0, 10, None).discrepancies.len(), 3);
+
+    let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + 5));
+    context.attached_deposit = 1;
+    testing_env!(context);
+    contract.repair_voter_voting_power(voter_account_id(1).to_string());
+    contract.repair_votes(votable.clone(), "1".to_owned(), U128::from(2 * E24));
+    contract.repair_total_voting_power(U128::from(6 * E24));
+
+    assert!(contract.check_invariants(0, 10, None).discrepancies.is_empty());
+    // the rewards streamed before the repair are released to the previous total,
+    // the repaired total is used for the next rewards and the snapshots
+    let stream = contract.reward_streams.get(&meta_pool_account()).unwrap();
+    assert_eq!(stream.accum_streamed, 5 * E24);
+    assert_eq!(contract.rewards_total_voting_power, 6 * E24);
+    let now = nanos_to_millis(to_ts(GENESIS_TIME_IN_DAYS + 5));
+    assert_eq!(contract.get_total_voting_power_at(now).0, 6 * E24);
+    let voter = contract.internal_get_voter(&voter_id);
+    assert_eq!(voter.available_voting_power, 4 * E24);
+    assert_eq!(contract.get_total_votes(votable, "1".to_owned()).0, 2 * E24);
+}
+
+#[test]
+fn test_storage_management() {
+    let mut contract = setup_new_test();
+    let votable = votable_account().as_str().to_string();
+    let min_balance = contract.storage_balance_bounds().min.0;
+
+    // owner requires storage deposit
+    let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context.clone());
+    contract.set_storage_deposit_required(true);
+
+    context.predecessor_account_id = voter_account_id(1);
+    context.attached_deposit = 2 * min_balance;
+    testing_env!(context.clone());
+    let balance = contract.storage_deposit(None, Some(true));
+    assert_eq!(balance.total.0, min_balance);
+    assert_eq!(contract.storage_balance_of(voter_account_id(1)).unwrap().available.0, min_balance);
+
+    set_context_caller(&mpdao_token_account());
+    let initial_storage_usage = near_sdk::env::storage_usage();
+    contract.ft_on_transfer(voter_account_id(1), U128::from(6 * E6), "60".to_owned());
+    // the voter checkpoints are charged too
+    assert_eq!(
+        contract.storage_accounts.get(&voter_account_id(1).to_string()).unwrap().used_bytes,
+        near_sdk::env::storage_usage() - initial_storage_usage
+    );
+    set_context_caller(&voter_account_id(1));
+    contract.vote(U128::from(2 * E24), votable.clone(), "1".to_owned());
+    let balance = contract.storage_balance_of(voter_account_id(1)).unwrap();
+    assert!(balance.available.0 < min_balance);
+    assert!(balance.available.0 > 0);
+
+    // opting out of auto compound releases its storage
+    contract.set_auto_compound(Some(60));
+    assert!(contract.storage_balance_of(voter_account_id(1)).unwrap().available.0 < balance.available.0);
+    contract.set_auto_compound(None);
+    assert_eq!(contract.storage_balance_of(voter_account_id(1)).unwrap().available, balance.available);
+
+    // the delegation records are charged to the delegator, and released on undelegate
+    contract.delegate_voting_power(voter_account_id(3), U128::from(E24));
+    let delegated_balance = contract.storage_balance_of(voter_account_id(1)).unwrap();
+    assert!(delegated_balance.available.0 < balance.available.0);
+    contract.undelegate_voting_power(voter_account_id(3), None);
+    assert_eq!(contract.storage_balance_of(voter_account_id(1)).unwrap().available, balance.available);
+
+    // an account without storage deposit can not lock
+    set_context_caller(&mpdao_token_account());
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.ft_on_transfer(voter_account_id(2), U128::from(6 * E6), "60".to_owned());
+    }));
+    assert!(result.is_err());
+    assert!(contract.storage_balance_of(voter_account_id(2)).is_none());
+
+    // a removed voter keeps the deposit for the records still stored, the rest is refunded
+    set_context_caller(&voter_account_id(1));
+    contract.unvote(votable, "1".to_owned());
+    contract.unlock_position(0);
+    let mut context = get_context(&voter_account_id(1), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + 61));
+    context.attached_deposit = 1;
+    testing_env!(context);
+    contract.withdraw_all();
+    assert!(contract.voters.get(&voter_account_id(1).to_string()).is_none());
+    let storage = contract.storage_accounts.get(&voter_account_id(1).to_string()).unwrap();
+    assert!(storage.used_bytes > 0);
+    assert_eq!(storage.deposit, storage.used_bytes as u128 * near_sdk::env::storage_byte_cost());
+    assert_eq!(contract.storage_balance_of(voter_account_id(1)).unwrap().available.0, 0);
+}
+
+#[test]
+fn test_owner_withdraw_excess_mpdao() {
+    let mut contract = setup_new_test();
+    contract.ft_on_transfer(voter_account_id(1), U128::from(6 * E6), "60".to_owned());
+    let msg = format!(
+        "for-claims:{}",
+        serde_json::to_string(&vec![(voter_account_id(2).to_string(), 2)]).unwrap()
+    );
+    contract.ft_on_transfer(operator_account(), U128::from(2 * E6), msg);
+    assert_eq!(contract.internal_get_voters_mpdao(), 6 * E6);
+    // 6 locked + 2 unclaimed
+    assert_eq!(contract.internal_owed_balance(&mpdao_token_account()), 8 * E6);
+
+    // the contract holds 10 mpDAO
+    let balance_of_result = |contract: &mut MetaVoteContract, amount: u128| {
+        testing_env!(
+            get_context(&contract_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS)),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(serde_json::to_vec(&U128::from(10 * E6)).unwrap())]
+        );
+        contract.after_owner_balance_of_callback(mpdao_token_account(), U128::from(amount))
+    };
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        balance_of_result(&mut contract, 3 * E6);
+    }));
+    assert!(result.is_err());
+    // the min deposit amount is kept for deposits not yet recorded
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        balance_of_result(&mut contract, 2 * E6);
+    }));
+    assert!(result.is_err());
+    balance_of_result(&mut contract, E6);
+
+    // withdrawn mpDAO is owed until the transfer resolves
+    set_context_caller(&mpdao_token_account());
+    contract.ft_on_transfer(voter_account_id(3), U128::from(6 * E6), "30".to_owned());
+    let mut context = get_context(&voter_account_id(3), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context.clone());
+    contract.unlock_position(0);
+    context.block_timestamp = to_ts(GENESIS_TIME_IN_DAYS + 31);
+    testing_env!(context);
+    contract.withdraw_all();
+    assert_eq!(contract.internal_get_voters_mpdao(), 6 * E6);
+    assert_eq!(contract.mpdao_in_transit, 6 * E6);
+    assert_eq!(contract.internal_owed_balance(&mpdao_token_account()), 14 * E6);
+}
+
+#[test]
+fn test_owner_withdraw_one_at_a_time() {
+    let mut contract = setup_new_test();
+    let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context.clone());
+    contract.owner_withdraw_mpdao(U128::from(E6));
+    assert_eq!(
+        contract.owner_withdraw_in_transit,
+        Some((mpdao_token_account(), E6))
+    );
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.owner_withdraw_stnear(U128::from(E6));
+    }));
+    assert!(result.is_err());
+
+    // resolved even if the balance check or the transfer failed
+    testing_env!(
+        get_context(&contract_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS)),
+        near_sdk::VMConfig::test(),
+        near_sdk::RuntimeFeesConfig::test(),
+        Default::default(),
+        vec![near_sdk::PromiseResult::Failed]
+    );
+    contract.after_owner_withdraw_callback(mpdao_token_account(), U128::from(E6));
+    assert!(contract.owner_withdraw_in_transit.is_none());
+    testing_env!(context);
+    contract.owner_withdraw_stnear(U128::from(E6));
+}
+
+#[test]
+fn test_mpdao_running_totals() {
+    let mut contract = setup_new_test();
+    contract.ft_on_transfer(voter_account_id(1), U128::from(10 * E6), "30".to_owned());
+    contract.ft_on_transfer(voter_account_id(2), U128::from(6 * E6), "60".to_owned());
+    let info = contract.get_contract_info();
+    assert_eq!(info.total_locked_mpdao.0, 16 * E6);
+    assert_eq!(info.total_unlocking_mpdao.0, 0);
+
+    let mut context = get_context(&voter_account_id(1), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    testing_env!(context.clone());
+    contract.unlock_partial_position(0, U128::from(4 * E6));
+    let info = contract.get_contract_info();
+    assert_eq!(info.total_locked_mpdao.0, 12 * E6);
+    assert_eq!(info.total_unlocking_mpdao.0, 4 * E6);
+    let schedule = contract.get_unbonding_schedule();
+    assert_eq!(schedule.len(), 1);
+    assert_eq!(schedule[0].1 .0, 4 * E6);
+
+    // after the unbonding period
+    context.block_timestamp = to_ts(GENESIS_TIME_IN_DAYS + 31);
+    testing_env!(context.clone());
+    let info = contract.get_contract_info();
+    assert_eq!(info.total_unlocking_mpdao.0, 0);
+    assert_eq!(info.total_unlocked_mpdao.0, 4 * E6);
+
+    contract.relock_partial_position(1, U128::from(E6), 60, U128::from(0));
+    let info = contract.get_contract_info();
+    assert_eq!(info.total_unlocked_mpdao.0, 0);
+    assert_eq!(info.total_balance_mpdao.0, 3 * E6);
+    assert_eq!(info.total_locked_mpdao.0, 13 * E6);
+    assert!(contract.get_unbonding_schedule().is_empty());
+
+    context.attached_deposit = 1;
+    testing_env!(context);
+    contract.withdraw(vec![], U128::from(2 * E6));
+    let info = contract.get_contract_info();
+    assert_eq!(info.total_balance_mpdao.0, E6);
+    assert_eq!(contract.internal_get_voters_mpdao(), 14 * E6);
+}
+
+#[test]
+fn test_backfill_mpdao_totals() {
+    let mut contract = setup_new_test();
+    for id in 1..=3 {
+        contract.ft_on_transfer(voter_account_id(id), U128::from(6 * E6), "30".to_owned());
+    }
+    // as right after the upgrade
+    contract.total_locked_mpdao = 0;
+    contract.mpdao_totals_backfill_index = Some(3);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+        context.attached_deposit = 1;
+        testing_env!(context);
+        contract.owner_withdraw_mpdao(U128::from(E6));
+    }));
+    assert!(result.is_err());
+
+    // not counted yet, and a new voter counted from its first deposit
+    set_context_caller(&voter_account_id(1));
+    contract.unlock_partial_position(0, U128::from(2 * E6));
+    set_context_caller(&mpdao_token_account());
+    contract.ft_on_transfer(voter_account_id(4), U128::from(5 * E6), "30".to_owned());
+    assert_eq!(contract.internal_get_voters_mpdao(), 5 * E6);
+    // or from the delegation that created it
+    set_context_caller(&voter_account_id(2));
+    contract.delegate_voting_power(voter_account_id(5), U128::from(E24));
+    set_context_caller(&mpdao_token_account());
+    contract.ft_on_transfer(voter_account_id(5), U128::from(E6), "30".to_owned());
+    assert_eq!(contract.internal_get_voters_mpdao(), 6 * E6);
+
+    set_context_caller(&owner_account());
+    let progress = contract.operator_backfill_mpdao_totals(2);
+    assert_eq!(progress.counted_voters, 2);
+    assert_eq!(progress.remaining_voters, 1);
+    assert!(!progress.done);
+    let progress = contract.operator_backfill_mpdao_totals(2);
+    assert_eq!(progress.counted_voters, 1);
+    assert!(progress.done);
+    assert!(contract.mpdao_totals_backfill_index.is_none());
+    let info = contract.get_contract_info();
+    assert_eq!(info.total_locked_mpdao.0, 22 * E6);
+    assert_eq!(info.total_unlocking_mpdao.0, 2 * E6);
+}
+
+#[test]
+fn test_reward_tokens() {
+    let mut contract = setup_new_test();
+    let reward_token: AccountId = "usdc.near".parse().unwrap();
+    let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context);
+    contract.register_reward_token(reward_token.clone(), U128::from(E6));
+    assert_eq!(contract.get_reward_tokens().len(), 3);
+
+    // distribute 5 usdc and 2 mpDAO
+    let msg = format!(
+        "for-claims:{}",
+        serde_json::to_string(&vec![(voter_account_id(1).to_string(), 3), (voter_account_id(2).to_string(), 2)]).unwrap()
+    );
+    set_context_caller(&reward_token);
+    contract.ft_on_transfer(operator_account(), U128::from(5 * E6), msg.clone());
+    set_context_caller(&mpdao_token_account());
+    contract.ft_on_transfer(operator_account(), U128::from(5 * E6), msg);
+
+    assert_eq!(contract.get_claimable(voter_account_id(1).to_string(), reward_token.clone()).0, 3 * E6);
+    let claimable = contract.get_claimable_tokens(voter_account_id(1).to_string());
+    assert_eq!(
+        claimable,
+        vec![
+            (mpdao_token_account().to_string(), U128::from(3 * E6)),
+            (reward_token.to_string(), U128::from(3 * E6)),
+        ]
+    );
+
+    set_context_caller(&voter_account_id(1));
+    contract.claim(reward_token.clone(), U128::from(E6));
+    assert_eq!(contract.get_claimable(voter_account_id(1).to_string(), reward_token.clone()).0, 2 * E6);
+    contract.claim_all();
+    assert_eq!(contract.get_claimable(voter_account_id(1).to_string(), reward_token.clone()).0, 0);
+    // mpDAO rewards can only be locked
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.claim(mpdao_token_account(), U128::from(E6));
+    }));
+    assert!(result.is_err());
+
+    // voter 2 has unclaimed rewards
+    let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.unregister_reward_token(reward_token.clone());
+    }));
+    assert!(result.is_err());
+    assert_eq!(contract.get_reward_tokens()[2].total_unclaimed.0, 2 * E6);
+}
+
+#[test]
+fn test_stream_rewards() {
+    let mut contract = setup_new_test();
+    let stnear = meta_pool_account();
+    let at_day = |account: &AccountId, days: u64| {
+        testing_env!(get_context(account, ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + days)));
+    };
+    // 6 mpDAO for 60 days => 6 voting power
+    contract.ft_on_transfer(voter_account_id(1), U128::from(6 * E6), "60".to_owned());
+
+    // 10 stNEAR streamed for 10 days
+    at_day(&stnear, 0);
+    contract.ft_on_transfer(owner_account(), U128::from(10 * E24), "stream-rewards:10".to_owned());
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.ft_on_transfer(voter_account_id(1), U128::from(E24), "stream-rewards:10".to_owned());
+    }));
+    assert!(result.is_err());
+
+    // voter 2 joins after 5 days
+    at_day(&mpdao_token_account(), 5);
+    contract.ft_on_transfer(voter_account_id(2), U128::from(6 * E6), "60".to_owned());
+    // rounding down can leave 1 yocto
+    let pending = contract.get_pending_stream_rewards(voter_account_id(1).to_string());
+    assert_eq!(pending[0].0, stnear.to_string());
+    assert!(pending[0].1 .0.abs_diff(5 * E24) <= 1);
+    // released and not yet settled rewards are still owed
+    assert_eq!(contract.internal_owed_balance(&stnear), 10 * E24);
+
+    at_day(&voter_account_id(1), 12);
+    let pending = contract.get_pending_stream_rewards(voter_account_id(2).to_string());
+    assert!(pending[0].1 .0.abs_diff(25 * E24 / 10) <= 1);
+    contract.claim_stnear(U128::from(74 * E24 / 10));
+    assert!(contract.get_claimable_stnear(&voter_account_id(1).to_string()).0.abs_diff(E24 / 10) <= 1);
+    contract.settle_stream_rewards(voter_account_id(2).to_string());
+    assert!(contract.get_claimable_stnear(&voter_account_id(2).to_string()).0.abs_diff(25 * E24 / 10) <= 1);
+
+    // settled rewards are capped at the released rewards not yet settled
+    at_day(&mpdao_token_account(), 12);
+    contract.ft_on_transfer(voter_account_id(3), U128::from(6 * E6), "60".to_owned());
+    at_day(&stnear, 12);
+    contract.ft_on_transfer(owner_account(), U128::from(2 * E24), "stream-rewards:1".to_owned());
+    let mut stream = contract.reward_streams.get(&stnear).unwrap();
+    stream.accum_settled += E24;
+    contract.reward_streams.insert(&stnear, &stream);
+    at_day(&voter_account_id(3), 14);
+    contract.settle_stream_rewards(voter_account_id(1).to_string());
+    contract.settle_stream_rewards(voter_account_id(2).to_string());
+    contract.settle_stream_rewards(voter_account_id(3).to_string());
+    let stream = contract.reward_streams.get(&stnear).unwrap();
+    assert_eq!(stream.accum_settled, stream.accum_streamed);
+    assert!(contract.get_claimable_stnear(&voter_account_id(3).to_string()).0 < 2 * E24 / 3);
+
+    let streams = contract.get_reward_streams();
+    assert_eq!(streams[0].remaining.0, 0);
+    assert_eq!(streams[0].accum_streamed.0, 12 * E24);
+
+    // nothing is written for an account without voting power
+    contract.settle_stream_rewards("nobody.near".to_string());
+    assert!(contract
+        .stream_rewards_paid
+        .get(&("nobody.near".to_string(), stnear))
+        .is_none());
+}
+
+#[test]
+fn test_unregister_streamed_reward_token() {
+    let mut contract = setup_new_test();
+    let reward_token: AccountId = "usdc.near".parse().unwrap();
+    contract.ft_on_transfer(voter_account_id(1), U128::from(6 * E6), "60".to_owned());
+    let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context.clone());
+    contract.register_reward_token(reward_token.clone(), U128::from(E6));
+    set_context_caller(&reward_token);
+    contract.ft_on_transfer(owner_account(), U128::from(12 * E6), "stream-rewards:10".to_owned());
+
+    // not yet released
+    testing_env!(context.clone());
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.unregister_reward_token(reward_token.clone());
+    }));
+    assert!(result.is_err());
+
+    // released, not yet settled
+    context.block_timestamp = to_ts(GENESIS_TIME_IN_DAYS + 20);
+    testing_env!(context.clone());
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.unregister_reward_token(reward_token.clone());
+    }));
+    assert!(result.is_err());
+
+    let mut voter_context = context.clone();
+    voter_context.predecessor_account_id = voter_account_id(1);
+    testing_env!(voter_context);
+    contract.claim(reward_token.clone(), U128::from(12 * E6));
+    testing_env!(context);
+    contract.unregister_reward_token(reward_token.clone());
+    assert!(contract.get_reward_streams().is_empty());
+    // the voters keep working without the stream
+    set_context_caller(&mpdao_token_account());
+    contract.ft_on_transfer(voter_account_id(1), U128::from(6 * E6), "30".to_owned());
+}
+
+#[test]
+fn test_incentive_pools() {
+    let mut contract = setup_new_test();
+    let stnear = meta_pool_account();
+    let object = || votable_account().to_string();
+    let at_day = |account: &AccountId, days: u64| {
+        testing_env!(get_context(account, ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + days)));
+    };
+    let vp = calculate_voting_power(6 * E6, 60);
+    at_day(&mpdao_token_account(), 0);
+    for n in 1..=3 {
+        contract.ft_on_transfer(voter_account_id(n), U128::from(6 * E6), "60".to_owned());
+    }
+    at_day(&voter_account_id(1), 0);
+    contract.vote(U128::from(vp), object(), "1".to_owned());
+    at_day(&voter_account_id(2), 0);
+    contract.vote(U128::from(vp / 2), object(), "1".to_owned());
+
+    // 30 stNEAR for the voters of the object at day 10, anyone can deposit
+    at_day(&stnear, 0);
+    let msg = format!(
+        "incentive:{{\"contract_address\":\"{}\",\"votable_object_id\":\"1\",\"cutoff_ms\":{}}}",
+        object(),
+        to_ts(GENESIS_TIME_IN_DAYS + 10) / 1_000_000
+    );
+    contract.ft_on_transfer(voter_account_id(3), U128::from(30 * E24), msg);
+    let pools = contract.get_incentive_pools(object(), "1".to_owned());
+    assert_eq!(pools.len(), 1);
+    assert_eq!(pools[0].amount.0, 30 * E24);
+    let pool_id = pools[0].pool_id;
+    at_day(&voter_account_id(1), 5);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.claim_incentive(pool_id);
+    }));
+    assert!(result.is_err());
+
+    // vote changes after the cutoff don't change the shares
+    at_day(&voter_account_id(2), 11);
+    contract.unvote(object(), "1".to_owned());
+    at_day(&voter_account_id(3), 11);
+    contract.vote(U128::from(vp), object(), "1".to_owned());
+    assert_eq!(contract.get_claimable_incentive(pool_id, voter_account_id(3).to_string()).0, 0);
+    assert_eq!(contract.get_claimable_incentive(pool_id, voter_account_id(2).to_string()).0, 10 * E24);
+
+    at_day(&voter_account_id(1), 12);
+    assert_eq!(contract.claim_incentive(pool_id).0, 20 * E24);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.claim_incentive(pool_id);
+    }));
+    assert!(result.is_err());
+    assert_eq!(contract.get_claimable_stnear(&voter_account_id(1).to_string()).0, 20 * E24);
+    at_day(&voter_account_id(2), 12);
+    assert_eq!(contract.claim_incentive(pool_id).0, 10 * E24);
+    at_day(&voter_account_id(3), 12);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.claim_incentive(pool_id);
+    }));
+    assert!(result.is_err());
+    assert_eq!(contract.internal_owed_balance(&stnear), 30 * E24);
+
+    // closed after the claim period
+    at_day(&voter_account_id(3), 69);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.close_incentive_pool(pool_id);
+    }));
+    assert!(result.is_err());
+    at_day(&voter_account_id(3), 70);
+    assert!(contract.close_incentive_pool(pool_id));
+    assert!(contract.get_incentive_pool(pool_id).is_none());
+    assert!(contract.get_incentive_pools(object(), "1".to_owned()).is_empty());
+    for n in 1..=3 {
+        assert!(contract
+            .incentive_votes
+            .get(&(pool_id, voter_account_id(n).to_string()))
+            .is_none());
+    }
+
+    // new pools need a minimum amount and a cutoff within the max unbond period
+    at_day(&stnear, 70);
+    let deposit = |contract: &mut MetaVoteContract, amount: u128, cutoff_days: u64| {
+        let msg = format!(
+            "incentive:{{\"contract_address\":\"{}\",\"votable_object_id\":\"1\",\"cutoff_ms\":{}}}",
+            object(),
+            to_ts(GENESIS_TIME_IN_DAYS + 70 + cutoff_days) / 1_000_000
+        );
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.ft_on_transfer(voter_account_id(3), U128::from(amount), msg);
+        }))
+        .is_ok()
+    };
+    assert!(!deposit(&mut contract, 99 * E20, 10));
+    assert!(!deposit(&mut contract, E24, MAX_UNBOND_PERIOD as u64 + 1));
+    assert!(deposit(&mut contract, E24, MAX_UNBOND_PERIOD as u64));
+}
+
+#[test]
+fn test_merkle_distribution() {
+    let mut contract = setup_new_test();
+    let stnear = meta_pool_account();
+    let at_day = |account: &AccountId, days: u64| {
+        testing_env!(get_context(account, ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + days)));
+    };
+    let hash_pair = |a: [u8; 32], b: [u8; 32]| {
+        if a <= b {
+            env::keccak256_array(&[a, b].concat())
+        } else {
+            env::keccak256_array(&[b, a].concat())
+        }
+    };
+    at_day(&stnear, 0);
+    let leaves: Vec<[u8; 32]> = (0..3)
+        .map(|n| merkle_leaf(n, voter_account_id(n as u8 + 1).as_str(), (n as u128 + 1) * 10 * E24))
+        .collect();
+    let node_01 = hash_pair(leaves[0], leaves[1]);
+    let root = hash_pair(node_01, leaves[2]);
+
+    let msg = format!(
+        "merkle-distribution:{{\"merkle_root\":\"{}\",\"expiry_days\":30}}",
+        hex_encode(&root)
+    );
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.ft_on_transfer(voter_account_id(1), U128::from(60 * E24), msg.clone());
+    }));
+    assert!(result.is_err());
+    contract.ft_on_transfer(owner_account(), U128::from(60 * E24), msg);
+    assert_eq!(contract.get_merkle_distributions(0, 10).len(), 1);
+    assert_eq!(contract.internal_owed_balance(&stnear), 60 * E24);
+
+    at_day(&voter_account_id(1), 1);
+    // wrong amount
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.claim_merkle(0, 0, U128::from(20 * E24), vec![hex_encode(&leaves[1]), hex_encode(&leaves[2])]);
+    }));
+    assert!(result.is_err());
+    contract.claim_merkle(0, 0, U128::from(10 * E24), vec![hex_encode(&leaves[1]), hex_encode(&leaves[2])]);
+    assert!(contract.is_merkle_claimed(0, 0));
+    assert!(!contract.is_merkle_claimed(0, 1));
+    assert_eq!(contract.get_claimable_stnear(&voter_account_id(1).to_string()).0, 10 * E24);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.claim_merkle(0, 0, U128::from(10 * E24), vec![hex_encode(&leaves[1]), hex_encode(&leaves[2])]);
+    }));
+    assert!(result.is_err());
+    at_day(&voter_account_id(3), 1);
+    contract.claim_merkle(0, 2, U128::from(30 * E24), vec![hex_encode(&node_01)]);
+    assert_eq!(contract.get_claimable_stnear(&voter_account_id(3).to_string()).0, 30 * E24);
+    assert_eq!(contract.get_merkle_distribution(0).unwrap().claimed.0, 40 * E24);
+
+    // expired, voter 2 can't claim and the rest is swept
+    at_day(&voter_account_id(2), 30);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.claim_merkle(0, 1, U128::from(20 * E24), vec![hex_encode(&leaves[0]), hex_encode(&leaves[2])]);
+    }));
+    assert!(result.is_err());
+    assert!(contract.sweep_merkle_distribution(0));
+    assert!(contract.get_merkle_distribution(0).is_none());
+    assert_eq!(contract.internal_owed_balance(&stnear), 40 * E24);
+    // the claimed flags are removed too
+    assert!(contract.merkle_claimed.get(&(0, 0)).is_none());
+    assert!(!contract.is_merkle_claimed(0, 0));
+}
+
+#[test]
+fn test_expiring_claims() {
+    let mut contract = setup_new_test();
+    let stnear = meta_pool_account();
+    let at_day = |account: &AccountId, days: u64| {
+        testing_env!(get_context(account, ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + days)));
+    };
+    at_day(&stnear, 0);
+    let msg = format!("for-claims:[[\"{}\",50000]]", voter_account_id(1));
+    contract.ft_on_transfer(owner_account(), U128::from(5 * E24), msg);
+    // 10 stNEAR to voter 1 and 20 stNEAR to voter 2, expiring in 30 days
+    let msg = format!(
+        "for-claims-expiring:{{\"expiry_days\":30,\"claims\":[[\"{}\",100000],[\"{}\",200000]]}}",
+        voter_account_id(1),
+        voter_account_id(2)
+    );
+    // only the owner or the operator can distribute expiring claims
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.ft_on_transfer(voter_account_id(3), U128::from(30 * E24), msg.clone());
+    }));
+    assert!(result.is_err());
+    contract.ft_on_transfer(owner_account(), U128::from(30 * E24), msg);
+    let expiring = contract.get_expiring_claims(voter_account_id(1).to_string());
+    assert_eq!(expiring.len(), 1);
+    assert_eq!(expiring[0].amount.0, 10 * E24);
+    assert_eq!(expiring[0].expiry_ms, to_ts(GENESIS_TIME_IN_DAYS + 30) / 1_000_000);
+    assert_eq!(contract.get_claimable_stnear(&voter_account_id(1).to_string()).0, 15 * E24);
+
+    // claims consume the expiring amounts first
+    at_day(&voter_account_id(1), 1);
+    contract.claim_stnear(U128::from(12 * E24));
+    assert!(contract.get_expiring_claims(voter_account_id(1).to_string()).is_empty());
+
+    // a failed transfer restores the expiring amounts
+    testing_env!(
+        get_context(&contract_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + 1)),
+        near_sdk::VMConfig::test(),
+        near_sdk::RuntimeFeesConfig::test(),
+        Default::default(),
+        vec![near_sdk::PromiseResult::Failed]
+    );
+    let consumed_claims = vec![ConsumedClaim {
+        batch_id: 0,
+        expiry_ms: to_ts(GENESIS_TIME_IN_DAYS + 30) / 1_000_000,
+        amount: U128::from(10 * E24),
+    }];
+    contract.after_transfer_stnear_callback(&voter_account_id(1).to_string(), U128::from(12 * E24), consumed_claims);
+    let expiring = contract.get_expiring_claims(voter_account_id(1).to_string());
+    assert_eq!(expiring.len(), 1);
+    assert_eq!(expiring[0].amount.0, 10 * E24);
+    assert_eq!(contract.get_claimable_stnear(&voter_account_id(1).to_string()).0, 15 * E24);
+    assert_eq!(contract.get_claim_batches(0, 10)[0].unclaimed.0, 30 * E24);
+    at_day(&voter_account_id(1), 1);
+    contract.claim_stnear(U128::from(12 * E24));
+    assert!(contract.get_expiring_claims(voter_account_id(1).to_string()).is_empty());
+    assert_eq!(contract.get_claimable_stnear(&voter_account_id(1).to_string()).0, 3 * E24);
+    assert_eq!(contract.get_claim_batches(0, 10)[0].unclaimed.0, 20 * E24);
+
+    at_day(&owner_account(), 29);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.sweep_expired_claims(0, 0, 10);
+    }));
+    assert!(result.is_err());
+    at_day(&voter_account_id(2), 30);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.sweep_expired_claims(0, 0, 10);
+    }));
+    assert!(result.is_err());
+    at_day(&owner_account(), 30);
+    assert_eq!(contract.sweep_expired_claims(0, 0, 1).0, 0);
+    assert_eq!(contract.sweep_expired_claims(0, 1, 1).0, 20 * E24);
+    assert!(contract.get_claim_batches(0, 10).is_empty());
+    assert_eq!(contract.get_claimable_stnear(&voter_account_id(2).to_string()).0, 0);
+    assert_eq!(contract.total_unclaimed_stnear, 3 * E24);
+}
+
+#[test]
+fn test_claim_stnear_and_lock() {
+    let mut contract = setup_new_test();
+    let swap_account = AccountId::new_unchecked("swap.near".to_string());
+    let stnear_claimable = |contract: &MetaVoteContract| contract.get_claimable_stnear(&voter_account_id(1).to_string()).0;
+    let swap_result = |result: near_sdk::PromiseResult| {
+        testing_env!(
+            get_context(&contract_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS)),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result]
+        );
+    };
+    contract.ft_on_transfer(voter_account_id(1), U128::from(6 * E6), "60".to_owned());
+    set_context_caller(&meta_pool_account());
+    let msg = format!("for-claims:[[\"{}\",100000]]", voter_account_id(1));
+    contract.ft_on_transfer(owner_account(), U128::from(10 * E24), msg);
+
+    let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context);
+    contract.set_swap_contract(Some(swap_account.clone()));
+
+    // 4 stNEAR swapped for 3 mpDAO, locked for 60 days
+    set_context_caller(&voter_account_id(1));
+    contract.claim_stnear_and_lock(U128::from(4 * E24), 60, U128::from(2 * E6));
+    assert_eq!(stnear_claimable(&contract), 6 * E24);
+    assert_eq!(contract.stnear_in_transit, 4 * E24);
+    swap_result(near_sdk::PromiseResult::Successful(serde_json::to_vec(&U128::from(4 * E24)).unwrap()));
+    contract.after_swap_stnear_callback(0, U128::from(4 * E24));
+    assert_eq!(contract.stnear_in_transit, 0);
+    assert!(contract.get_pending_swap(0).unwrap().stnear_resolved);
+    set_context_caller(&mpdao_token_account());
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.ft_on_transfer(voter_account_id(2), U128::from(3 * E6), "swap-lock:0".to_owned());
+    }));
+    assert!(result.is_err());
+    contract.ft_on_transfer(swap_account.clone(), U128::from(3 * E6), "swap-lock:0".to_owned());
+    assert!(contract.get_pending_swap(0).is_none());
+    assert_eq!(contract.total_locked_mpdao, 9 * E6);
+    let voter = contract.internal_get_voter(&voter_account_id(1).to_string());
+    assert_eq!(voter.locking_positions.len(), 1);
+
+    // failed swap, the stNEAR is claimable again
+    set_context_caller(&voter_account_id(1));
+    contract.claim_stnear_and_lock(U128::from(2 * E24), 60, U128::from(2 * E6));
+    swap_result(near_sdk::PromiseResult::Failed);
+    contract.after_swap_stnear_callback(1, U128::from(2 * E24));
+    assert_eq!(stnear_claimable(&contract), 6 * E24);
+    assert!(contract.get_pending_swap(1).is_none());
+    set_context_caller(&mpdao_token_account());
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.ft_on_transfer(swap_account.clone(), U128::from(E6), "swap-lock:1".to_owned());
+    }));
+    assert!(result.is_err());
+
+    // a voter that cannot lock now gets the mpDAO as claimable
+    set_context_caller(&voter_account_id(1));
+    contract.claim_stnear_and_lock(U128::from(2 * E24), 30, U128::from(E6));
+    swap_result(near_sdk::PromiseResult::Successful(serde_json::to_vec(&U128::from(2 * E24)).unwrap()));
+    contract.after_swap_stnear_callback(2, U128::from(2 * E24));
+    contract.storage_deposit_required = true;
+    set_context_caller(&mpdao_token_account());
+    contract.ft_on_transfer(swap_account.clone(), U128::from(2 * E6), "swap-lock:2".to_owned());
+    assert!(contract.get_pending_swap(2).is_none());
+    assert_eq!(contract.get_claimable_mpdao(&voter_account_id(1).to_string()).0, 2 * E6);
+    assert_eq!(contract.internal_get_voter(&voter_account_id(1).to_string()).locking_positions.len(), 1);
+}
+
+#[test]
+fn test_claim_to_and_auto_compound() {
+    let mut contract = setup_new_test();
+    for n in 1..=2 {
+        contract.ft_on_transfer(voter_account_id(n), U128::from(6 * E6), "60".to_owned());
+    }
+    let msg = format!("for-claims:[[\"{}\",10],[\"{}\",5]]", voter_account_id(1), voter_account_id(2));
+    contract.ft_on_transfer(owner_account(), U128::from(15 * E6), msg);
+    set_context_caller(&meta_pool_account());
+    let msg = format!("for-claims:[[\"{}\",30000]]", voter_account_id(1));
+    contract.ft_on_transfer(owner_account(), U128::from(3 * E24), msg);
+
+    // claims to another account
+    set_context_caller(&voter_account_id(1));
+    contract.claim_stnear_to(voter_account_id(3), U128::from(E24));
+    assert_eq!(contract.get_claimable_stnear(&voter_account_id(1).to_string()).0, 2 * E24);
+    assert_eq!(contract.stnear_in_transit, E24);
+    contract.claim_mpdao_and_lock_for(voter_account_id(3), U128::from(4 * E6), 30);
+    assert_eq!(contract.get_claimable_mpdao(&voter_account_id(1).to_string()).0, 6 * E6);
+    let voter = contract.internal_get_voter(&voter_account_id(3).to_string());
+    assert_eq!(voter.locking_positions.len(), 1);
+    assert_eq!(contract.total_locked_mpdao, 16 * E6);
+
+    // voter 2 opts-in to lock its mpDAO rewards for 90 days
+    set_context_caller(&voter_account_id(2));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.set_auto_compound(Some(MAX_UNBOND_PERIOD + 1));
+    }));
+    assert!(result.is_err());
+    contract.set_auto_compound(Some(90));
+    assert_eq!(contract.get_auto_compound_voters(0, 10), vec![(voter_account_id(2).to_string(), 90)]);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.auto_compound_claims(0, 10);
+    }));
+    assert!(result.is_err());
+    // voter 1 opts-in for 120 days, then the max unbond period is lowered: skipped
+    set_context_caller(&voter_account_id(1));
+    contract.set_auto_compound(Some(120));
+    contract.max_unbond_period = 100;
+    set_context_caller(&owner_account());
+    assert_eq!(contract.auto_compound_claims(0, 10), 2);
+    assert_eq!(contract.get_claimable_mpdao(&voter_account_id(2).to_string()).0, 0);
+    assert_eq!(contract.get_claimable_mpdao(&voter_account_id(1).to_string()).0, 6 * E6);
+    let voter = contract.internal_get_voter(&voter_account_id(2).to_string());
+    assert_eq!(voter.locking_positions.len(), 2);
+    assert_eq!(contract.total_locked_mpdao, 21 * E6);
+}
+
+#[test]
+fn test_withdraw_to() {
+    let mut contract = setup_new_test();
+    let dex = AccountId::new_unchecked("dex.near".to_string());
+    let voter_id = voter_account_id(1).to_string();
+    let call_with_deposit = |account: &AccountId, days: u64| {
+        let mut context = get_context(account, ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + days));
+        context.attached_deposit = 1;
+        testing_env!(context);
+    };
+    let transfer_result = |value: Vec<u8>| {
+        testing_env!(
+            get_context(&contract_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + 31)),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(value)]
+        );
+    };
+    contract.ft_on_transfer(voter_account_id(1), U128::from(6 * E6), "30".to_owned());
+    set_context_caller(&voter_account_id(1));
+    contract.unlock_position(0);
+
+    // ft_transfer_call to the dex, that uses 4 mpDAO and refunds 2
+    contract.storage_deposit_required = true;
+    call_with_deposit(&voter_account_id(1), 31);
+    contract.withdraw_to(dex, vec![0], U128::from(0), Some("deposit".to_owned()));
+    assert_eq!(contract.mpdao_in_transit, 6 * E6);
+    assert!(contract.voters.get(&voter_id).is_none());
+    transfer_result(serde_json::to_vec(&U128::from(4 * E6)).unwrap());
+    contract.after_transfer_mpdao_callback(voter_id.clone(), U128::from(6 * E6));
+    assert_eq!(contract.mpdao_in_transit, 0);
+    assert_eq!(contract.voters.get(&voter_id).unwrap().balance, 2 * E6);
+    // the recreated voter is charged again
+    assert!(contract.storage_accounts.get(&voter_id).unwrap().used_bytes > 0);
+    assert_eq!(contract.total_balance_mpdao, 2 * E6);
+
+    // ft_transfer to another account
+    call_with_deposit(&voter_account_id(1), 31);
+    contract.withdraw_to(voter_account_id(2), vec![], U128::from(0), None);
+    transfer_result(vec![]);
+    contract.after_transfer_mpdao_callback(voter_id.clone(), U128::from(2 * E6));
+    assert!(contract.voters.get(&voter_id).is_none());
+    assert_eq!(contract.total_balance_mpdao, 0);
+    assert_eq!(contract.mpdao_in_transit, 0);
+}
+
+#[test]
+fn test_early_withdraw() {
+    let mut contract = setup_new_test();
+    let mpdao = mpdao_token_account();
+    let call_with_deposit = |account: &AccountId, days: u64| {
+        let mut context = get_context(account, ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + days));
+        context.attached_deposit = 1;
+        testing_env!(context);
+    };
+    contract.ft_on_transfer(voter_account_id(1), U128::from(30 * E6), "300".to_owned());
+    contract.ft_on_transfer(voter_account_id(2), U128::from(6 * E6), "60".to_owned());
+    let vp = calculate_voting_power(30 * E6, 300);
+
+    // disabled by default
+    call_with_deposit(&voter_account_id(1), 0);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.early_withdraw(0, U128::from(10 * E6));
+    }));
+    assert!(result.is_err());
+
+    // 50% for the max unbond period remaining, burned
+    call_with_deposit(&owner_account(), 0);
+    contract.set_early_withdraw_config(5_000, PenaltyDestination::Burn);
+    let preview = contract.preview_early_withdraw(voter_account_id(1).to_string(), 0, U128::from(10 * E6));
+    assert_eq!(preview.penalty.0, 5 * E6);
+    assert_eq!(preview.net_amount.0, 5 * E6);
+    assert_eq!(preview.remaining_unbond_ms, days_to_millis(300));
+
+    // requires 1 yoctoNEAR
+    set_context_caller(&voter_account_id(1));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.early_withdraw(0, U128::from(10 * E6));
+    }));
+    assert!(result.is_err());
+    call_with_deposit(&voter_account_id(1), 0);
+    contract.early_withdraw(0, U128::from(10 * E6));
+    let voter = contract.internal_get_voter(&voter_account_id(1).to_string());
+    assert_eq!(voter.get_position(0).amount, 20 * E6);
+    assert_eq!(voter.balance, 0);
+    assert_eq!(contract.get_available_voting_power(voter_account_id(1).to_string()).0, vp - vp / 3);
+    assert_eq!(contract.total_burned_mpdao, 5 * E6);
+    assert_eq!(contract.mpdao_in_transit, 5 * E6);
+    assert_eq!(contract.total_locked_mpdao, 26 * E6);
+    // the penalty is not withdrawable by the owner
+    assert_eq!(contract.internal_owed_balance(&mpdao), 36 * E6);
+
+    // unlocking position with 30 of 60 days remaining, redistributed to the voting power
+    call_with_deposit(&owner_account(), 0);
+    contract.set_early_withdraw_config(5_000, PenaltyDestination::Redistribute);
+    set_context_caller(&voter_account_id(2));
+    contract.unlock_position(0);
+    call_with_deposit(&voter_account_id(2), 30);
+    let preview = contract.preview_early_withdraw(voter_account_id(2).to_string(), 0, U128::from(6 * E6));
+    assert_eq!(preview.penalty.0, 3 * E6 / 10);
+    contract.early_withdraw(0, U128::from(6 * E6));
+    assert!(contract.voters.get(&voter_account_id(2).to_string()).is_none());
+    assert_eq!(contract.total_unbonding_mpdao, 0);
+    let pending = contract.get_pending_stream_rewards(voter_account_id(1).to_string());
+    assert_eq!(pending[0].0, mpdao.to_string());
+    assert!(pending[0].1 .0.abs_diff(3 * E6 / 10) <= 1);
+    assert_eq!(contract.internal_owed_balance(&mpdao), 36 * E6);
+}
+
+#[test]
+fn test_evm_delegation_replay_recased_or_legacy() {
+    let mut contract = setup_eip712_delegation();
+    contract.remove_delegated_evm_address(TEST_EVM_ADDRESS.to_uppercase().replace("0X", "0x"));
+    assert_eq!(contract.get_delegate(TEST_EVM_ADDRESS.into()), None);
+    // the same signature with the address recased, unprefixed or as legacy message
+    let recased = TEST_EVM_ADDRESS.to_uppercase().replace("0X", "0x");
+    let unprefixed = TEST_EVM_ADDRESS.trim_start_matches("0x").to_string();
+    for evm_address in [recased, unprefixed] {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.delegate_evm_address(
+                evm_address.clone(),
+                0,
+                TEST_EIP712_DEADLINE,
+                TEST_EIP712_SIGNATURE_NONCE_0.into(),
+            );
+        }));
+        assert!(result.is_err());
+    }
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.pre_delegate_evm_address(TEST_EVM_ADDRESS.into(), TEST_EVM_SIGNATURE.into());
+    }));
+    assert!(result.is_err());
+    assert_eq!(contract.get_delegate(TEST_EVM_ADDRESS.into()), None);
+    assert_eq!(contract.get_evm_delegation_nonce(TEST_EVM_ADDRESS.into()), 1);
+}
+
+#[test]
+fn test_decay_reduces_delegations() {
+    let mut contract = setup_new_test();
+    let delegator: AccountId = voter_account();
+    let delegate: AccountId = voter_account_id(1);
+    let delegator_id = delegator.as_str().to_string();
+    let delegate_id = delegate.as_str().to_string();
+    let votable = votable_account().as_str().to_string();
+
+    let mut context = get_context(&owner_account(), ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS));
+    context.attached_deposit = 1;
+    testing_env!(context);
+    contract.set_voting_power_model(VotingPowerModel::Decay);
+
+    // 6 mpDAO for 60 days => 6 voting power, all delegated and voted by the delegate
+    set_context_caller(&mpdao_token_account());
+    contract.ft_on_transfer(delegator.clone(), U128::from(6 * E6), "60".to_owned());
+    set_context_caller(&delegator);
+    contract.delegate_voting_power(delegate.clone(), U128::from(6 * E24));
+    set_context_caller(&delegate);
+    contract.vote(U128::from(6 * E24), votable.clone(), "1".to_owned());
+    set_context_caller(&delegator);
+    contract.unlock_position(0);
+
+    // half way, the decayed voting power is removed from the delegation
+    testing_env!(get_context(&delegator, ntoy(TEST_INITIAL_BALANCE), 0, to_ts(GENESIS_TIME_IN_DAYS + 30)));
+    contract.sync_voting_power(delegator_id.clone());
+    assert_eq!(
+        contract.get_voting_power_delegates(delegator.clone()),
+        vec![(delegate_id.clone(), U128::from(3 * E24))]
+    );
+    assert_eq!(contract.get_total_votes(votable.clone(), "1".to_owned()).0, 0);
+    assert_eq!(contract.internal_get_voter(&delegate_id).available_voting_power, 3 * E24);
+    assert_eq!(contract.internal_get_voter(&delegator_id).available_voting_power, 0);
+
+    // undelegating returns only the voting power left
+    contract.undelegate_voting_power(delegate.clone(), None);
+    assert!(contract.voters.get(&delegate_id).is_none());
+    assert_eq!(contract.internal_get_voter(&delegator_id).available_voting_power, 3 * E24);
+    assert_eq!(contract.total_voting_power, 3 * E24);
+}
+
+/// MetaVoteContract as deployed before the upgrade, written independently of migrate::OldState
+#[derive(BorshSerialize)]
+struct BaselineMetaVoteContract {
+    owner_id: AccountId,
+    operator_id: AccountId,
+    voters: UnorderedMap<String, Voter>,
+    votes: UnorderedMap<ContractAddress, UnorderedMap<VotableObjId, u128>>,
+    min_unbond_period: Days,
+    max_unbond_period: Days,
+    min_deposit_amount: MpDAOAmount,
+    max_locking_positions: u8,
+    max_voting_positions: u8,
+    mpdao_token_contract_address: AccountId,
+    total_voting_power: u128,
+    claimable_mpdao: UnorderedMap<String, u128>,
+    accumulated_mpdao_distributed_for_claims: u128,
+    total_unclaimed_mpdao: u128,
+    stnear_token_contract_address: AccountId,
+    claimable_stnear: UnorderedMap<String, u128>,
+    accum_distributed_stnear_for_claims: u128,
+    total_unclaimed_stnear: u128,
+    registration_cost: u128,
+    associated_user_data: UnorderedMap<String, String>,
+    prev_governance_contract: String,
+    evm_delegates: UnorderedMap<String, Vec<EvmAddress>>,
+    evm_pre_delegation: LookupMap<EvmAddress, (String, EvmSignature)>,
+    evm_delegation_signatures: LookupMap<EvmAddress, (String, EvmSignature)>,
+    lock_votes_in_end_timestamp_ms: u64,
+    lock_votes_in_address: Option<String>,
+    lock_votes_in_numeric_id: u16,
+}
+
+#[test]
+fn test_migrate_baseline_state() {
+    let mut contract = setup_new_test();
+    contract.ft_on_transfer(voter_account_id(1), U128::from(6 * E6), "60".to_owned());
+    let total_voting_power = contract.total_voting_power;
+    let baseline = BaselineMetaVoteContract {
+        owner_id: contract.owner_id,
+        operator_id: contract.operator_id,
+        voters: contract.voters,
+        votes: contract.votes,
+        min_unbond_period: contract.min_unbond_period,
+        max_unbond_period: contract.max_unbond_period,
+        min_deposit_amount: contract.min_deposit_amount,
+        max_locking_positions: contract.max_locking_positions,
+        max_voting_positions: contract.max_voting_positions,
+        mpdao_token_contract_address: contract.mpdao_token_contract_address,
+        total_voting_power,
+        claimable_mpdao: contract.claimable_mpdao,
+        accumulated_mpdao_distributed_for_claims: contract.accumulated_mpdao_distributed_for_claims,
+        total_unclaimed_mpdao: contract.total_unclaimed_mpdao,
+        stnear_token_contract_address: contract.stnear_token_contract_address,
+        claimable_stnear: contract.claimable_stnear,
+        accum_distributed_stnear_for_claims: contract.accum_distributed_stnear_for_claims,
+        total_unclaimed_stnear: contract.total_unclaimed_stnear,
+        registration_cost: contract.registration_cost,
+        associated_user_data: contract.associated_user_data,
+        prev_governance_contract: contract.prev_governance_contract,
+        evm_delegates: contract.evm_delegates,
+        evm_pre_delegation: contract.evm_pre_delegation,
+        evm_delegation_signatures: contract.evm_delegation_signatures,
+        lock_votes_in_end_timestamp_ms: 1_700_000_000_000,
+        lock_votes_in_address: Some("mpip.near".to_string()),
+        lock_votes_in_numeric_id: 7,
+    };
+    near_sdk::env::state_write(&baseline);
+
+    let contract = MetaVoteContract::migrate();
+    assert_eq!(contract.lock_votes_in_end_timestamp_ms, 1_700_000_000_000);
+    assert_eq!(contract.lock_votes_in_address, Some("mpip.near".to_string()));
+    assert_eq!(contract.lock_votes_in_numeric_id, 7);
+    assert_eq!(contract.get_voters_count().0, 1);
+    assert_eq!(contract.get_total_voting_power().0, total_voting_power);
+    assert_eq!(contract.get_all_locking_positions(voter_account_id(1).to_string()).len(), 1);
+    assert_eq!(
+        contract.get_total_voting_power_at(to_ts(GENESIS_TIME_IN_DAYS) / 1_000_000).0,
+        total_voting_power
+    );
+}
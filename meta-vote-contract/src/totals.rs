@@ -0,0 +1,193 @@
+use crate::*;
+use near_sdk::near_bindgen;
+use near_sdk::serde::{Deserialize, Serialize};
+
+/// end of the unbonding period rounded up to the day, key of the unbonding schedule
+fn unbonding_schedule_key(locking_position: &LockingPosition) -> EpochMillis {
+    let day = days_to_millis(1);
+    let unlocked_at = locking_position.unlocking_started_at.unwrap()
+        + locking_position.locking_period_millis();
+    unlocked_at.div_ceil(day) * day
+}
+
+// *******************************************************
+// * Contract mpDAO running totals                       *
+// * mirrored accounts (.evmp.near) are not counted,     *
+// * their positions are not backed by mpDAO held here   *
+// *******************************************************
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BackfillProgressJSON {
+    /// voters still to count
+    pub remaining_voters: u64,
+    /// voters counted in this batch
+    pub counted_voters: u32,
+    pub done: bool,
+}
+
+impl MetaVoteContract {
+    /// While the backfill runs, a voter created after the upgrade has nothing to count,
+    /// so it is counted from its creation on. Call before creating the voter record.
+    pub(crate) fn internal_count_new_voter(&mut self, voter_id: &VoterId) {
+        if self.mpdao_totals_backfill_index.is_some() && self.voters.get(voter_id).is_none() {
+            self.mpdao_counted_voters.insert(voter_id.clone());
+        }
+    }
+
+    /// whether the voter mpDAO is in the running totals
+    fn internal_counts_mpdao(&mut self, voter_id: &VoterId) -> bool {
+        if is_pseudo_near_address(voter_id) {
+            return false;
+        }
+        if self.mpdao_totals_backfill_index.is_none() {
+            return true;
+        }
+        self.internal_count_new_voter(voter_id);
+        self.mpdao_counted_voters.contains(voter_id)
+    }
+
+    pub(crate) fn internal_add_locked_mpdao(&mut self, voter_id: &VoterId, amount: MpDAOAmount) {
+        if self.internal_counts_mpdao(voter_id) {
+            self.total_locked_mpdao += amount;
+        }
+    }
+
+    pub(crate) fn internal_sub_locked_mpdao(&mut self, voter_id: &VoterId, amount: MpDAOAmount) {
+        if self.internal_counts_mpdao(voter_id) {
+            self.total_locked_mpdao = self.total_locked_mpdao.saturating_sub(amount);
+        }
+    }
+
+    /// `amount` of the unlocking `locking_position` added to the unbonding total and schedule
+    pub(crate) fn internal_add_unbonding_mpdao(
+        &mut self,
+        voter_id: &VoterId,
+        locking_position: &LockingPosition,
+        amount: MpDAOAmount,
+    ) {
+        if !self.internal_counts_mpdao(voter_id) {
+            return;
+        }
+        self.total_unbonding_mpdao += amount;
+        let key = unbonding_schedule_key(locking_position);
+        let scheduled = self.unbonding_schedule.get(&key).unwrap_or(0);
+        self.unbonding_schedule.insert(&key, &(scheduled + amount));
+    }
+
+    pub(crate) fn internal_sub_unbonding_mpdao(
+        &mut self,
+        voter_id: &VoterId,
+        locking_position: &LockingPosition,
+        amount: MpDAOAmount,
+    ) {
+        if !self.internal_counts_mpdao(voter_id) {
+            return;
+        }
+        self.total_unbonding_mpdao = self.total_unbonding_mpdao.saturating_sub(amount);
+        let key = unbonding_schedule_key(locking_position);
+        let scheduled = self.unbonding_schedule.get(&key).unwrap_or(0).saturating_sub(amount);
+        if scheduled == 0 {
+            self.unbonding_schedule.remove(&key);
+        } else {
+            self.unbonding_schedule.insert(&key, &scheduled);
+        }
+    }
+
+    pub(crate) fn internal_add_balance_mpdao(&mut self, voter_id: &VoterId, amount: MpDAOAmount) {
+        if self.internal_counts_mpdao(voter_id) {
+            self.total_balance_mpdao += amount;
+        }
+    }
+
+    pub(crate) fn internal_sub_balance_mpdao(&mut self, voter_id: &VoterId, amount: MpDAOAmount) {
+        if self.internal_counts_mpdao(voter_id) {
+            self.total_balance_mpdao = self.total_balance_mpdao.saturating_sub(amount);
+        }
+    }
+
+    /// fully unlocked positions removed, their amount was added to the voter balance
+    pub(crate) fn internal_cleared_positions(
+        &mut self,
+        voter_id: &VoterId,
+        cleared_positions: &[LockingPosition],
+    ) {
+        for locking_position in cleared_positions {
+            self.internal_sub_unbonding_mpdao(voter_id, locking_position, locking_position.amount);
+            self.internal_add_balance_mpdao(voter_id, locking_position.amount);
+        }
+    }
+
+    /// adds all the voter mpDAO to the totals, used to initialize them after an upgrade
+    fn internal_add_voter_mpdao(&mut self, voter_id: &VoterId, voter: &Voter) {
+        for locking_position in voter.locking_positions.iter() {
+            if locking_position.is_locked() {
+                self.internal_add_locked_mpdao(voter_id, locking_position.amount);
+            } else {
+                self.internal_add_unbonding_mpdao(voter_id, &locking_position, locking_position.amount);
+            }
+        }
+        self.internal_add_balance_mpdao(voter_id, voter.balance);
+    }
+
+    /// unbonding mpDAO whose unbonding period ended (rounded up to the day), not yet cleared
+    pub(crate) fn internal_get_unlocked_mpdao(&self) -> MpDAOAmount {
+        let now = get_current_epoch_millis();
+        self.unbonding_schedule
+            .iter()
+            .filter(|(unlocked_at, _)| *unlocked_at <= now)
+            .map(|(_, amount)| amount)
+            .sum()
+    }
+
+    /// mpDAO owed to voters: locking positions and balances
+    pub(crate) fn internal_get_voters_mpdao(&self) -> MpDAOAmount {
+        self.total_locked_mpdao + self.total_unbonding_mpdao + self.total_balance_mpdao
+    }
+}
+
+#[near_bindgen]
+impl MetaVoteContract {
+    /// Counts the mpDAO of the voters that existed before the upgrade in the running totals,
+    /// `limit` voters per call, from the last index down so removed voters do not move
+    /// uncounted ones behind the cursor. Call again until done.
+    pub fn operator_backfill_mpdao_totals(&mut self, limit: u32) -> BackfillProgressJSON {
+        self.assert_operator();
+        let mut index = self
+            .mpdao_totals_backfill_index
+            .expect("The mpDAO totals are already counted");
+        index = std::cmp::min(index, self.voters.len());
+        let mut counted_voters = 0;
+        for _ in 0..limit {
+            if index == 0 {
+                break;
+            }
+            index -= 1;
+            let voter_id = self.voters.keys_as_vector().get(index).unwrap();
+            if self.mpdao_counted_voters.insert(voter_id.clone()) {
+                let voter = self.voters.get(&voter_id).unwrap();
+                self.internal_add_voter_mpdao(&voter_id, &voter);
+                counted_voters += 1;
+            }
+        }
+        let done = index == 0;
+        self.mpdao_totals_backfill_index = if done { None } else { Some(index) };
+        BackfillProgressJSON {
+            remaining_voters: index,
+            counted_voters,
+            done,
+        }
+    }
+
+    /// [(unlocked_at, mpDAO amount)] of the unbonding positions, sorted by date.
+    /// unlocked_at is the end of the unbonding period rounded up to the day.
+    pub fn get_unbonding_schedule(&self) -> Vec<(EpochMillis, U128String)> {
+        let mut schedule: Vec<(EpochMillis, U128String)> = self
+            .unbonding_schedule
+            .iter()
+            .map(|(unlocked_at, amount)| (unlocked_at, amount.into()))
+            .collect();
+        schedule.sort_by_key(|(unlocked_at, _)| *unlocked_at);
+        schedule
+    }
+}
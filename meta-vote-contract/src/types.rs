@@ -1,4 +1,5 @@
 use uint::construct_uint;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::json_types::U128;
 
@@ -15,6 +16,27 @@ pub type EvmSignature = String;
 pub type EpochMillis = u64;
 pub type PositionIndex = u64;
 
+/// How the voting power of a locking position evolves.
+/// Linear: amount * unbond_days / 60 while locked, zero once the unlock starts.
+/// Decay: locked positions (auto-relock) keep the max voting power, unlocking positions
+/// decay linearly to zero at the end of the unbond period.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum VotingPowerModel {
+    Linear,
+    Decay,
+}
+
+/// where the early withdraw penalties go: kept in the contract, transferred to the treasury,
+/// or released as mpDAO rewards to the voting power (see streaming.rs)
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum PenaltyDestination {
+    Burn,
+    Treasury,
+    Redistribute,
+}
+
 construct_uint! {
     /// 256-bit unsigned integer.
     pub struct U256(4);
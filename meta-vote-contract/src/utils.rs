@@ -30,6 +30,11 @@ pub fn pseudo_near_address(external_address: &String) -> String {
     format!("{}.evmp.near", external_address)
 }
 
+/// mirrored accounts, their locking positions are not backed by mpDAO in this contract
+pub fn is_pseudo_near_address(account_id: &str) -> bool {
+    account_id.ends_with(".evmp.near")
+}
+
 pub fn assert_at_least_1_mpdao(mpdao_amount: MpDAOAmount) {
     assert!(mpdao_amount >= ONE_MPDAO, "amount should be at least 1 mpDAO"); // at least 1 mpDAO
 }
@@ -45,3 +50,199 @@ pub fn calculate_voting_power(mpdao_amount: MpDAOAmount, unbond_days: Days) -> u
     proportional(base_vp, unbond_days.into(), 60) // apply multiplier
 }
 
+/// multiplier in basis points for unbond_days, linear between the curve points
+/// and constant before the first and after the last point.
+/// The curve must be validated: non-empty, increasing days and non-decreasing multipliers.
+pub fn curve_multiplier_bps(curve: &[(Days, u32)], unbond_days: Days) -> u32 {
+    let (first_days, first_bps) = curve[0];
+    if unbond_days <= first_days {
+        return first_bps;
+    }
+    for points in curve.windows(2) {
+        let (from_days, from_bps) = points[0];
+        let (to_days, to_bps) = points[1];
+        if unbond_days <= to_days {
+            let delta = (to_bps - from_bps) as u64 * (unbond_days - from_days) as u64
+                / (to_days - from_days) as u64;
+            return from_bps + delta as u32;
+        }
+    }
+    curve[curve.len() - 1].1
+}
+
+/// Voting power using a multiplier curve of (days, multiplier_bps) points
+pub fn calculate_voting_power_from_curve(
+    mpdao_amount: MpDAOAmount,
+    unbond_days: Days,
+    curve: &[(Days, u32)],
+) -> u128 {
+    let base_vp = mpdao_amount.checked_mul(E18).expect("vp overflow");
+    proportional(
+        base_vp,
+        curve_multiplier_bps(curve, unbond_days).into(),
+        BASIS_POINTS.into(),
+    )
+}
+
+
+/// decodes an hex string, with or without 0x prefix (None if odd length or not hex)
+pub fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// the form of new evm address keys (nonces, delegations and pseudo accounts):
+/// 0x prefixed EIP-55 checksum case, the case existing keys were stored with
+pub fn normalize_evm_address(evm_address: &str) -> String {
+    require!(
+        evm_address.starts_with("0x"),
+        "evm_address must start with 0x"
+    );
+    require!(
+        evm_address.len() == 42 && hex_decode(evm_address).is_some(),
+        "evm_address must be 20 bytes hex"
+    );
+    let hex = evm_address[2..].to_lowercase();
+    let hash = env::keccak256_array(hex.as_bytes());
+    let checksummed: String = hex
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+    format!("0x{}", checksummed)
+}
+
+/// The key `evm_address` is stored with, in any case: as sent, lowercase or checksum case,
+/// the first one where `exists`. The checksum case for new keys.
+pub fn find_evm_address_key(evm_address: &str, exists: impl Fn(&String) -> bool) -> String {
+    let checksummed = normalize_evm_address(evm_address);
+    [evm_address.to_string(), evm_address.to_lowercase()]
+        .into_iter()
+        .find(|key| exists(key))
+        .unwrap_or(checksummed)
+}
+
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// decodes a 32 bytes hex string
+pub fn hex_decode_32(hex: &str) -> Option<[u8; 32]> {
+    hex_decode(hex)?.try_into().ok()
+}
+
+/// merkle distribution leaf: keccak256 of "<index>:<account_id>:<amount>"
+pub fn merkle_leaf(index: u64, account_id: &str, amount: u128) -> [u8; 32] {
+    env::keccak256_array(format!("{}:{}:{}", index, account_id, amount).as_bytes())
+}
+
+/// parent nodes are keccak256 of the two children sorted, so the proof has no left/right flags
+pub fn verify_merkle_proof(leaf: [u8; 32], proof: &[[u8; 32]], root: &[u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| {
+        let (a, b) = if node <= *sibling {
+            (node, *sibling)
+        } else {
+            (*sibling, node)
+        };
+        env::keccak256_array(&[a, b].concat())
+    });
+    computed == *root
+}
+
+/// message the evm address signs to delegate to a near account
+pub fn evm_delegation_message(account_id: &str) -> String {
+    format!("delegate to {}", account_id)
+}
+
+/// recovers the ethereum address that signed the 32 bytes `hash`
+/// signature is 65 bytes hex: r, s, v (v in 27/28 or 0/1)
+pub fn recover_evm_signer(hash: &[u8; 32], signature: &str) -> Option<[u8; 20]> {
+    let signature = hex_decode(signature)?;
+    if signature.len() != 65 {
+        return None;
+    }
+    let v = match signature[64] {
+        27 | 28 => signature[64] - 27,
+        0 | 1 => signature[64],
+        _ => return None,
+    };
+    // malleability_flag rejects high-s signatures, as ethereum does
+    let public_key = env::ecrecover(hash, &signature[..64], v, true)?;
+    let address_hash = env::keccak256_array(&public_key);
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&address_hash[12..]);
+    Some(address)
+}
+
+/// personal_sign (EIP-191) hash of a text message
+pub fn personal_sign_hash(message: &str) -> [u8; 32] {
+    let prefixed = format!("\x19Ethereum Signed Message:\n{}{}", message.len(), message);
+    env::keccak256_array(prefixed.as_bytes())
+}
+
+fn eip712_uint256(value: u64) -> [u8; 32] {
+    let mut result = [0u8; 32];
+    result[24..].copy_from_slice(&value.to_be_bytes());
+    result
+}
+
+/// EIP-712 hash of Delegation(string contract,string delegate,uint256 nonce,uint256 deadline)
+/// with domain EIP712Domain(string name,string version) = ("Meta Vote", "1")
+/// There is no chainId, the NEAR contract account is part of the message.
+pub fn eip712_delegation_hash(
+    contract_id: &str,
+    delegate_id: &str,
+    nonce: u64,
+    deadline: u64,
+) -> [u8; 32] {
+    let domain_separator = env::keccak256_array(
+        &[
+            env::keccak256_array(EIP712_DOMAIN_TYPE.as_bytes()),
+            env::keccak256_array(EIP712_DOMAIN_NAME.as_bytes()),
+            env::keccak256_array(EIP712_DOMAIN_VERSION.as_bytes()),
+        ]
+        .concat(),
+    );
+    let struct_hash = env::keccak256_array(
+        &[
+            env::keccak256_array(EIP712_DELEGATION_TYPE.as_bytes()),
+            env::keccak256_array(contract_id.as_bytes()),
+            env::keccak256_array(delegate_id.as_bytes()),
+            eip712_uint256(nonce),
+            eip712_uint256(deadline),
+        ]
+        .concat(),
+    );
+    env::keccak256_array(&[&[0x19, 0x01][..], &domain_separator, &struct_hash].concat())
+}
+
+/// true if `signature` over `hash` was made by evm_address
+pub fn is_evm_signer(evm_address: &str, hash: &[u8; 32], signature: &str) -> bool {
+    match (hex_decode(evm_address), recover_evm_signer(hash, signature)) {
+        (Some(address), Some(signer)) => address == signer,
+        _ => false,
+    }
+}
+
+/// true if `signature` is a personal_sign of "delegate to <account_id>" by evm_address
+pub fn is_valid_evm_delegation_signature(
+    evm_address: &str,
+    account_id: &str,
+    signature: &str,
+) -> bool {
+    is_evm_signer(
+        evm_address,
+        &personal_sign_hash(&evm_delegation_message(account_id)),
+        signature,
+    )
+}
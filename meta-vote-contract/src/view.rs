@@ -12,6 +12,7 @@ type U128String = U128;
 pub struct ContractInfoJson {
     pub owner_id: String,
     pub operator_id: String,
+    pub treasury_id: String,
     pub voter_count: u64,
     pub min_unbond_period: u16,
     pub max_unbond_period: u16,
@@ -27,6 +28,12 @@ pub struct ContractInfoJson {
     pub accum_distributed_stnear_for_claims: U128String,
     pub total_unclaimed_stnear: U128String,
     pub evm_delegates_count: u64,
+    // mpDAO held for voters, mirrored accounts excluded
+    pub total_locked_mpdao: U128String,
+    pub total_unlocking_mpdao: U128String,
+    pub total_unlocked_mpdao: U128String, // unbonding period ended, not yet cleared
+    pub total_balance_mpdao: U128String,
+    pub total_burned_mpdao: U128String, // early withdraw penalties kept in the contract
 }
 
 #[near_bindgen]
@@ -39,9 +46,11 @@ impl MetaVoteContract {
     }
 
     pub fn get_contract_info(&self) -> ContractInfoJson {
+        let total_unlocked_mpdao = self.internal_get_unlocked_mpdao();
         ContractInfoJson {
             owner_id : self.owner_id.as_str().into(),
             operator_id : self.operator_id.as_str().into(),
+            treasury_id : self.treasury_id.as_str().into(),
             voter_count : self.voters.len(),
             min_unbond_period : self.min_unbond_period,
             max_unbond_period : self.max_unbond_period,
@@ -57,6 +66,11 @@ impl MetaVoteContract {
             accum_distributed_stnear_for_claims : self.accum_distributed_stnear_for_claims.into(),
             total_unclaimed_stnear : self.total_unclaimed_stnear.into(),
             evm_delegates_count : self.evm_delegates.len(),
+            total_locked_mpdao : self.total_locked_mpdao.into(),
+            total_unlocking_mpdao : self.total_unbonding_mpdao.saturating_sub(total_unlocked_mpdao).into(),
+            total_unlocked_mpdao : total_unlocked_mpdao.into(),
+            total_balance_mpdao : self.total_balance_mpdao.into(),
+            total_burned_mpdao : self.total_burned_mpdao.into(),
         }
     }
 
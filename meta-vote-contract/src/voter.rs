@@ -34,8 +34,12 @@ impl Voter {
         }
     }
 
+    /// Note: delegates can have voting power and votes without locking positions
     pub(crate) fn is_empty(&self) -> bool {
-        self.balance == 0 && self.locking_positions.is_empty()
+        self.balance == 0
+            && self.locking_positions.is_empty()
+            && self.available_voting_power == 0
+            && self.vote_positions.is_empty()
     }
 
     pub(crate) fn sum_locked(&self) -> MpDAOAmount {
@@ -68,6 +72,31 @@ impl Voter {
         result
     }
 
+    pub(crate) fn sum_locked_voting_power(&self) -> u128 {
+        let mut result = 0_u128;
+        for locking_position in self.locking_positions.iter() {
+            if locking_position.is_locked() {
+                result += locking_position.voting_power;
+            }
+        }
+        result
+    }
+
+    /// voting power of the unlocking positions at timestamp_ms
+    pub(crate) fn sum_decaying_voting_power(
+        &self,
+        model: VotingPowerModel,
+        timestamp_ms: EpochMillis,
+    ) -> u128 {
+        let mut result = 0_u128;
+        for locking_position in self.locking_positions.iter() {
+            if !locking_position.is_locked() {
+                result += locking_position.voting_power_at(model, timestamp_ms);
+            }
+        }
+        result
+    }
+
     pub(crate) fn sum_used_votes(&self) -> u128 {
         let mut result = 0_u128;
         for map in self.vote_positions.values() {
@@ -153,8 +182,13 @@ impl Voter {
         }
     }
 
-    // clear SEVERAL fully unlocked positions
-    pub fn clear_fully_unlocked_positions(&mut self, position_index_list: Vec<PositionIndex>) {
+    // clear SEVERAL fully unlocked positions, returns the cleared positions
+    pub fn clear_fully_unlocked_positions(
+        &mut self,
+        voter_id: &VoterId,
+        position_index_list: Vec<PositionIndex>,
+    ) -> Vec<LockingPosition> {
+        let mut cleared_positions = Vec::new();
         let mut position_index_list = position_index_list;
         position_index_list.sort();
         position_index_list.reverse();
@@ -164,8 +198,16 @@ impl Voter {
             if locking_position.is_unlocked() {
                 self.balance += locking_position.amount;
                 self.remove_position(index);
+                Event::Clear {
+                    voter_id,
+                    index,
+                    amount: locking_position.amount.into(),
+                }
+                .emit();
+                cleared_positions.push(locking_position);
             }
         }
+        cleared_positions
     }
 
 }
@@ -1,6 +1,9 @@
 use crate::interface::*;
 use crate::*;
-use near_sdk::{assert_one_yocto, json_types::U128, near_bindgen, Promise, PromiseResult};
+use near_sdk::{
+    assert_one_yocto, is_promise_success, json_types::U128, near_bindgen, serde_json, Promise,
+    PromiseResult,
+};
 
 #[near_bindgen]
 impl MetaVoteContract {
@@ -8,18 +11,24 @@ impl MetaVoteContract {
     // * Withdraw *
     // ************
 
-    fn internal_withdraw(
+    pub(crate) fn internal_withdraw(
         &mut self,
         voter_id: &String,
+        receiver_id: &String,
         position_index_list: Vec<PositionIndex>,
         optional_amount_to_withdraw: Option<u128>,
+        msg: Option<String>,
     ) {
         assert_one_yocto();
+        self.internal_ensure_voter_checkpoints(voter_id);
         let mut voter = self.internal_get_voter_or_panic(&voter_id);
         // Clear locking positions, and increase the voter balance.
         if position_index_list.len() > 0 {
-            voter.clear_fully_unlocked_positions(position_index_list);
+            let cleared_positions =
+                voter.clear_fully_unlocked_positions(voter_id, position_index_list);
+            self.internal_cleared_positions(voter_id, &cleared_positions);
         }
+        self.internal_sync_voting_power(voter_id, &mut voter);
         let total_to_withdraw = optional_amount_to_withdraw.unwrap_or(voter.balance);
         require!(total_to_withdraw > 0, "Nothing to withdraw.");
         assert!(
@@ -30,14 +39,26 @@ impl MetaVoteContract {
         );
 
         voter.balance -= total_to_withdraw;
+        self.internal_sub_balance_mpdao(voter_id, total_to_withdraw);
 
-        if voter.is_empty() {
+        let voter_removed = voter.is_empty();
+        if voter_removed {
+            let initial_storage_usage = env::storage_usage();
             self.voters.remove(&voter_id);
-            log!("GODSPEED: {} is no longer part of Meta Vote!", &voter_id);
+            self.auto_compound.remove(voter_id);
+            self.internal_record_storage(voter_id, initial_storage_usage);
+            self.internal_refund_storage(voter_id);
         } else {
             self.voters.insert(&voter_id, &voter);
         }
-        self.transfer_mpdao_to_voter(voter_id, total_to_withdraw);
+        Event::Withdraw {
+            voter_id,
+            receiver_id,
+            amount: total_to_withdraw.into(),
+            voter_removed,
+        }
+        .emit();
+        self.transfer_mpdao_to_voter(voter_id, receiver_id, total_to_withdraw, msg);
     }
 
     #[payable]
@@ -48,7 +69,28 @@ impl MetaVoteContract {
     ) {
         let amount_to_withdraw = amount_from_balance.0;
         let voter_id = env::predecessor_account_id().as_str().to_string();
-        self.internal_withdraw(&voter_id, position_index_list, if amount_to_withdraw==0 {None} else {Some(amount_to_withdraw)});
+        self.internal_withdraw(&voter_id, &voter_id, position_index_list, if amount_to_withdraw==0 {None} else {Some(amount_to_withdraw)}, None);
+    }
+
+    /// Withdraws to `receiver_id`, with ft_transfer_call if there is a `msg`.
+    /// The amount not used by the receiver is restored to the voter balance.
+    #[payable]
+    pub fn withdraw_to(
+        &mut self,
+        receiver_id: AccountId,
+        position_index_list: Vec<PositionIndex>,
+        amount: U128String,
+        msg: Option<String>,
+    ) {
+        let voter_id = env::predecessor_account_id().as_str().to_string();
+        let amount_to_withdraw = if amount.0 == 0 { None } else { Some(amount.0) };
+        self.internal_withdraw(
+            &voter_id,
+            &receiver_id.into(),
+            position_index_list,
+            amount_to_withdraw,
+            msg,
+        );
     }
 
     #[payable]
@@ -56,36 +98,60 @@ impl MetaVoteContract {
         let voter_id = env::predecessor_account_id().as_str().to_string();
         let voter = self.internal_get_voter_or_panic(&voter_id);
         let position_index_list = voter.get_unlocked_position_indexes();
-        self.internal_withdraw(&voter_id, position_index_list, None);
+        self.internal_withdraw(&voter_id, &voter_id, position_index_list, None, None);
     }
 
     // *************************
     // * Internals & callbacks *
     // *************************
 
-    pub(crate) fn transfer_mpdao_to_voter(&mut self, voter_id: &String, amount: MpDAOAmount) {
-        ext_ft::ext(self.mpdao_token_contract_address.clone())
-            .with_static_gas(GAS_FOR_FT_TRANSFER)
-            .with_attached_deposit(1)
-            .ft_transfer(voter_id.clone(), U128::from(amount), None)
-            .then(
-                Self::ext(env::current_account_id())
-                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
-                    .after_transfer_mpdao_callback(voter_id.clone(), U128::from(amount)),
-            );
+    pub(crate) fn transfer_mpdao_to_voter(
+        &mut self,
+        voter_id: &String,
+        receiver_id: &String,
+        amount: MpDAOAmount,
+        msg: Option<String>,
+    ) {
+        self.mpdao_in_transit += amount;
+        let token =
+            ext_ft::ext(self.mpdao_token_contract_address.clone()).with_attached_deposit(1);
+        let transfer = match msg {
+            Some(msg) => token.with_static_gas(GAS_FOR_FT_TRANSFER_CALL).ft_transfer_call(
+                AccountId::new_unchecked(receiver_id.clone()),
+                U128::from(amount),
+                None,
+                msg,
+            ),
+            None => token
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .ft_transfer(receiver_id.clone(), U128::from(amount), None),
+        };
+        transfer.then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                .after_transfer_mpdao_callback(voter_id.clone(), U128::from(amount)),
+        );
     }
 
+    /// ft_transfer_call returns the amount used by the receiver, the refund is restored
     #[private]
     pub fn after_transfer_mpdao_callback(&mut self, voter_id: VoterId, amount: U128) {
         let amount = amount.0;
+        self.mpdao_in_transit -= amount;
         match env::promise_result(0) {
             PromiseResult::NotReady => unreachable!(),
-            PromiseResult::Successful(_) => {
+            PromiseResult::Successful(value) => {
+                // ft_transfer returns nothing, all was transferred
+                let used = serde_json::from_slice::<U128>(&value)
+                    .map_or(amount, |used| std::cmp::min(used.0, amount));
                 log!(
-                    "WITHDRAW: {} mpDAO transfer to {}",
-                    amount,
+                    "WITHDRAW: {} mpDAO transfer from {}",
+                    used,
                     voter_id.to_string()
                 );
+                if used < amount {
+                    self.restore_transfer_to_mpdao(amount - used, voter_id);
+                }
             }
             PromiseResult::Failed => {
                 log!(
@@ -99,18 +165,36 @@ impl MetaVoteContract {
     }
 
     fn restore_transfer_to_mpdao(&mut self, amount: Balance, voter_id: VoterId) {
+        let initial_storage_usage = env::storage_usage();
         let mut voter = self.internal_get_voter(&voter_id);
         voter.balance += amount;
         self.voters.insert(&voter_id, &voter);
+        // the voter could have been removed and its storage refunded
+        self.internal_record_storage(&voter_id, initial_storage_usage);
+        self.internal_add_balance_mpdao(&voter_id, amount);
+        Event::WithdrawFailed {
+            voter_id: &voter_id,
+            amount: amount.into(),
+        }
+        .emit();
     }
 
     /// This transfer is only to claim available stNEAR
     pub(crate) fn transfer_stnear_to_voter(
-        &self,
+        &mut self,
         source: &String,
         receiver: &String,
         amount: Balance,
+        consumed_claims: Vec<ConsumedClaim>,
     ) -> Promise {
+        Event::Claim {
+            voter_id: source,
+            receiver_id: receiver,
+            token: self.stnear_token_contract_address.as_str(),
+            amount: amount.into(),
+        }
+        .emit();
+        self.stnear_in_transit += amount;
         ext_ft::ext(self.stnear_token_contract_address.clone())
             .with_static_gas(GAS_FOR_FT_TRANSFER)
             .with_attached_deposit(1)
@@ -118,13 +202,19 @@ impl MetaVoteContract {
             .then(
                 Self::ext(env::current_account_id())
                     .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
-                    .after_transfer_stnear_callback(&source, U128::from(amount)),
+                    .after_transfer_stnear_callback(&source, U128::from(amount), consumed_claims),
             )
     }
 
     #[private]
-    pub fn after_transfer_stnear_callback(&mut self, source: &String, amount: U128) {
+    pub fn after_transfer_stnear_callback(
+        &mut self,
+        source: &String,
+        amount: U128,
+        consumed_claims: Vec<ConsumedClaim>,
+    ) {
         let amount = amount.0;
+        self.stnear_in_transit -= amount;
         match env::promise_result(0) {
             PromiseResult::NotReady => unreachable!(),
             PromiseResult::Successful(_) => {
@@ -137,7 +227,138 @@ impl MetaVoteContract {
                     source
                 );
                 self.add_claimable_stnear(source, amount);
+                let token_id = self.stnear_token_contract_address.clone();
+                self.internal_restore_claim_batches(&token_id, source, &consumed_claims, amount);
+                Event::ClaimFailed {
+                    voter_id: source,
+                    token: self.stnear_token_contract_address.as_str(),
+                    amount: amount.into(),
+                }
+                .emit();
             }
         };
     }
+
+    // ******************
+    // * Owner withdraw *
+    // ******************
+
+    /// Transfers to the owner mpDAO held by the contract in excess of what is owed
+    /// to voters (balances, locking positions, unclaimed and in transit),
+    /// keeping `min_deposit_amount` for deposits not yet recorded.
+    #[payable]
+    pub fn owner_withdraw_mpdao(&mut self, mpdao_amount: U128String) -> Promise {
+        assert_one_yocto();
+        self.assert_only_owner();
+        // the mpDAO owed to voters is not known until then
+        require!(
+            self.mpdao_totals_backfill_index.is_none(),
+            "The mpDAO totals backfill is not done"
+        );
+        self.internal_owner_withdraw(self.mpdao_token_contract_address.clone(), mpdao_amount)
+    }
+
+    /// Transfers to the owner stNEAR held by the contract in excess of the
+    /// unclaimed stNEAR and the stNEAR in transit.
+    #[payable]
+    pub fn owner_withdraw_stnear(&mut self, stnear_amount: U128String) -> Promise {
+        assert_one_yocto();
+        self.assert_only_owner();
+        self.internal_owner_withdraw(self.stnear_token_contract_address.clone(), stnear_amount)
+    }
+
+    #[private]
+    pub fn after_owner_balance_of_callback(&mut self, token_id: AccountId, amount: U128) -> Promise {
+        let balance = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<U128>(&value)
+                    .expect("Invalid ft_balance_of result")
+                    .0
+            }
+            _ => panic!("ft_balance_of failed"),
+        };
+        let owed = self.internal_owed_balance(&token_id);
+        // a deposit (ft_transfer_call) can be in the balance before ft_on_transfer records it
+        let reserved = if token_id == self.mpdao_token_contract_address {
+            self.min_deposit_amount
+        } else {
+            0
+        };
+        let excess = balance.saturating_sub(owed).saturating_sub(reserved);
+        assert!(
+            amount.0 <= excess,
+            "Not enough excess balance. Balance {}, owed to voters {}, reserved for deposits {}, requested {}.",
+            balance,
+            owed,
+            reserved,
+            amount.0
+        );
+        Event::OwnerWithdraw {
+            token: token_id.as_str(),
+            amount,
+            balance: balance.into(),
+            owed: owed.into(),
+        }
+        .emit();
+        ext_ft::ext(token_id)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .with_attached_deposit(1)
+            .ft_transfer(self.owner_id.to_string(), amount, None)
+    }
+
+    #[private]
+    pub fn after_owner_withdraw_callback(&mut self, token_id: AccountId, amount: U128) {
+        self.owner_withdraw_in_transit = None;
+        if !is_promise_success() {
+            log!("FAILED: owner withdraw of {} {} not transferred.", amount.0, token_id);
+        }
+    }
+}
+
+impl MetaVoteContract {
+    /// token balance the contract must keep for voters
+    pub(crate) fn internal_owed_balance(&self, token_id: &AccountId) -> Balance {
+        if *token_id == self.mpdao_token_contract_address {
+            self.internal_get_voters_mpdao()
+                + self.total_unclaimed_mpdao
+                + self.mpdao_in_transit
+                + self.internal_get_stream_owed(token_id)
+                + self.internal_get_incentive_pools_unclaimed(token_id)
+                + self.internal_get_merkle_unclaimed(token_id)
+                + self.total_burned_mpdao
+        } else if *token_id == self.stnear_token_contract_address {
+            self.total_unclaimed_stnear
+                + self.stnear_in_transit
+                + self.internal_get_stream_owed(token_id)
+                + self.internal_get_incentive_pools_unclaimed(token_id)
+                + self.internal_get_merkle_unclaimed(token_id)
+        } else {
+            panic!("Unknown token address: {}", token_id)
+        }
+    }
+
+    /// One owner withdraw at a time, reserved until its transfer resolves, so the
+    /// balance read by another one can not count the amount in flight as excess.
+    fn internal_owner_withdraw(&mut self, token_id: AccountId, amount: U128String) -> Promise {
+        require!(amount.0 > 0, "Amount must be greater than zero.");
+        require!(
+            self.owner_withdraw_in_transit.is_none(),
+            "An owner withdraw is in progress."
+        );
+        self.owner_withdraw_in_transit = Some((token_id.clone(), amount.0));
+        ext_ft::ext(token_id.clone())
+            .with_static_gas(GAS_FOR_FT_BALANCE_OF)
+            .ft_balance_of(env::current_account_id())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_OWNER_WITHDRAW_CALLBACK)
+                    .after_owner_balance_of_callback(token_id.clone(), amount),
+            )
+            // also resolves when the balance check fails
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .after_owner_withdraw_callback(token_id, amount),
+            )
+    }
 }
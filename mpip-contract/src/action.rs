@@ -0,0 +1,238 @@
+use crate::*;
+use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::Gas;
+
+// ///////////////////
+// Proposal actions //
+// ///////////////////
+
+/// On-chain action performed by the MPIP contract when an accepted proposal is executed.
+/// Deposits and transfers are paid from the MPIP contract (treasury) balance.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum MpipAction {
+    FunctionCall {
+        receiver_id: AccountId,
+        method_name: String,
+        args: Base64VecU8,
+        deposit: U128,
+        gas: U64,
+    },
+    /// NEAR transfer
+    Transfer {
+        receiver_id: AccountId,
+        amount: U128,
+    },
+    /// NEP-141 `ft_transfer`, the receiver must be registered in the token contract
+    FtTransfer {
+        token_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    },
+}
+
+impl MpipAction {
+    /// Gas attached to the action promise.
+    pub(crate) fn gas(&self) -> Gas {
+        match self {
+            MpipAction::FunctionCall { gas, .. } => Gas(gas.0),
+            MpipAction::Transfer { .. } => Gas(0),
+            MpipAction::FtTransfer { .. } => GAS_FOR_FT_TRANSFER,
+        }
+    }
+
+    pub(crate) fn assert_valid(&self) {
+        match self {
+            MpipAction::FunctionCall {
+                receiver_id,
+                method_name,
+                gas,
+                ..
+            } => {
+                // the actions are performed by the MPIP account, private fns included
+                require!(
+                    *receiver_id != env::current_account_id(),
+                    "Actions cannot call the MPIP contract."
+                );
+                require!(!method_name.is_empty(), "Action method name is empty.");
+                require!(gas.0 > 0, "Action gas must be greater than zero.");
+                require!(
+                    gas.0 <= MAX_GAS_PER_ACTION.0,
+                    format!("Action gas exceeds the max of {} TGas.", MAX_GAS_PER_ACTION.0 / TGAS)
+                );
+            }
+            MpipAction::Transfer { amount, .. } | MpipAction::FtTransfer { amount, .. } => {
+                require!(amount.0 > 0, "Transfer amount must be greater than zero.");
+            }
+        }
+    }
+
+    pub(crate) fn to_promise(&self) -> Promise {
+        match self {
+            MpipAction::FunctionCall {
+                receiver_id,
+                method_name,
+                args,
+                deposit,
+                gas,
+            } => Promise::new(receiver_id.clone()).function_call(
+                method_name.clone(),
+                args.0.clone(),
+                deposit.0,
+                Gas(gas.0),
+            ),
+            MpipAction::Transfer {
+                receiver_id,
+                amount,
+            } => Promise::new(receiver_id.clone()).transfer(amount.0),
+            MpipAction::FtTransfer {
+                token_id,
+                receiver_id,
+                amount,
+                memo,
+            } => ext_ft_core::ext(token_id.clone())
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .with_attached_deposit(1)
+                .ft_transfer(receiver_id.clone(), *amount, memo.clone()),
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ActionStatus {
+    Pending,
+    Succeeded,
+    Failed,
+    /// not performed, a previous action failed
+    Skipped,
+}
+
+/// Result of the execution of the proposal actions, one status per action.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MpipExecution {
+    pub executed_at: EpochMillis,
+    pub results: Vec<ActionStatus>,
+}
+
+impl MpipExecution {
+    pub(crate) fn has_failed(&self) -> bool {
+        self.results.contains(&ActionStatus::Failed)
+    }
+
+    pub(crate) fn is_pending(&self) -> bool {
+        self.results.contains(&ActionStatus::Pending)
+    }
+}
+
+/// Gas to perform the actions in sequence, each one with its callback.
+fn actions_gas(actions: &[MpipAction]) -> u64 {
+    actions
+        .iter()
+        .map(|action| action.gas().0 + GAS_FOR_RESOLVE_ACTION.0)
+        .sum()
+}
+
+impl MpipContract {
+    pub(crate) fn assert_valid_actions(&self, actions: &[MpipAction]) {
+        require!(
+            actions.len() <= MAX_ACTIONS_PER_MPIP,
+            format!("A proposal can have at most {} actions.", MAX_ACTIONS_PER_MPIP)
+        );
+        for action in actions.iter() {
+            action.assert_valid();
+        }
+        let total_gas = actions_gas(actions);
+        require!(
+            total_gas <= MAX_GAS_FOR_ACTIONS.0,
+            format!(
+                "Proposal actions require more than {} TGas.",
+                MAX_GAS_FOR_ACTIONS.0 / TGAS
+            )
+        );
+    }
+
+    pub(crate) fn internal_get_proposal_actions(&self, mpip_id: MpipId) -> Vec<MpipAction> {
+        self.proposal_actions.get(&mpip_id).unwrap_or_default()
+    }
+
+    pub(crate) fn internal_set_proposal_actions(
+        &mut self,
+        mpip_id: MpipId,
+        actions: Vec<MpipAction>,
+    ) {
+        if actions.is_empty() {
+            self.proposal_actions.remove(&mpip_id);
+        } else {
+            self.proposal_actions.insert(&mpip_id, &actions);
+        }
+    }
+
+    /// Starts the timelock of an accepted proposal.
+    pub(crate) fn internal_queue_proposal(&mut self, mpip_id: MpipId) {
+        let eta = get_current_epoch_millis() + self.timelock_delay;
+        self.queued_eta.insert(&mpip_id, &eta);
+        self.queued_grace_period.insert(&mpip_id, &self.grace_period);
+        log!("MPIP {} queued, eta {}", mpip_id, eta);
+    }
+
+    pub(crate) fn assert_proposal_is_executable(&self, mpip_id: MpipId) {
+        require!(
+            matches!(self.internal_get_proposal_state(mpip_id), MpipState::Queued),
+            "Proposal is not queued"
+        );
+        require!(
+            get_current_epoch_millis() >= self.queued_eta.get(&mpip_id).unwrap(),
+            "Proposal timelock has not finished"
+        );
+    }
+
+    /// Marks the proposal as executed and performs the first action. Each action callback
+    /// records its result and performs the next action, see `execute_action_callback`.
+    pub(crate) fn internal_execute_proposal(&mut self, mpip_id: MpipId) {
+        let actions = self.internal_get_proposal_actions(mpip_id);
+        let required_gas = actions_gas(&actions);
+        require!(
+            env::prepaid_gas().0 - env::used_gas().0 >= required_gas + GAS_FOR_EXECUTE_PROPOSAL.0,
+            format!(
+                "Not enough gas to execute the proposal actions, {} TGas required.",
+                (required_gas + GAS_FOR_EXECUTE_PROPOSAL.0) / TGAS
+            )
+        );
+
+        let mut proposal = self.internal_get_proposal(&mpip_id);
+        proposal.executed = true;
+        self.proposals.insert(&mpip_id, &proposal);
+        self.executions.insert(
+            &mpip_id,
+            &MpipExecution {
+                executed_at: get_current_epoch_millis(),
+                results: vec![ActionStatus::Pending; actions.len()],
+            },
+        );
+        log!("Executing MPIP {} with {} actions", mpip_id, actions.len());
+        if !actions.is_empty() {
+            self.internal_execute_action(mpip_id, 0, &actions);
+        }
+    }
+
+    /// Performs the action, its callback carries the gas of the actions after it.
+    pub(crate) fn internal_execute_action(
+        &self,
+        mpip_id: MpipId,
+        action_index: usize,
+        actions: &[MpipAction],
+    ) {
+        let callback_gas = GAS_FOR_RESOLVE_ACTION.0 + actions_gas(&actions[action_index + 1..]);
+        actions[action_index].to_promise().then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(Gas(callback_gas))
+                .execute_action_callback(mpip_id, action_index as u32),
+        );
+    }
+}
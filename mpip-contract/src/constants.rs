@@ -1,4 +1,5 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use crate::types::EpochMillis;
 use near_sdk::{BorshStorageKey, CryptoHash, Gas};
 
 pub const TGAS: u64 = 1_000_000_000_000;
@@ -8,6 +9,22 @@ pub const ONE_HUNDRED: u16 = 10_000;
 /// Amount of gas for fungible token transfers.
 pub const GAS_FOR_GET_VOTING_POWER: Gas = Gas(10 * TGAS);
 pub const GAS_FOR_RESOLVE_VOTE: Gas = Gas(11 * TGAS);
+pub const GAS_FOR_FT_TRANSFER: Gas = Gas(10 * TGAS);
+pub const GAS_FOR_RESOLVE_ACTION: Gas = Gas(5 * TGAS);
+/// Gas used by `execute_proposal` itself, besides the gas attached to the actions.
+pub const GAS_FOR_EXECUTE_PROPOSAL: Gas = Gas(20 * TGAS);
+
+/// Limits for the actions attached to a proposal, all of them run in a single transaction.
+pub const MAX_ACTIONS_PER_MPIP: usize = 10;
+pub const MAX_GAS_PER_ACTION: Gas = Gas(150 * TGAS);
+pub const MAX_GAS_FOR_ACTIONS: Gas = Gas(250 * TGAS);
+
+/// Timelock defaults: accepted proposals wait 2 days before execution,
+/// and can be executed during 14 days after that.
+pub const DEFAULT_TIMELOCK_DELAY: EpochMillis = 2 * 24 * 60 * 60 * 1000;
+pub const DEFAULT_GRACE_PERIOD: EpochMillis = 14 * 24 * 60 * 60 * 1000;
+/// The guardian and token holders always get at least 1 day to react to a queued proposal.
+pub const MIN_TIMELOCK_DELAY: EpochMillis = 24 * 60 * 60 * 1000;
 
 #[derive(BorshSerialize, BorshDeserialize, BorshStorageKey)]
 pub enum StorageKey {
@@ -17,4 +34,8 @@ pub enum StorageKey {
     Voters,
     Proposers,
     Votes { hash_id: CryptoHash },
+    ProposalActions,
+    Executions,
+    QueuedEta,
+    QueuedGracePeriod,
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use crate::types::{MpipId, VoterId};
+use crate::types::{EpochMillis, MpipId, VoterId};
 use crate::*;
 use near_sdk::json_types::U128;
 use near_sdk::{ext_contract, AccountId};
@@ -8,6 +8,9 @@ pub trait ExtMetaVote {
     fn get_available_voting_power(&self, voter_id: VoterId);
     fn get_all_locking_positions(&self, voter_id: VoterId);
     fn get_total_voting_power(&self);
+    // historical voting power, for snapshot-based voting
+    fn get_voting_power_at(&self, voter_id: VoterId, timestamp_ms: EpochMillis);
+    fn get_total_voting_power_at(&self, timestamp_ms: EpochMillis);
 }
 
 #[ext_contract(ext_self)]
@@ -27,5 +30,8 @@ pub trait SelfMetaVote {
         body: String,
         data: String,
         extra: String,
+        actions: Option<Vec<MpipAction>>,
     );
+
+    fn execute_action_callback(&mut self, mpip_id: MpipId, action_index: u32);
 }
@@ -19,6 +19,13 @@ impl MpipContract {
         );
     }
 
+    pub(crate) fn assert_only_guardian(&self) {
+        require!(
+            self.guardian_id == env::signer_account_id(),
+            "Only the guardian can call this function."
+        );
+    }
+
     pub(crate) fn assert_only_operator_or_creator(&self, mpip_id: MpipId) {
         let proposal = self.internal_get_proposal(&mpip_id);
         require!(
@@ -154,7 +161,7 @@ impl MpipContract {
             .expect("Voter has no voted on this proposal")
     }
 
-    pub(crate) fn internal_get_total_voting_power_from_promise(&self) -> Balance {
+    pub(crate) fn internal_get_voting_power_from_promise(&self) -> Balance {
         require!(
             env::promise_results_count() == 1,
             "This is a callback method."
@@ -1,20 +1,25 @@
 use crate::constants::*;
 use crate::interface::*;
-use mpip::{Mpip, MpipJSON, MpipState};
+use action::{ActionStatus, MpipAction, MpipExecution};
+use mpip::{Mpip, MpipJSON, MpipState, NewMpipParams};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::unordered_map::UnorderedMap;
 use near_sdk::json_types::U128;
 use near_sdk::json_types::U64;
-use near_sdk::{env, log, near_bindgen, require, AccountId, Balance, PanicOnDefault, Promise};
+use near_sdk::{
+    env, log, near_bindgen, require, AccountId, Balance, PanicOnDefault, Promise, PromiseResult,
+};
 use types::*;
 use utils::get_current_epoch_millis;
 use vote::{Vote, VoteJson, VoteType};
 use vote_counting::{ProposalVote, ProposalVoteJson};
 use voter::{Voter, VoterJson};
 
+mod action;
 mod constants;
 mod interface;
 mod internal;
+mod migrate;
 mod mpip;
 mod types;
 mod utils;
@@ -58,6 +63,20 @@ pub struct MpipContract {
     /// If a quorum is set to 50%, this means that 50% of all circulating $mpDAO need to vote yes for the proposal to pass.
     // Percent is denominated in basis points 100% equals 10_000 basis points.
     pub quorum_floor: BasisPoints,
+
+    /// On-chain actions performed when the proposal is executed.
+    pub proposal_actions: UnorderedMap<MpipId, Vec<MpipAction>>,
+    pub executions: UnorderedMap<MpipId, MpipExecution>,
+
+    /// Timelock between the acceptance and the execution of a proposal.
+    /// Queued proposals are executable from the eta until eta + grace_period,
+    /// and the guardian can veto them before execution.
+    pub timelock_delay: EpochMillis,
+    pub grace_period: EpochMillis,
+    pub guardian_id: AccountId,
+    pub queued_eta: UnorderedMap<MpipId, EpochMillis>,
+    /// grace period when the proposal was queued
+    pub queued_grace_period: UnorderedMap<MpipId, EpochMillis>,
 }
 
 #[near_bindgen]
@@ -80,6 +99,7 @@ impl MpipContract {
         );
 
         Self {
+            guardian_id: admin_id.clone(),
             admin_id,
             operator_id,
             meta_token_contract_address,
@@ -97,6 +117,12 @@ impl MpipContract {
             votes: UnorderedMap::new(StorageKey::MpipVotes),
             voters: UnorderedMap::new(StorageKey::Voters),
             proposers: UnorderedMap::new(StorageKey::Proposers),
+            proposal_actions: UnorderedMap::new(StorageKey::ProposalActions),
+            executions: UnorderedMap::new(StorageKey::Executions),
+            timelock_delay: DEFAULT_TIMELOCK_DELAY,
+            grace_period: DEFAULT_GRACE_PERIOD,
+            queued_eta: UnorderedMap::new(StorageKey::QueuedEta),
+            queued_grace_period: UnorderedMap::new(StorageKey::QueuedGracePeriod),
         }
     }
 
@@ -122,6 +148,30 @@ impl MpipContract {
         self.admin_id = new_value;
     }
 
+    /// Update the Guardian role, who can veto queued proposals.
+    pub fn update_guardian_role(&mut self, new_value: AccountId) {
+        self.assert_only_admin();
+        self.guardian_id = new_value;
+    }
+
+    /// Update the timelock delay in milliseconds, applies to proposals queued after the change.
+    pub fn update_timelock_delay(&mut self, new_value: U64) {
+        self.assert_only_admin();
+        require!(
+            new_value.0 >= MIN_TIMELOCK_DELAY,
+            format!("Timelock delay must be at least {} ms.", MIN_TIMELOCK_DELAY)
+        );
+        self.timelock_delay = new_value.0;
+    }
+
+    /// Update the grace period in milliseconds to execute a queued proposal,
+    /// applies to proposals queued after the change.
+    pub fn update_grace_period(&mut self, new_value: U64) {
+        self.assert_only_admin();
+        require!(new_value.0 > 0, "Grace period must be greater than zero.");
+        self.grace_period = new_value.0;
+    }
+
     pub fn pay_to_account(&mut self, amount: U128, to: AccountId) -> Promise {
         self.assert_only_admin();
         Promise::new(to).transfer(amount.0)
@@ -174,27 +224,35 @@ impl MpipContract {
     // *  *
     // ************
 
+    /// The voting power snapshot is taken at the vote start timestamp:
+    /// the quorum and every vote use the voting power at that moment.
+    /// With the Decay voting power model, sync the voters in Meta Vote first
+    /// (`operator_sync_voting_power`) so the snapshot includes the decay.
     pub fn start_voting_period(&mut self, mpip_id: MpipId) {
         self.assert_only_operator_or_creator(mpip_id);
         self.assert_proposal_is_active_or_draft(mpip_id);
+        let vote_start_timestamp = get_current_epoch_millis();
         ext_metavote::ext(self.meta_vote_contract_address.clone())
             .with_static_gas(GAS_FOR_GET_VOTING_POWER)
             .with_attached_deposit(1)
-            .get_total_voting_power()
+            .get_total_voting_power_at(vote_start_timestamp)
             .then(
                 Self::ext(env::current_account_id())
                     .with_static_gas(GAS_FOR_RESOLVE_VOTE)
-                    .start_voting_period_callback(mpip_id),
+                    .start_voting_period_callback(mpip_id, vote_start_timestamp),
             );
     }
 
     #[private]
-    pub fn start_voting_period_callback(&mut self, mpip_id: MpipId) {
-        let total_voting_power = self.internal_get_total_voting_power_from_promise();
+    pub fn start_voting_period_callback(
+        &mut self,
+        mpip_id: MpipId,
+        vote_start_timestamp: EpochMillis,
+    ) {
+        let total_voting_power = self.internal_get_voting_power_from_promise();
         let mut proposal = self.internal_get_proposal(&mpip_id);
-        let now = get_current_epoch_millis();
-        proposal.vote_start_timestamp = Some(now);
-        proposal.vote_end_timestamp = Some(now + self.voting_period);
+        proposal.vote_start_timestamp = Some(vote_start_timestamp);
+        proposal.vote_end_timestamp = Some(vote_start_timestamp + self.voting_period);
         proposal.draft = false;
         proposal.v_power_quorum_to_reach = Some(self.internal_get_quorum(total_voting_power));
         self.proposals.insert(&mpip_id, &proposal);
@@ -204,6 +262,7 @@ impl MpipContract {
     // * Proposal creators functions *
     // *********
 
+    /// `actions` are performed on-chain if the proposal is accepted, see `execute_proposal`.
     #[payable]
     pub fn create_proposal(
         &mut self,
@@ -212,9 +271,13 @@ impl MpipContract {
         body: String,
         data: String,
         extra: String,
+        actions: Option<Vec<MpipAction>>,
     ) {
         self.assert_open_for_new_mpips();
         self.assert_proposal_storage_is_covered();
+        if let Some(actions) = &actions {
+            self.assert_valid_actions(actions);
+        }
         ext_metavote::ext(self.meta_vote_contract_address.clone())
             .with_static_gas(GAS_FOR_GET_VOTING_POWER)
             .with_attached_deposit(1)
@@ -222,7 +285,14 @@ impl MpipContract {
             .then(
                 Self::ext(env::current_account_id())
                     .with_static_gas(GAS_FOR_RESOLVE_VOTE)
-                    .create_proposal_callback(title, short_description, body, data, extra),
+                    .create_proposal_callback(
+                        title,
+                        short_description,
+                        body,
+                        data,
+                        extra,
+                        actions,
+                    ),
             );
     }
 
@@ -234,11 +304,22 @@ impl MpipContract {
         body: String,
         data: String,
         extra: String,
+        actions: Option<Vec<MpipAction>>,
     ) -> MpipId {
         let total_v_power = self.internal_get_user_total_voting_power_from_promise();
         self.assert_proposal_threshold(total_v_power);
         let id = self.proposals.len() as MpipId;
-        self.internal_create_proposal(id, title, short_description, body, data, extra);
+        self.internal_create_proposal(
+            id,
+            NewMpipParams {
+                title,
+                short_description,
+                body,
+                data,
+                extra,
+                actions: actions.unwrap_or_default(),
+            },
+        );
         id
     }
 
@@ -270,6 +351,14 @@ impl MpipContract {
         self.proposals.insert(&mpip_id, &proposal);
     }
 
+    /// Replaces the actions performed if the proposal is accepted.
+    pub fn update_proposal_actions(&mut self, mpip_id: MpipId, actions: Vec<MpipAction>) {
+        self.assert_only_creator(mpip_id);
+        self.assert_proposal_is_active_or_draft(mpip_id);
+        self.assert_valid_actions(&actions);
+        self.internal_set_proposal_actions(mpip_id, actions);
+    }
+
     pub fn update_meta_vote_contract_address(&mut self, new_meta_vote_contract_address: AccountId) {
         self.assert_only_admin();
         self.meta_vote_contract_address = new_meta_vote_contract_address;
@@ -365,6 +454,36 @@ impl MpipContract {
         self.internal_proposal_is_active_or_draft(mpip_id)
     }
 
+    /// Timestamp from which a queued proposal can be executed.
+    pub fn get_proposal_eta(&self, mpip_id: MpipId) -> Option<EpochMillis> {
+        self.queued_eta.get(&mpip_id)
+    }
+
+    pub fn get_timelock_delay(&self) -> U64 {
+        U64::from(self.timelock_delay)
+    }
+
+    pub fn get_grace_period(&self) -> U64 {
+        U64::from(self.grace_period)
+    }
+
+    /// Grace period of a queued proposal, executable until eta + grace period.
+    pub fn get_proposal_grace_period(&self, mpip_id: MpipId) -> Option<U64> {
+        self.queued_grace_period.get(&mpip_id).map(U64::from)
+    }
+
+    pub fn get_guardian(&self) -> AccountId {
+        self.guardian_id.clone()
+    }
+
+    pub fn get_proposal_actions(&self, mpip_id: MpipId) -> Vec<MpipAction> {
+        self.internal_get_proposal_actions(mpip_id)
+    }
+
+    pub fn get_proposal_execution(&self, mpip_id: MpipId) -> Option<MpipExecution> {
+        self.executions.get(&mpip_id)
+    }
+
     // *********
     // * VOTER FUNCTIONS *
     // *********
@@ -377,10 +496,15 @@ impl MpipContract {
     ) {
         self.assert_proposal_is_on_voting(&mpip_id);
         self.assert_has_not_voted(mpip_id, env::predecessor_account_id());
+        // voting power snapshot at the proposal vote start
+        let snapshot_timestamp = self
+            .internal_get_proposal(&mpip_id)
+            .vote_start_timestamp
+            .unwrap();
         ext_metavote::ext(self.meta_vote_contract_address.clone())
             .with_static_gas(GAS_FOR_GET_VOTING_POWER)
             .with_attached_deposit(1)
-            .get_all_locking_positions(env::predecessor_account_id())
+            .get_voting_power_at(env::predecessor_account_id(), snapshot_timestamp)
             .then(
                 Self::ext(env::current_account_id())
                     .with_static_gas(GAS_FOR_RESOLVE_VOTE)
@@ -401,7 +525,7 @@ impl MpipContract {
         vote_type: VoteType,
         memo: String,
     ) {
-        let total_v_power = self.internal_get_user_total_voting_power_from_promise();
+        let total_v_power = self.internal_get_voting_power_from_promise();
         let mut voter = self.internal_get_voter(&voter_id);
         assert!(
             total_v_power > 0,
@@ -490,19 +614,83 @@ impl MpipContract {
     // * BOT FUNCTIONS *
     // *********
 
+    /// Queues the proposal once the voting period is over and it was accepted,
+    /// and executes it when the timelock is over.
     pub fn process_voting_status(&mut self, mpip_id: MpipId) {
         self.assert_only_operator();
-        if !self.internal_proposal_is_on_voting(&mpip_id) {
-            return;
+        match self.internal_get_proposal_state(mpip_id) {
+            MpipState::Accepted => self.internal_queue_proposal(mpip_id),
+            MpipState::Queued
+                if get_current_epoch_millis() >= self.queued_eta.get(&mpip_id).unwrap() =>
+            {
+                self.internal_execute_proposal(mpip_id)
+            }
+            _ => {}
         }
+    }
+
+    // *********
+    // * EXECUTION *
+    // *********
+
+    /// Starts the timelock of an accepted proposal, anyone can call it.
+    pub fn queue_proposal(&mut self, mpip_id: MpipId) {
+        require!(
+            matches!(
+                self.internal_get_proposal_state(mpip_id),
+                MpipState::Accepted
+            ),
+            "Proposal is not accepted or already queued"
+        );
+        self.internal_queue_proposal(mpip_id);
+    }
+
+    /// The guardian can stop a queued proposal before it is executed.
+    pub fn veto_proposal(&mut self, mpip_id: MpipId) {
+        self.assert_only_guardian();
+        require!(
+            matches!(self.internal_get_proposal_state(mpip_id), MpipState::Queued),
+            "Proposal is not queued"
+        );
         let mut proposal = self.internal_get_proposal(&mpip_id);
-        if get_current_epoch_millis() >= proposal.vote_end_timestamp.unwrap() {
-            if self.internal_is_quorum_reached(mpip_id) {
-                // TODO EXECUTE
-                proposal.executed = true;
-                self.proposals.insert(&mpip_id, &proposal);
+        proposal.canceled = true;
+        self.proposals.insert(&mpip_id, &proposal);
+        log!("MPIP {} vetoed by {}", mpip_id, env::signer_account_id());
+    }
+
+    /// Performs the actions of a queued proposal once the timelock is over, anyone can call it.
+    /// The actions run in order, each one after the previous one succeeded: a failed action
+    /// skips the rest. The proposal is Executing until it ends as Executed or ExecutionFailed.
+    pub fn execute_proposal(&mut self, mpip_id: MpipId) {
+        self.assert_proposal_is_executable(mpip_id);
+        self.internal_execute_proposal(mpip_id);
+    }
+
+    #[private]
+    pub fn execute_action_callback(&mut self, mpip_id: MpipId, action_index: u32) {
+        let status = match env::promise_result(0) {
+            PromiseResult::Successful(_) => ActionStatus::Succeeded,
+            _ => ActionStatus::Failed,
+        };
+        log!(
+            "MPIP {} action {} {:?}",
+            mpip_id,
+            action_index,
+            status
+        );
+        let mut execution = self.executions.get(&mpip_id).unwrap();
+        let action_index = action_index as usize;
+        let next_index = action_index + 1;
+        if status == ActionStatus::Failed {
+            for result in execution.results[next_index..].iter_mut() {
+                *result = ActionStatus::Skipped;
             }
+        } else if next_index < execution.results.len() {
+            let actions = self.internal_get_proposal_actions(mpip_id);
+            self.internal_execute_action(mpip_id, next_index, &actions);
         }
+        execution.results[action_index] = status;
+        self.executions.insert(&mpip_id, &execution);
     }
 }
 
@@ -0,0 +1,60 @@
+use crate::*;
+use near_sdk::{env, near_bindgen};
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OldState {
+    pub admin_id: AccountId,
+    pub operator_id: AccountId,
+    pub meta_token_contract_address: ContractAddress,
+    pub meta_vote_contract_address: ContractAddress,
+    pub proposals: UnorderedMap<MpipId, Mpip>,
+    pub votes: UnorderedMap<MpipId, ProposalVote>,
+    pub voters: UnorderedMap<AccountId, Voter>,
+    pub proposers: UnorderedMap<AccountId, Vec<MpipId>>,
+    pub voting_period: EpochMillis,
+    pub min_meta_amount: Balance,
+    pub min_st_near_amount: Balance,
+    pub min_voting_power_amount: VotingPower,
+    pub mpip_cost_in_meta: Balance,
+    pub mpip_storage_near: Balance,
+    pub open_for_new_mpips: bool,
+    pub quorum_floor: BasisPoints,
+}
+
+#[near_bindgen]
+impl MpipContract {
+    #[init(ignore_state)]
+    #[private] // only contract account can call this fn
+    pub fn migrate() -> Self {
+        // retrieve the current state from the contract
+        let old: OldState = env::state_read().expect("failed");
+        // the admin is the guardian until a guardian is set
+        let guardian_id = old.admin_id.clone();
+        // return the new state
+        Self {
+            admin_id: old.admin_id,
+            operator_id: old.operator_id,
+            meta_token_contract_address: old.meta_token_contract_address,
+            meta_vote_contract_address: old.meta_vote_contract_address,
+            proposals: old.proposals,
+            votes: old.votes,
+            voters: old.voters,
+            proposers: old.proposers,
+            voting_period: old.voting_period,
+            min_meta_amount: old.min_meta_amount,
+            min_st_near_amount: old.min_st_near_amount,
+            min_voting_power_amount: old.min_voting_power_amount,
+            mpip_cost_in_meta: old.mpip_cost_in_meta,
+            mpip_storage_near: old.mpip_storage_near,
+            open_for_new_mpips: old.open_for_new_mpips,
+            quorum_floor: old.quorum_floor,
+            proposal_actions: UnorderedMap::new(StorageKey::ProposalActions),
+            executions: UnorderedMap::new(StorageKey::Executions),
+            timelock_delay: DEFAULT_TIMELOCK_DELAY,
+            grace_period: DEFAULT_GRACE_PERIOD,
+            guardian_id,
+            queued_eta: UnorderedMap::new(StorageKey::QueuedEta),
+            queued_grace_period: UnorderedMap::new(StorageKey::QueuedGracePeriod),
+        }
+    }
+}
@@ -13,11 +13,26 @@ pub enum MpipState {
     Active, // reviewed and accepted by managers
     VotingProcess, // on voting process
     Accepted, // accepted by votes
+    Queued, // accepted and waiting in the timelock, executable after the eta
+    Expired, // queued but not executed within the grace period
+    Vetoed, // queued and vetoed by the guardian
     Rejected, // rejected by votes
-    Executed, // proposal executed, performing on-chain actions
+    Executing, // proposal executed, performing on-chain actions
+    Executed, // proposal executed, all the on-chain actions succeeded
+    ExecutionFailed, // proposal executed, but some on-chain action failed
     Canceled, // canceled by manager after community awareness
 }
 
+/// Content of a new proposal, see `internal_create_proposal`
+pub struct NewMpipParams {
+    pub title: String,
+    pub short_description: String,
+    pub body: String,
+    pub data: String,
+    pub extra: String,
+    pub actions: Vec<MpipAction>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(crate = "near_sdk::serde")]
 pub struct MpipJSON {
@@ -108,17 +123,24 @@ impl Mpip {
 }
 
 impl MpipContract {
-    pub(crate) fn internal_create_proposal(
-        &mut self,
-        mpip_id: MpipId,
-        title: String,
-        short_description: String,
-        body: String,
-        data: String,
-        extra: String,
-    ) -> MpipId {
-        let proposal = Mpip::new(mpip_id, title, short_description, body, data, extra);
+    /// grace period snapshot of the queued proposal
+    pub(crate) fn internal_get_grace_period(&self, mpip_id: MpipId) -> EpochMillis {
+        self.queued_grace_period
+            .get(&mpip_id)
+            .unwrap_or(self.grace_period)
+    }
+
+    pub(crate) fn internal_create_proposal(&mut self, mpip_id: MpipId, params: NewMpipParams) -> MpipId {
+        let proposal = Mpip::new(
+            mpip_id,
+            params.title,
+            params.short_description,
+            params.body,
+            params.data,
+            params.extra,
+        );
         self.proposals.insert(&mpip_id, &proposal);
+        self.internal_set_proposal_actions(mpip_id, params.actions);
         let mut proposer = self.internal_get_proposer(proposal.creator_id.clone());
         proposer.push(mpip_id);
         self.proposers.insert(&proposal.creator_id, &proposer);
@@ -134,8 +156,15 @@ impl MpipContract {
     ) -> MpipState {
         let proposal = self.internal_get_proposal(&mpip_id);
         if proposal.executed {
-            return MpipState::Executed;
+            return match self.executions.get(&mpip_id) {
+                Some(execution) if execution.has_failed() => MpipState::ExecutionFailed,
+                Some(execution) if execution.is_pending() => MpipState::Executing,
+                _ => MpipState::Executed,
+            };
         } else if proposal.canceled {
+            if self.queued_eta.get(&mpip_id).is_some() {
+                return MpipState::Vetoed;
+            }
             return MpipState::Canceled;
         } else if proposal.draft {
             return MpipState::Draft;
@@ -150,7 +179,13 @@ impl MpipContract {
         if self.internal_is_quorum_reached(mpip_id)
             && self.get_proposal_vote_succeeded(mpip_id)
         {
-            return MpipState::Accepted;
+            return match self.queued_eta.get(&mpip_id) {
+                Some(eta) if get_current_epoch_millis() > eta + self.internal_get_grace_period(mpip_id) => {
+                    MpipState::Expired
+                }
+                Some(_) => MpipState::Queued,
+                None => MpipState::Accepted,
+            };
         } else {
             return MpipState::Rejected;
         }
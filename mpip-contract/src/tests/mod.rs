@@ -1,7 +1,9 @@
 // use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
-// use super::*;
-// use near_sdk::{testing_env, VMContext};
-// mod utils;
+use super::*;
+use near_sdk::{test_utils::get_created_receipts, testing_env, PromiseResult};
+use vote_counting::ProposalVote;
+mod utils;
+use utils::*;
 // fn new_contract() -> MpipContract {
 //     todo!();
     // MpipContract::new(
@@ -1048,4 +1050,332 @@
 //     assert_eq!(locking_position.len(), 1);
 //     let vote_position = &voters.first().unwrap().vote_positions;
 //     assert_eq!(vote_position.len(), 1);
-// }
\ No newline at end of file
+// }
+const DAY_MILLIS: EpochMillis = 24 * 60 * 60 * 1000;
+
+fn set_context(account: &AccountId, days: u64) {
+    testing_env!(get_context(
+        account.clone(),
+        ntoy(TEST_INITIAL_BALANCE),
+        0,
+        to_ts(GENESIS_TIME_IN_DAYS + days)
+    ));
+}
+
+fn set_callback_context(days: u64, result: PromiseResult) {
+    testing_env!(
+        get_context(
+            contract_account(),
+            ntoy(TEST_INITIAL_BALANCE),
+            0,
+            to_ts(GENESIS_TIME_IN_DAYS + days)
+        ),
+        near_sdk::VMConfig::test(),
+        near_sdk::RuntimeFeesConfig::test(),
+        Default::default(),
+        vec![result]
+    );
+}
+
+fn new_mpip_contract() -> MpipContract {
+    set_context(&owner_account(), 0);
+    MpipContract::new(
+        owner_account(),
+        operator_account(),
+        meta_token_account(),
+        meta_vote_account(),
+        U64::from(DAY_MILLIS),
+        U128::from(0),
+        U128::from(0),
+        1_000,
+    )
+}
+
+/// proposal voting from day `days`, accepted after one day
+fn new_voted_proposal(contract: &mut MpipContract, days: u64, actions: Vec<MpipAction>) -> MpipId {
+    set_context(&voter_account(), days);
+    let mpip_id = contract.proposals.len() as MpipId;
+    contract.internal_create_proposal(
+        mpip_id,
+        NewMpipParams {
+            title: "title".to_owned(),
+            short_description: "short description".to_owned(),
+            body: "body".to_owned(),
+            data: "data".to_owned(),
+            extra: "extra".to_owned(),
+            actions,
+        },
+    );
+    let vote_start = get_current_epoch_millis();
+    let mut proposal = contract.internal_get_proposal(&mpip_id);
+    proposal.draft = false;
+    proposal.vote_start_timestamp = Some(vote_start);
+    proposal.vote_end_timestamp = Some(vote_start + DAY_MILLIS);
+    proposal.v_power_quorum_to_reach = Some(10);
+    contract.proposals.insert(&mpip_id, &proposal);
+    let mut proposal_vote = ProposalVote::new(&mpip_id);
+    proposal_vote.for_votes = 100;
+    contract.votes.insert(&mpip_id, &proposal_vote);
+    mpip_id
+}
+
+fn transfer_action(amount: u128) -> MpipAction {
+    MpipAction::Transfer {
+        receiver_id: voter_account(),
+        amount: U128::from(amount),
+    }
+}
+
+fn action_results(contract: &MpipContract, mpip_id: MpipId) -> Vec<ActionStatus> {
+    contract.get_proposal_execution(mpip_id).unwrap().results
+}
+
+#[test]
+fn test_execute_proposal_actions_in_order() {
+    let mut contract = new_mpip_contract();
+    let actions = vec![transfer_action(1), transfer_action(2)];
+    let failing = new_voted_proposal(&mut contract, 0, actions.clone());
+    let succeeding = new_voted_proposal(&mut contract, 0, actions);
+
+    set_context(&voter_account(), 2);
+    assert!(matches!(contract.get_proposal_state(failing), MpipState::Accepted));
+    contract.queue_proposal(failing);
+    contract.queue_proposal(succeeding);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.execute_proposal(failing);
+    }));
+    assert!(result.is_err());
+
+    // only the first action and its callback are created
+    set_context(&voter_account(), 4);
+    contract.execute_proposal(failing);
+    assert_eq!(get_created_receipts().len(), 2);
+    assert_eq!(action_results(&contract, failing), vec![ActionStatus::Pending; 2]);
+    assert!(matches!(contract.get_proposal_state(failing), MpipState::Executing));
+    set_callback_context(4, PromiseResult::Failed);
+    contract.execute_action_callback(failing, 0);
+    assert_eq!(
+        action_results(&contract, failing),
+        vec![ActionStatus::Failed, ActionStatus::Skipped]
+    );
+    assert!(matches!(contract.get_proposal_state(failing), MpipState::ExecutionFailed));
+
+    // the callback of a successful action performs the next one
+    set_context(&voter_account(), 4);
+    contract.execute_proposal(succeeding);
+    set_callback_context(4, PromiseResult::Successful(vec![]));
+    contract.execute_action_callback(succeeding, 0);
+    assert_eq!(get_created_receipts().len(), 2);
+    assert_eq!(
+        action_results(&contract, succeeding),
+        vec![ActionStatus::Succeeded, ActionStatus::Pending]
+    );
+    assert!(matches!(contract.get_proposal_state(succeeding), MpipState::Executing));
+    set_callback_context(4, PromiseResult::Successful(vec![]));
+    contract.execute_action_callback(succeeding, 1);
+    assert!(get_created_receipts().is_empty());
+    assert!(matches!(contract.get_proposal_state(succeeding), MpipState::Executed));
+}
+
+#[test]
+fn test_actions_cannot_call_the_mpip_contract() {
+    let contract = new_mpip_contract();
+    let function_call = |receiver_id: AccountId| MpipAction::FunctionCall {
+        receiver_id,
+        method_name: "execute_action_callback".to_owned(),
+        args: near_sdk::json_types::Base64VecU8(vec![]),
+        deposit: U128::from(0),
+        gas: U64::from(5 * TGAS),
+    };
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.assert_valid_actions(&[function_call(contract_account())]);
+    }));
+    assert!(result.is_err());
+    contract.assert_valid_actions(&[function_call(votable_account())]);
+}
+
+#[test]
+fn test_grace_period_snapshot() {
+    let mut contract = new_mpip_contract();
+    let queued_before = new_voted_proposal(&mut contract, 0, vec![]);
+    let queued_after = new_voted_proposal(&mut contract, 0, vec![]);
+    set_context(&voter_account(), 2);
+    contract.queue_proposal(queued_before);
+
+    // the new grace period applies to proposals queued after the change
+    set_context(&owner_account(), 2);
+    contract.update_grace_period(U64::from(DAY_MILLIS));
+    contract.queue_proposal(queued_after);
+    assert_eq!(
+        contract.get_proposal_grace_period(queued_before),
+        Some(U64::from(DEFAULT_GRACE_PERIOD))
+    );
+    set_context(&voter_account(), 6);
+    assert!(matches!(contract.get_proposal_state(queued_before), MpipState::Queued));
+    assert!(matches!(contract.get_proposal_state(queued_after), MpipState::Expired));
+}
+
+#[test]
+fn test_timelock_queue_veto_and_expiry() {
+    let mut contract = new_mpip_contract();
+    set_context(&owner_account(), 0);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.update_timelock_delay(U64::from(0));
+    }));
+    assert!(result.is_err());
+    contract.update_timelock_delay(U64::from(MIN_TIMELOCK_DELAY));
+
+    let vetoed = new_voted_proposal(&mut contract, 0, vec![transfer_action(1)]);
+    let expired = new_voted_proposal(&mut contract, 0, vec![transfer_action(1)]);
+    set_context(&voter_account(), 0);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.queue_proposal(vetoed);
+    }));
+    assert!(result.is_err());
+
+    // queued with the eta after the timelock delay
+    set_context(&voter_account(), 2);
+    contract.queue_proposal(vetoed);
+    contract.queue_proposal(expired);
+    let queued_at = get_current_epoch_millis();
+    assert_eq!(contract.get_proposal_eta(vetoed), Some(queued_at + MIN_TIMELOCK_DELAY));
+    assert!(matches!(contract.get_proposal_state(vetoed), MpipState::Queued));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.queue_proposal(vetoed);
+    }));
+    assert!(result.is_err());
+
+    // only the guardian vetoes, and a vetoed proposal can not be executed
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.veto_proposal(vetoed);
+    }));
+    assert!(result.is_err());
+    set_context(&owner_account(), 2);
+    contract.veto_proposal(vetoed);
+    assert!(matches!(contract.get_proposal_state(vetoed), MpipState::Vetoed));
+    set_context(&voter_account(), 3);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.execute_proposal(vetoed);
+    }));
+    assert!(result.is_err());
+
+    // not executed within the grace period
+    set_context(&voter_account(), 3 + 15);
+    assert!(matches!(contract.get_proposal_state(expired), MpipState::Expired));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.execute_proposal(expired);
+    }));
+    assert!(result.is_err());
+    set_context(&owner_account(), 3 + 15);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.veto_proposal(expired);
+    }));
+    assert!(result.is_err());
+    assert!(contract.get_proposal_execution(expired).is_none());
+}
+
+/// the only receipt created, a call to meta-vote: (method, json args)
+fn meta_vote_call() -> (String, near_sdk::serde_json::Value) {
+    let receipts = get_created_receipts();
+    let receipt = receipts.iter().find(|receipt| receipt.receiver_id == meta_vote_account()).unwrap();
+    match &receipt.actions[0] {
+        near_sdk::mock::VmAction::FunctionCall { function_name, args, .. } => (
+            function_name.clone(),
+            near_sdk::serde_json::from_slice(args).unwrap(),
+        ),
+        _ => panic!("not a function call"),
+    }
+}
+
+fn voting_power_result(voting_power: u128) -> PromiseResult {
+    PromiseResult::Successful(near_sdk::serde_json::to_vec(&U128::from(voting_power)).unwrap())
+}
+
+/// draft proposal by the voter, voting from day `days` with `total_voting_power` at that moment
+fn new_voting_proposal(contract: &mut MpipContract, days: u64, total_voting_power: u128) -> MpipId {
+    set_context(&voter_account(), days);
+    let mpip_id = contract.proposals.len() as MpipId;
+    contract.internal_create_proposal(
+        mpip_id,
+        NewMpipParams {
+            title: "title".to_owned(),
+            short_description: "short description".to_owned(),
+            body: "body".to_owned(),
+            data: "data".to_owned(),
+            extra: "extra".to_owned(),
+            actions: vec![],
+        },
+    );
+    contract.start_voting_period(mpip_id);
+    let (method, args) = meta_vote_call();
+    assert_eq!(method, "get_total_voting_power_at");
+    assert_eq!(args["timestamp_ms"], to_ts(GENESIS_TIME_IN_DAYS + days) / 1_000_000);
+    set_callback_context(days, voting_power_result(total_voting_power));
+    contract.start_voting_period_callback(mpip_id, to_ts(GENESIS_TIME_IN_DAYS + days) / 1_000_000);
+    mpip_id
+}
+
+#[test]
+fn test_vote_uses_snapshot_voting_power() {
+    let mut contract = new_mpip_contract();
+    let mpip_id = new_voting_proposal(&mut contract, 1, 1_000);
+    let snapshot_ms = to_ts(GENESIS_TIME_IN_DAYS + 1) / 1_000_000;
+
+    // voting later, the voting power is queried at the vote start
+    testing_env!(get_context(
+        voter_account(),
+        ntoy(TEST_INITIAL_BALANCE),
+        0,
+        to_ts(GENESIS_TIME_IN_DAYS + 1) + 3_600 * 1_000_000_000
+    ));
+    contract.vote_proposal(mpip_id, VoteType::For, "memo".to_owned());
+    let (method, args) = meta_vote_call();
+    assert_eq!(method, "get_voting_power_at");
+    assert_eq!(args["voter_id"], voter_account().to_string());
+    assert_eq!(args["timestamp_ms"], snapshot_ms);
+
+    // meta-vote returns 40 at the snapshot, whatever the current voting power is
+    set_callback_context(1, voting_power_result(40));
+    contract.vote_proposal_callback(mpip_id, voter_account(), VoteType::For, "memo".to_owned());
+    assert_eq!(contract.get_proposal_votes(mpip_id).for_votes.0, 40);
+}
+
+#[test]
+fn test_voting_power_locked_after_snapshot_does_not_count() {
+    let mut contract = new_mpip_contract();
+    let mpip_id = new_voting_proposal(&mut contract, 1, 1_000);
+    let late_voter = multi_voter_account("late".to_owned());
+
+    // locked after the vote start: the snapshot query is still at the vote start, where it had none
+    testing_env!(get_context(
+        late_voter.clone(),
+        ntoy(TEST_INITIAL_BALANCE),
+        0,
+        to_ts(GENESIS_TIME_IN_DAYS + 1) + 1_000_000_000
+    ));
+    contract.vote_proposal(mpip_id, VoteType::For, "memo".to_owned());
+    let (_, args) = meta_vote_call();
+    assert_eq!(args["timestamp_ms"], to_ts(GENESIS_TIME_IN_DAYS + 1) / 1_000_000);
+    set_callback_context(1, voting_power_result(0));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.vote_proposal_callback(mpip_id, late_voter.clone(), VoteType::For, "memo".to_owned());
+    }));
+    assert!(result.is_err());
+    assert_eq!(contract.get_proposal_votes(mpip_id).for_votes.0, 0);
+}
+
+#[test]
+fn test_quorum_uses_snapshot_total_voting_power() {
+    let mut contract = new_mpip_contract();
+    // 10% of the 1_000 voting power at the vote start
+    let mpip_id = new_voting_proposal(&mut contract, 1, 1_000);
+    assert_eq!(contract.internal_get_proposal(&mpip_id).v_power_quorum_to_reach, Some(100));
+
+    set_callback_context(1, voting_power_result(99));
+    contract.vote_proposal_callback(mpip_id, voter_account(), VoteType::For, "memo".to_owned());
+    assert!(!contract.internal_is_quorum_reached(mpip_id));
+    let other_voter = multi_voter_account("other".to_owned());
+    set_callback_context(1, voting_power_result(1));
+    contract.vote_proposal_callback(mpip_id, other_voter, VoteType::For, "memo".to_owned());
+    assert!(contract.internal_is_quorum_reached(mpip_id));
+}
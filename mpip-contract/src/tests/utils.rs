@@ -1,12 +1,15 @@
 #![allow(unused_imports)]
 #![allow(unused_variables)]
 #![allow(dead_code)]
+#![allow(clippy::needless_return, clippy::inconsistent_digit_grouping, clippy::identity_op)]
 
 use near_sdk::{AccountId, MockedBlockchain, PromiseResult, VMContext, Balance, PublicKey, Gas};
 
-use crate::constants::YOCTO_UNITS;
 use crate::types::*;
 
+pub type Days = u16;
+pub const YOCTO_UNITS: u128 = 1_000_000_000_000_000_000_000_000;
+
 pub const LOCKUP_NEAR: u128 = 1000;
 pub const GENESIS_TIME_IN_DAYS: u64 = 500;
 pub const YEAR: u64 = 365;
@@ -58,6 +61,10 @@ pub fn multi_voter_account(id: String) -> AccountId {
     AccountId::new_unchecked(format!("voter_{}.metavote.near", id))
 }
 
+pub fn meta_vote_account() -> AccountId {
+    AccountId::new_unchecked("meta-vote.metavote.near".to_string())
+}
+
 pub fn votable_account() -> AccountId {
     AccountId::new_unchecked("votable.metavote.near".to_string())
 }